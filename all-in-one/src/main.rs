@@ -0,0 +1,177 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use tracing::error;
+
+//The services only talk to each other over loopback, so these ports are fixed
+const OBJECTS_ADDR: &str = "127.0.0.1:6001";
+const DEPENDENCIES_ADDR: &str = "127.0.0.1:6002";
+const SUBMIT_ADDR: &str = "127.0.0.1:6003";
+const UNDO_ADDR: &str = "127.0.0.1:6004";
+const OPERATIONS_ADDR: &str = "127.0.0.1:6005";
+const REP_CACHE_ADDR: &str = "127.0.0.1:6006";
+
+const OBJ_TOPIC: &str = "ObjectState";
+const REPR_TOPIC: &str = "ClientRepresentation";
+
+fn var_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| String::from(default))
+}
+
+fn url(addr: &str) -> String {
+    format!("http://{}", addr)
+}
+
+fn addr(addr: &str) -> SocketAddr {
+    addr.parse().unwrap()
+}
+
+///Where the log and each cache live.
+///Everything is kept in memory unless STORAGE_DIR is set, in which case it's kept in files under it.
+struct Storage {
+    dir: Option<String>,
+}
+
+impl Storage {
+    fn log(&self) -> String {
+        match &self.dir {
+            Some(dir) => format!("file://{}/log", dir),
+            None => String::from("memory://all-in-one"),
+        }
+    }
+
+    //Each cache gets its own store, like each service gets its own Redis
+    fn cache(&self, name: &str) -> String {
+        match &self.dir {
+            Some(dir) => format!("file://{}/{}", dir, name),
+            None => format!("memory://{}", name),
+        }
+    }
+}
+
+//A service stopping takes down the whole process, the same as its container exiting would
+async fn supervise<E: std::fmt::Display>(name: &str, service: impl Future<Output = Result<(), E>>) {
+    match service.await {
+        Ok(()) => error!("{} stopped", name),
+        Err(e) => error!("{} stopped: {}", name, e),
+    }
+    std::process::exit(1);
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let run_url = var_or("RUN_URL", "0.0.0.0:6000").parse()?;
+    let updates_url = var_or("UPDATES_URL", "0.0.0.0:7000");
+    let geom_url = var_or("GEOM_URL", "local://");
+    match std::env::var("JAEGER_URL") {
+        Ok(jaeger_url) => trace_lib::init_tracer(&jaeger_url, "all-in-one")?,
+        Err(_) => env_logger::init(),
+    }
+    let storage = Storage {
+        dir: std::env::var("STORAGE_DIR").ok(),
+    };
+    let log = commit_log::connect(&storage.log())?;
+
+    tokio::spawn(supervise(
+        "objects",
+        objects::run(
+            addr(OBJECTS_ADDR),
+            storage.cache("objects"),
+            log.clone(),
+            String::from("object-group"),
+            String::from(OBJ_TOPIC),
+        ),
+    ));
+    tokio::spawn(supervise(
+        "dependencies",
+        dependencies::run(
+            addr(DEPENDENCIES_ADDR),
+            storage.cache("dependencies"),
+            log.clone(),
+            String::from("dep-group"),
+            String::from(OBJ_TOPIC),
+        ),
+    ));
+    let tessellations = match &storage.dir {
+        Some(_) => Some(cache_store::connect(&storage.cache("tessellations")).await?),
+        None => None,
+    };
+    tokio::spawn(supervise(
+        "operations",
+        operations_server::run(
+            addr(OPERATIONS_ADDR),
+            geom_url,
+            operations::TessellationCache::new(
+                operations_server::DEFAULT_CACHE_SIZE,
+                tessellations,
+            ),
+        ),
+    ));
+    tokio::spawn(supervise(
+        "submit",
+        submit::run(
+            addr(SUBMIT_ADDR),
+            url(OBJECTS_ADDR),
+            url(DEPENDENCIES_ADDR),
+            url(OPERATIONS_ADDR),
+            log.clone(),
+            String::from(OBJ_TOPIC),
+        ),
+    ));
+    tokio::spawn(supervise(
+        "undo",
+        undo::run(
+            addr(UNDO_ADDR),
+            storage.cache("undo"),
+            url(OBJECTS_ADDR),
+            log.clone(),
+            String::from("undoGroup"),
+            String::from(OBJ_TOPIC),
+        ),
+    ));
+    let repr_log = log.clone();
+    tokio::spawn(async move {
+        representations::run(
+            repr_log,
+            String::from("reprGroup"),
+            String::from(OBJ_TOPIC),
+            String::from(REPR_TOPIC),
+            url(OPERATIONS_ADDR),
+        )
+        .await;
+        error!("representations stopped");
+        std::process::exit(1);
+    });
+    tokio::spawn(supervise(
+        "rep_cache",
+        rep_cache::run(
+            addr(REP_CACHE_ADDR),
+            storage.cache("rep-cache"),
+            log.clone(),
+            String::from("rep-group"),
+            String::from(REPR_TOPIC),
+        ),
+    ));
+    tokio::spawn(supervise(
+        "updates",
+        updates::run(
+            updates_url,
+            log.clone(),
+            String::from("updatesGroup"),
+            String::from(REPR_TOPIC),
+        ),
+    ));
+
+    supervise(
+        "api",
+        api::run(
+            run_url,
+            url(UNDO_ADDR),
+            url(OBJECTS_ADDR),
+            url(OPERATIONS_ADDR),
+            url(SUBMIT_ADDR),
+            url(REP_CACHE_ADDR),
+        ),
+    )
+    .await;
+    Ok(())
+}
@@ -6,6 +6,24 @@ use submit::*;
 use tonic::transport::Channel;
 use tonic::Status;
 
+//Separates a file from its branch in the key every service stores the branch's changes under
+const BRANCH_SEPARATOR: char = '@';
+
+///The key a branch's changes are stored under.  The file's main line is keyed by the file itself.
+pub fn branch_key(file: &str, branch: &str) -> Result<String, Status> {
+    if branch.is_empty() {
+        return Ok(String::from(file));
+    }
+    //Services use : to build their own keys off this one
+    if branch.contains(BRANCH_SEPARATOR) || branch.contains(':') {
+        return Err(Status::invalid_argument(format!(
+            "Branch name {:?} can't contain {} or :",
+            branch, BRANCH_SEPARATOR
+        )));
+    }
+    Ok(format!("{}{}{}", file, BRANCH_SEPARATOR, branch))
+}
+
 pub async fn undo_client(
     undo_url: String,
 ) -> Result<undo::undo_client::UndoClient<Channel>, Status> {
@@ -56,15 +74,15 @@ pub async fn operations_client(
     Ok(ops_client)
 }
 
-pub async fn get_objects(
+///Gets the latest change to each object at the offset, or None if the object isn't found
+pub async fn get_changes(
     client: &mut objects_client::ObjectsClient<Channel>,
     file: &str,
-    obj_ids: Vec<String>,
+    obj_ids: &[String],
     offset: i64,
-    error_on_missing: bool,
-) -> Result<Vec<ObjectMsg>, Status> {
+) -> Result<Vec<Option<ChangeMsg>>, Status> {
     let mut obj_offsets = Vec::new();
-    for obj in &obj_ids {
+    for obj in obj_ids {
         obj_offsets.push(objects::ObjectAtOffset {
             offset,
             obj_id: obj.clone(),
@@ -77,9 +95,24 @@ pub async fn get_objects(
         }))
         .await;
     let changes = trace_response(resp)?;
+    Ok(changes
+        .objects
+        .into_iter()
+        .map(|change_opt| change_opt.change)
+        .collect())
+}
+
+pub async fn get_objects(
+    client: &mut objects_client::ObjectsClient<Channel>,
+    file: &str,
+    obj_ids: Vec<String>,
+    offset: i64,
+    error_on_missing: bool,
+) -> Result<Vec<ObjectMsg>, Status> {
+    let changes = get_changes(client, file, &obj_ids, offset).await?;
     let mut objects = Vec::new();
-    for (change_opt, obj_id) in changes.objects.into_iter().zip(obj_ids.into_iter()) {
-        match change_opt.change {
+    for (change_opt, obj_id) in changes.into_iter().zip(obj_ids.into_iter()) {
+        match change_opt {
             Some(change) => match change.change_type {
                 Some(change_msg::ChangeType::Add(msg))
                 | Some(change_msg::ChangeType::Modify(msg)) => objects.push(msg),
@@ -93,7 +126,9 @@ pub async fn get_objects(
                         warn!("Object {:?} has been deleted, skipping", msg.id);
                     }
                 }
-                None => {
+                Some(change_msg::ChangeType::Branch(..))
+                | Some(change_msg::ChangeType::Tag(..))
+                | None => {
                     if error_on_missing {
                         return Err(Status::not_found(format!(
                             "Object {:?} has no data set",
@@ -116,6 +151,268 @@ pub async fn get_objects(
     Ok(objects)
 }
 
+pub async fn get_tags(
+    client: &mut objects_client::ObjectsClient<Channel>,
+    file: &str,
+) -> Result<Vec<TagMsg>, Status> {
+    let resp = client
+        .get_tags(TracedRequest::new(objects::GetTagsInput {
+            file: String::from(file),
+        }))
+        .await;
+    Ok(trace_response(resp)?.tags)
+}
+
+///The offset a tag names
+pub async fn tag_offset(
+    client: &mut objects_client::ObjectsClient<Channel>,
+    file: &str,
+    tag: &str,
+) -> Result<i64, Status> {
+    let tags = get_tags(client, file).await?;
+    match tags.into_iter().find(|msg| msg.name == tag) {
+        Some(msg) => Ok(msg.offset),
+        None => Err(Status::not_found(format!("Tag {:?} not found", tag))),
+    }
+}
+
+///The offset given directly, or the one the tag names if there is one
+pub async fn offset_or_tag(
+    client: &mut objects_client::ObjectsClient<Channel>,
+    file: &str,
+    offset: i64,
+    tag: &str,
+) -> Result<i64, Status> {
+    if tag.is_empty() {
+        Ok(offset)
+    } else {
+        tag_offset(client, file, tag).await
+    }
+}
+
+///The object as it stands after the change, or None if it's been deleted or was never added
+pub fn object_after(change: Option<ChangeMsg>) -> Option<ObjectMsg> {
+    match change.and_then(|change| change.change_type) {
+        Some(change_msg::ChangeType::Add(msg)) | Some(change_msg::ChangeType::Modify(msg)) => {
+            Some(msg)
+        }
+        Some(change_msg::ChangeType::Delete(..))
+        | Some(change_msg::ChangeType::Branch(..))
+        | Some(change_msg::ChangeType::Tag(..))
+        | None => None,
+    }
+}
+
+///The change that takes an object from its current state to the target one, if it needs one
+pub fn change_to(
+    user: &str,
+    obj_id: &str,
+    current: &Option<ObjectMsg>,
+    target: Option<ObjectMsg>,
+) -> Option<ChangeMsg> {
+    if *current == target {
+        return None;
+    }
+    match (current, target) {
+        (None, Some(obj)) => Some(add(user, obj)),
+        (Some(..), Some(obj)) => Some(modify(user, obj)),
+        (Some(..), None) => Some(delete(user, String::from(obj_id))),
+        (None, None) => None,
+    }
+}
+
+///Gets every object in the file that exists at the given offset
+pub async fn get_all_objects(
+    client: &mut objects_client::ObjectsClient<Channel>,
+    file: &str,
+    offset: i64,
+) -> Result<Vec<ObjectMsg>, Status> {
+    let resp = client
+        .get_object_list(TracedRequest::new(objects::GetObjectListInput {
+            file: String::from(file),
+            offset,
+        }))
+        .await;
+    let obj_ids = trace_response(resp)?.obj_ids;
+    get_objects(client, file, obj_ids, offset, false).await
+}
+
+///Gets the contents of every symbol file the objects instance, following symbols within symbols
+pub async fn get_symbol_files(
+    obj_client: &mut objects_client::ObjectsClient<Channel>,
+    ops_client: &mut operations::operations_client::OperationsClient<Channel>,
+    objects: Vec<ObjectMsg>,
+) -> Result<Vec<operations::SymbolFileMsg>, Status> {
+    let mut symbol_files: Vec<operations::SymbolFileMsg> = Vec::new();
+    let mut pending = objects;
+    while !pending.is_empty() {
+        let resp = ops_client
+            .get_symbol_files(TracedRequest::new(operations::GetSymbolFilesInput {
+                objects: pending,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        pending = Vec::new();
+        for file_ref in output.files {
+            //Symbols can refer to different versions of the same file
+            let existing = symbol_files
+                .iter_mut()
+                .find(|sym| sym.file == file_ref.file && sym.offset == file_ref.offset);
+            if let Some(existing) = existing {
+                if !existing.definitions.contains(&file_ref.definition) {
+                    existing.definitions.push(file_ref.definition);
+                }
+                continue;
+            }
+            let objects = get_all_objects(obj_client, &file_ref.file, file_ref.offset).await?;
+            pending.extend(objects.iter().cloned());
+            symbol_files.push(operations::SymbolFileMsg {
+                file: file_ref.file,
+                objects,
+                offset: file_ref.offset,
+                definitions: vec![file_ref.definition],
+            });
+        }
+    }
+    Ok(symbol_files)
+}
+
+///The object's representation as of the offset, at a representation::LevelOfDetail.
+///If the cache doesn't have that version of the object, it's generated again.
+pub async fn get_rep_at_offset(
+    rep_cache_client: &mut rep_cache::rep_cache_client::RepCacheClient<Channel>,
+    obj_client: &mut objects_client::ObjectsClient<Channel>,
+    ops_client: &mut operations::operations_client::OperationsClient<Channel>,
+    file: &str,
+    obj_id: String,
+    offset: i64,
+    lod: i32,
+) -> Result<Option<representation::UpdateChangeMsg>, Status> {
+    let resp = rep_cache_client
+        .get_object_representations(TracedRequest::new(
+            rep_cache::GetObjectRepresentationsInput {
+                file: String::from(file),
+                obj_ids: vec![obj_id.clone()],
+                offset,
+                lod,
+            },
+        ))
+        .await;
+    match trace_response(resp) {
+        Ok(mut output) => return Ok(output.reps.pop()),
+        Err(e) if e.code() == tonic::Code::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    let change = match get_changes(obj_client, file, std::slice::from_ref(&obj_id), offset)
+        .await?
+        .pop()
+        .flatten()
+    {
+        Some(change) => change,
+        None => return Ok(None),
+    };
+    debug!(
+        "Regenerating representation of {} at offset {}",
+        obj_id, offset
+    );
+    let user = change.user.clone();
+    let resp = ops_client
+        .client_representation(TracedRequest::new(operations::ClientRepresentationInput {
+            objects: vec![change],
+            lods: vec![lod],
+        }))
+        .await;
+    let mut output = trace_response(resp)?;
+    Ok(output
+        .outputs
+        .pop()
+        .map(|update| representation::UpdateChangeMsg {
+            file: String::from(file),
+            user,
+            obj_id,
+            update: Some(update),
+            offset,
+            branch: None,
+            lod,
+            tag: None,
+        }))
+}
+
+///The full representations of the objects as of the offset.
+///They're read from rep_cache, and any it no longer has are generated again.
+pub async fn get_reps_at_offset(
+    rep_cache_client: &mut rep_cache::rep_cache_client::RepCacheClient<Channel>,
+    obj_client: &mut objects_client::ObjectsClient<Channel>,
+    ops_client: &mut operations::operations_client::OperationsClient<Channel>,
+    file: &str,
+    obj_ids: Vec<String>,
+    offset: i64,
+) -> Result<Vec<representation::UpdateChangeMsg>, Status> {
+    let lod = representation::LevelOfDetail::Full as i32;
+    let resp = rep_cache_client
+        .get_object_representations(TracedRequest::new(
+            rep_cache::GetObjectRepresentationsInput {
+                file: String::from(file),
+                obj_ids: obj_ids.clone(),
+                offset,
+                lod,
+            },
+        ))
+        .await;
+    match trace_response(resp) {
+        Ok(output) => return Ok(output.reps),
+        Err(e) if e.code() == tonic::Code::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    let mut reps = Vec::new();
+    for obj_id in obj_ids {
+        let rep = get_rep_at_offset(
+            rep_cache_client,
+            obj_client,
+            ops_client,
+            file,
+            obj_id,
+            offset,
+            lod,
+        )
+        .await?;
+        reps.extend(rep);
+    }
+    Ok(reps)
+}
+
+pub fn to_drawing_format(format: i32) -> Result<operations::DrawingFormat, Status> {
+    match api::DrawingFormat::from_i32(format) {
+        Some(api::DrawingFormat::Svg) => Ok(operations::DrawingFormat::Svg),
+        Some(api::DrawingFormat::Pdf) => Ok(operations::DrawingFormat::Pdf),
+        Some(api::DrawingFormat::Dxf) => Ok(operations::DrawingFormat::Dxf),
+        None => Err(Status::invalid_argument("Unknown drawing format")),
+    }
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+///Streams an exported file back to the client in chunks
+pub fn stream_file(
+    data: Vec<u8>,
+) -> tokio::sync::mpsc::Receiver<Result<api::FileChunkMsg, Status>> {
+    let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+    tokio::spawn(async move {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            if let Err(e) = tx
+                .send(Ok(api::FileChunkMsg {
+                    data: chunk.to_vec(),
+                }))
+                .await
+            {
+                error!("{}", e);
+                break;
+            }
+        }
+    });
+    rx
+}
+
 pub async fn submit_changes(
     client: &mut submit_changes_client::SubmitChangesClient<Channel>,
     file: String,
@@ -0,0 +1,1391 @@
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use trace_lib::{trace_response, TracedRequest};
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod common;
+
+mod api {
+    tonic::include_proto!("api");
+}
+use api::*;
+
+mod representation {
+    tonic::include_proto!("representation");
+}
+
+mod rep_cache {
+    tonic::include_proto!("rep_cache");
+}
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+
+mod object_state {
+    tonic::include_proto!("object_state");
+}
+
+mod operations {
+    tonic::include_proto!("operations");
+}
+
+mod undo {
+    tonic::include_proto!("undo");
+}
+
+mod submit {
+    tonic::include_proto!("submit");
+}
+
+mod objects {
+    tonic::include_proto!("objects");
+}
+
+fn unavailable<T: std::fmt::Debug>(err: T) -> Status {
+    Status::unavailable(format!("Couldn't connect to child service: {:?}", err))
+}
+
+#[derive(Debug, Clone)]
+struct Prefix {
+    file: String,
+    user: String,
+    offset: i64,
+}
+
+impl Prefix {
+    pub fn new(prefix_opt: Option<OpPrefixMsg>) -> Result<Prefix, Status> {
+        if let Some(prefix) = prefix_opt {
+            if !prefix.tag.is_empty() {
+                return Err(Status::invalid_argument(
+                    "A tag can only be given when reading a file",
+                ));
+            }
+            Ok(Prefix {
+                file: common::branch_key(&prefix.file, &prefix.branch)?,
+                user: prefix.user,
+                offset: prefix.offset,
+            })
+        } else {
+            Err(Status::invalid_argument("Operation prefix is required"))
+        }
+    }
+
+    ///Like new, but the offset can be given as a tag
+    pub async fn for_reading(
+        prefix_opt: Option<OpPrefixMsg>,
+        obj_url: &str,
+    ) -> Result<Prefix, Status> {
+        let mut prefix_msg =
+            prefix_opt.ok_or_else(|| Status::invalid_argument("Operation prefix is required"))?;
+        let tag = std::mem::take(&mut prefix_msg.tag);
+        let mut prefix = Prefix::new(Some(prefix_msg))?;
+        if !tag.is_empty() {
+            let mut obj_client = common::objects_client(String::from(obj_url)).await?;
+            prefix.offset = common::tag_offset(&mut obj_client, &prefix.file, &tag).await?;
+        }
+        Ok(prefix)
+    }
+}
+
+#[derive(Debug)]
+struct ApiService {
+    undo_url: String,
+    obj_url: String,
+    ops_url: String,
+    submit_url: String,
+    rep_cache_url: String,
+}
+
+#[tonic::async_trait]
+impl api_server::Api for ApiService {
+    #[instrument]
+    async fn begin_undo_event(
+        &self,
+        request: Request<BeginUndoEventInput>,
+    ) -> Result<Response<BeginUndoEventOutput>, Status> {
+        let msg = request.into_inner();
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let req = TracedRequest::new(undo::BeginUndoEventInput {
+            file: common::branch_key(&msg.file, &msg.branch)?,
+            user: msg.user,
+        });
+        let resp = undo_client.begin_undo_event(req).await;
+        trace_response(resp)?;
+        Ok(Response::new(BeginUndoEventOutput {}))
+    }
+
+    #[instrument]
+    async fn undo_latest(
+        &self,
+        request: Request<UndoLatestInput>,
+    ) -> Result<Response<UndoLatestOutput>, Status> {
+        let msg = request.into_inner();
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let req = TracedRequest::new(undo::UndoLatestInput {
+            file: prefix.file.clone(),
+            user: prefix.user.clone(),
+        });
+        let resp = undo_client.undo_latest(req).await;
+        let changes = trace_response(resp)?;
+        let resp = submit_client
+            .submit_changes(TracedRequest::new(submit::SubmitChangesInput {
+                file: prefix.file,
+                user: prefix.user,
+                offset: prefix.offset,
+                changes: changes.changes,
+            }))
+            .await;
+        let mut output = trace_response(resp)?;
+        match output.offsets.pop() {
+            Some(offset) => Ok(Response::new(UndoLatestOutput { offset })),
+            None => Err(Status::out_of_range(
+                "No offsets received from submit service",
+            )),
+        }
+    }
+
+    #[instrument]
+    async fn redo_latest(
+        &self,
+        request: Request<RedoLatestInput>,
+    ) -> Result<Response<RedoLatestOutput>, Status> {
+        let msg = request.into_inner();
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let resp = undo_client
+            .redo_latest(TracedRequest::new(undo::RedoLatestInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        let changes = trace_response(resp)?;
+        let req = TracedRequest::new(submit::SubmitChangesInput {
+            file: prefix.file,
+            user: prefix.user,
+            offset: prefix.offset,
+            changes: changes.changes,
+        });
+        let resp = submit_client.submit_changes(req).await;
+        let mut output = trace_response(resp)?;
+        match output.offsets.pop() {
+            Some(offset) => Ok(Response::new(RedoLatestOutput { offset })),
+            None => Err(Status::out_of_range(
+                "No offsets received from submit service",
+            )),
+        }
+    }
+
+    type OpenFileStream = tokio::sync::mpsc::Receiver<Result<OpenFileOutput, Status>>;
+
+    #[instrument]
+    async fn open_file(
+        &self,
+        request: Request<OpenFileInput>,
+    ) -> Result<Response<Self::OpenFileStream>, Status> {
+        let msg = request.into_inner();
+        let file = common::branch_key(&msg.file, &msg.branch)?;
+        let (default_lod, object_lods) = (msg.lod, msg.object_lods);
+        let lod_for = move |obj_id: &str| *object_lods.get(obj_id).unwrap_or(&default_lod);
+        let loaded: HashSet<String> = msg.loaded_ids.into_iter().collect();
+        let mut rep_cache_client = common::rep_cache_client(self.rep_cache_url.clone()).await?;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let offset = match msg.at {
+            Some(open_file_input::At::Offset(offset)) => Some(offset),
+            Some(open_file_input::At::Tag(tag)) => {
+                Some(common::tag_offset(&mut obj_client, &file, &tag).await?)
+            }
+            None => None,
+        };
+        //Objects come from the cache's index of where they are, the list as of the offset, or the latest list as it streams in
+        let mut obj_ids: BoxStream<'static, Result<String, Status>> = match (msg.region, offset) {
+            (Some(region), _) => {
+                let resp = rep_cache_client
+                    .get_objects_in_region(TracedRequest::new(rep_cache::GetObjectsInRegionInput {
+                        file: file.clone(),
+                        offset: offset.unwrap_or(i64::MAX),
+                        bottom_left: region.bottom_left,
+                        top_right: region.top_right,
+                        eye: region.eye,
+                    }))
+                    .await;
+                let obj_ids = trace_response(resp)?.obj_ids;
+                futures::stream::iter(obj_ids.into_iter().map(Ok)).boxed()
+            }
+            (None, Some(offset)) => {
+                let resp = obj_client
+                    .get_object_list(TracedRequest::new(objects::GetObjectListInput {
+                        file: file.clone(),
+                        offset,
+                    }))
+                    .await;
+                let obj_ids = trace_response(resp)?.obj_ids;
+                futures::stream::iter(obj_ids.into_iter().map(Ok)).boxed()
+            }
+            (None, None) => {
+                let resp = obj_client
+                    .get_latest_object_list(TracedRequest::new(objects::GetLatestObjectListInput {
+                        file: file.clone(),
+                    }))
+                    .await;
+                trace_response(resp)?
+                    .map(|obj_id_res| obj_id_res.map(|msg| msg.obj_id))
+                    .boxed()
+            }
+        };
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let offset = offset.unwrap_or(i64::MAX);
+        let (mut tx, rx) = tokio::sync::mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Some(obj_id_res) = obj_ids.next().await {
+                let obj_id = match obj_id_res {
+                    Ok(obj_id) if loaded.contains(&obj_id) => continue,
+                    Ok(obj_id) => obj_id,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    }
+                };
+                let lod = lod_for(&obj_id);
+                let rep_res = common::get_rep_at_offset(
+                    &mut rep_cache_client,
+                    &mut obj_client,
+                    &mut ops_client,
+                    &file,
+                    obj_id,
+                    offset,
+                    lod,
+                )
+                .await;
+                match rep_res {
+                    Ok(obj_rep) => tx.send(Ok(OpenFileOutput { obj_rep })).await.unwrap(),
+                    Err(e) => error!("{}", e),
+                }
+            }
+        });
+        Ok(Response::new(rx))
+    }
+
+    #[instrument]
+    async fn create_walls(
+        &self,
+        request: Request<CreateWallsInput>,
+    ) -> Result<Response<CreateWallsOutput>, Status> {
+        let msg = request.into_inner();
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let mut walls = Vec::new();
+        for wall in msg.walls {
+            let wall_msg = operations::WallMsg {
+                first_pt: wall.first_pt,
+                second_pt: wall.second_pt,
+                width: wall.width,
+                height: wall.height,
+            };
+            info!("Creating wall {:?}", wall_msg);
+            walls.push(wall_msg);
+        }
+        let resp = ops_client
+            .create_walls(TracedRequest::new(operations::CreateWallsInput { walls }))
+            .await;
+        let objects = trace_response(resp)?;
+        let mut changes = Vec::new();
+        let mut ids = Vec::new();
+        for obj in objects.walls.into_iter() {
+            ids.push(obj.id.clone());
+            changes.push(common::add(&prefix.user, obj));
+        }
+
+        let resp = submit_client
+            .submit_changes(TracedRequest::new(submit::SubmitChangesInput {
+                file: prefix.file,
+                user: prefix.user,
+                offset: prefix.offset,
+                changes,
+            }))
+            .await;
+        let mut output = trace_response(resp)?;
+        match output.offsets.pop() {
+            Some(offset) => Ok(Response::new(CreateWallsOutput {
+                obj_ids: ids,
+                offset,
+            })),
+            None => Err(Status::out_of_range(
+                "No offsets received from submit service",
+            )),
+        }
+    }
+
+    #[instrument]
+    async fn move_objects(
+        &self,
+        request: Request<MoveObjectsInput>,
+    ) -> Result<Response<MoveObjectsOutput>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+
+        let objects = common::get_objects(
+            &mut obj_client,
+            &prefix.file,
+            msg.obj_ids,
+            prefix.offset,
+            false,
+        )
+        .await?;
+
+        let resp = ops_client
+            .move_objects(TracedRequest::new(operations::MoveObjectsInput {
+                objects,
+                delta: msg.delta,
+            }))
+            .await;
+        let objects = trace_response(resp)?;
+        let mut changes = Vec::new();
+        for obj in objects.objects {
+            changes.push(common::modify(&prefix.user, obj));
+        }
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(MoveObjectsOutput { offset }))
+    }
+
+    async fn join_objects_at_point(
+        &self,
+        request: Request<JoinObjectsAtPointInput>,
+    ) -> Result<Response<JoinObjectsAtPointOutput>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+
+        let mut objects = common::get_objects(
+            &mut obj_client,
+            &prefix.file,
+            vec![msg.first_id, msg.second_id],
+            prefix.offset,
+            true,
+        )
+        .await?;
+
+        let second_opt = objects.pop();
+        let first_opt = objects.pop();
+
+        let resp = ops_client
+            .join_objects(TracedRequest::new(operations::JoinObjectsInput {
+                first_obj: first_opt,
+                second_obj: second_opt,
+                first_wants: object_state::ref_id_msg::RefType::ProfilePoint as i32,
+                second_wants: object_state::ref_id_msg::RefType::ProfilePoint as i32,
+                guess: msg.guess,
+            }))
+            .instrument(info_span!("join_objects"))
+            .await;
+        let output = trace_response(resp)?;
+        let mut changes = Vec::new();
+        if let Some(first_obj) = output.first_obj {
+            changes.push(common::modify(&prefix.user, first_obj));
+        }
+        if let Some(second_obj) = output.second_obj {
+            changes.push(common::modify(&prefix.user, second_obj));
+        }
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(JoinObjectsAtPointOutput { offset }))
+    }
+
+    #[instrument]
+    async fn delete_objects(
+        &self,
+        request: Request<DeleteObjectsInput>,
+    ) -> Result<Response<DeleteObjectsOutput>, Status> {
+        let msg = request.into_inner();
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+
+        let mut changes = Vec::new();
+        for obj_id in msg.obj_ids {
+            changes.push(common::delete(&prefix.user, obj_id));
+        }
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(DeleteObjectsOutput { offset }))
+    }
+
+    #[instrument]
+    async fn create_sheet(
+        &self,
+        request: Request<CreateSheetInput>,
+    ) -> Result<Response<CreateSheetOutput>, Status> {
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let msg = request.into_inner();
+        let prefix = Prefix::new(msg.prefix)?;
+        let ops_sheet = operations::CreateSheetInput {
+            name: msg.name,
+            print_size: msg.print_size,
+        };
+        let resp = ops_client.create_sheet(TracedRequest::new(ops_sheet)).await;
+        let object = trace_response(resp)?;
+        match object.sheet {
+            Some(obj_msg) => {
+                let id = obj_msg.id.clone();
+                let change = common::add(&prefix.user, obj_msg);
+                let resp = submit_client
+                    .submit_changes(TracedRequest::new(submit::SubmitChangesInput {
+                        file: prefix.file,
+                        user: prefix.user,
+                        offset: prefix.offset,
+                        changes: vec![change],
+                    }))
+                    .await;
+                let mut output = trace_response(resp)?;
+                match output.offsets.pop() {
+                    Some(offset) => Ok(Response::new(CreateSheetOutput {
+                        sheet_id: id,
+                        offset,
+                    })),
+                    None => Err(Status::out_of_range(
+                        "No offsets received from submit service",
+                    )),
+                }
+            }
+            None => Err(Status::not_found(
+                "No sheet returned from operations service",
+            )),
+        }
+    }
+
+    #[instrument]
+    async fn create_viewport(
+        &self,
+        request: Request<CreateViewportInput>,
+    ) -> Result<Response<CreateViewportOutput>, Status> {
+        let msg = request.into_inner();
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let view_type = match msg.view_type {
+            Some(create_viewport_input::ViewType::Top(msg)) => {
+                operations::create_viewport_input::ViewType::Top(msg)
+            }
+            Some(create_viewport_input::ViewType::Front(msg)) => {
+                operations::create_viewport_input::ViewType::Front(msg)
+            }
+            Some(create_viewport_input::ViewType::Left(msg)) => {
+                operations::create_viewport_input::ViewType::Left(msg)
+            }
+            Some(create_viewport_input::ViewType::Right(msg)) => {
+                operations::create_viewport_input::ViewType::Right(msg)
+            }
+            Some(create_viewport_input::ViewType::Back(msg)) => {
+                operations::create_viewport_input::ViewType::Back(msg)
+            }
+            Some(create_viewport_input::ViewType::Bottom(msg)) => {
+                operations::create_viewport_input::ViewType::Bottom(msg)
+            }
+            Some(create_viewport_input::ViewType::Custom(msg)) => {
+                operations::create_viewport_input::ViewType::Custom(operations::CustomViewMsg {
+                    camera_pos: msg.camera_pos,
+                    target: msg.target,
+                })
+            }
+            None => return Err(tonic::Status::invalid_argument("No view type passed in")),
+        };
+        let ops_viewport = operations::CreateViewportInput {
+            sheet_id: msg.sheet_id,
+            view_type: Some(view_type),
+            origin: msg.origin,
+            scale: msg.scale,
+        };
+        let resp = ops_client
+            .create_viewport(TracedRequest::new(ops_viewport))
+            .await;
+        let object = trace_response(resp)?;
+        match object.viewport {
+            Some(obj_msg) => {
+                let id = obj_msg.id.clone();
+                let change = common::add(&prefix.user, obj_msg);
+                let resp = submit_client
+                    .submit_changes(TracedRequest::new(submit::SubmitChangesInput {
+                        file: prefix.file,
+                        user: prefix.user,
+                        offset: prefix.offset,
+                        changes: vec![change],
+                    }))
+                    .await;
+                let mut output = trace_response(resp)?;
+                match output.offsets.pop() {
+                    Some(offset) => Ok(Response::new(CreateViewportOutput {
+                        viewport_id: id,
+                        offset,
+                    })),
+                    None => Err(Status::out_of_range(
+                        "No offsets received from submit service",
+                    )),
+                }
+            }
+            None => Err(Status::not_found(
+                "No viewport returned from operations service",
+            )),
+        }
+    }
+
+    type ExportSheetStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_sheet(
+        &self,
+        request: Request<ExportSheetInput>,
+    ) -> Result<Response<Self::ExportSheetStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let format = common::to_drawing_format(msg.format)?;
+        let objects = common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?;
+        let resp = ops_client
+            .export_sheet(TracedRequest::new(operations::ExportSheetInput {
+                sheet_id: msg.sheet_id,
+                objects,
+                format: format as i32,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    type ExportViewStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_view(
+        &self,
+        request: Request<ExportViewInput>,
+    ) -> Result<Response<Self::ExportViewStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let format = common::to_drawing_format(msg.format)?;
+        let view = match AxisView::from_i32(msg.view) {
+            Some(AxisView::Top) => operations::AxisView::Top,
+            Some(AxisView::Front) => operations::AxisView::Front,
+            Some(AxisView::Left) => operations::AxisView::Left,
+            Some(AxisView::Right) => operations::AxisView::Right,
+            Some(AxisView::Back) => operations::AxisView::Back,
+            Some(AxisView::Bottom) => operations::AxisView::Bottom,
+            None => return Err(Status::invalid_argument("Unknown axis view")),
+        };
+        let objects = common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?;
+        let resp = ops_client
+            .export_view(TracedRequest::new(operations::ExportViewInput {
+                objects,
+                view: view as i32,
+                format: format as i32,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_dxf(
+        &self,
+        request: Request<ImportDxfInput>,
+    ) -> Result<Response<ImportDxfOutput>, Status> {
+        let msg = request.into_inner();
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let resp = ops_client
+            .import_dxf_walls(TracedRequest::new(operations::ImportDxfWallsInput {
+                data: msg.data,
+                layers: msg.layers,
+                width: msg.width,
+                height: msg.height,
+                tolerance: msg.tolerance,
+            }))
+            .await;
+        let objects = trace_response(resp)?;
+        if objects.walls.is_empty() {
+            return Ok(Response::new(ImportDxfOutput {
+                obj_ids: Vec::new(),
+                offset: prefix.offset,
+            }));
+        }
+        let mut changes = Vec::new();
+        let mut obj_ids = Vec::new();
+        for obj in objects.walls {
+            obj_ids.push(obj.id.clone());
+            changes.push(common::add(&prefix.user, obj));
+        }
+        //The whole import should undo in one step
+        let resp = undo_client
+            .begin_undo_event(TracedRequest::new(undo::BeginUndoEventInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        trace_response(resp)?;
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(ImportDxfOutput { obj_ids, offset }))
+    }
+
+    type ExportIfcStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_ifc(
+        &self,
+        request: Request<ExportIfcInput>,
+    ) -> Result<Response<Self::ExportIfcStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let objects = common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?;
+        let resp = ops_client
+            .export_ifc(TracedRequest::new(operations::ExportIfcInput {
+                name: prefix.file,
+                objects,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_ifc(
+        &self,
+        request: Request<ImportIfcInput>,
+    ) -> Result<Response<ImportIfcOutput>, Status> {
+        let msg = request.into_inner();
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let resp = ops_client
+            .import_ifc(TracedRequest::new(operations::ImportIfcInput {
+                data: msg.data,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        let issues = output
+            .issues
+            .into_iter()
+            .map(|issue| IfcImportIssueMsg {
+                step_id: issue.step_id,
+                entity: issue.entity,
+                global_id: issue.global_id,
+                reason: issue.reason,
+            })
+            .collect();
+        if output.objects.is_empty() {
+            return Ok(Response::new(ImportIfcOutput {
+                obj_ids: Vec::new(),
+                offset: prefix.offset,
+                issues,
+            }));
+        }
+        let mut changes = Vec::new();
+        let mut obj_ids = Vec::new();
+        for obj in output.objects {
+            obj_ids.push(obj.id.clone());
+            changes.push(common::add(&prefix.user, obj));
+        }
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        //The whole import should undo in one step
+        let resp = undo_client
+            .begin_undo_event(TracedRequest::new(undo::BeginUndoEventInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        trace_response(resp)?;
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(ImportIfcOutput {
+            obj_ids,
+            offset,
+            issues,
+        }))
+    }
+
+    type ExportGltfStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_gltf(
+        &self,
+        request: Request<ExportGltfInput>,
+    ) -> Result<Response<Self::ExportGltfStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let mut rep_cache_client = common::rep_cache_client(self.rep_cache_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let objects = common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?;
+        let symbol_files =
+            common::get_symbol_files(&mut obj_client, &mut ops_client, objects.clone()).await?;
+        //The meshes the representations pipeline made are exported, rather than tessellating everything again
+        let reps = common::get_reps_at_offset(
+            &mut rep_cache_client,
+            &mut obj_client,
+            &mut ops_client,
+            &prefix.file,
+            objects.into_iter().map(|obj| obj.id).collect(),
+            prefix.offset,
+        )
+        .await?;
+        let mut symbols = Vec::new();
+        for symbol_file in symbol_files {
+            let reps = common::get_reps_at_offset(
+                &mut rep_cache_client,
+                &mut obj_client,
+                &mut ops_client,
+                &symbol_file.file,
+                symbol_file.objects.into_iter().map(|obj| obj.id).collect(),
+                symbol_file.offset,
+            )
+            .await?;
+            symbols.push(operations::SymbolRepsMsg {
+                file: symbol_file.file,
+                offset: symbol_file.offset,
+                definitions: symbol_file.definitions,
+                reps,
+            });
+        }
+        let resp = ops_client
+            .export_gltf(TracedRequest::new(operations::ExportGltfInput {
+                reps,
+                symbols,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    type ExportMeshStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_mesh(
+        &self,
+        request: Request<ExportMeshInput>,
+    ) -> Result<Response<Self::ExportMeshStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let format = match MeshFormat::from_i32(msg.format) {
+            Some(MeshFormat::Obj) => operations::MeshFormat::Obj,
+            Some(MeshFormat::Stl) => operations::MeshFormat::Stl,
+            None => return Err(Status::invalid_argument("Unknown mesh format")),
+        };
+        let objects = if msg.obj_ids.is_empty() {
+            common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?
+        } else {
+            common::get_objects(
+                &mut obj_client,
+                &prefix.file,
+                msg.obj_ids,
+                prefix.offset,
+                true,
+            )
+            .await?
+        };
+        let symbol_files =
+            common::get_symbol_files(&mut obj_client, &mut ops_client, objects.clone()).await?;
+        let resp = ops_client
+            .export_mesh(TracedRequest::new(operations::ExportMeshInput {
+                objects,
+                symbol_files,
+                format: format as i32,
+                merge_vertices: msg.merge_vertices,
+                scale: msg.scale,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    type ExportSnapshotStream = tokio::sync::mpsc::Receiver<Result<FileChunkMsg, Status>>;
+
+    #[instrument]
+    async fn export_snapshot(
+        &self,
+        request: Request<ExportSnapshotInput>,
+    ) -> Result<Response<Self::ExportSnapshotStream>, Status> {
+        let msg = request.into_inner();
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let objects = common::get_all_objects(&mut obj_client, &prefix.file, prefix.offset).await?;
+        let resp = ops_client
+            .export_snapshot(TracedRequest::new(operations::ExportSnapshotInput {
+                file: prefix.file,
+                offset: prefix.offset,
+                objects,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(common::stream_file(output.data)))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_snapshot(
+        &self,
+        request: Request<ImportSnapshotInput>,
+    ) -> Result<Response<ImportSnapshotOutput>, Status> {
+        let msg = request.into_inner();
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let prefix = Prefix::new(msg.prefix)?;
+        let resp = ops_client
+            .import_snapshot(TracedRequest::new(operations::ImportSnapshotInput {
+                data: msg.data,
+                fresh_ids: msg.fresh_ids,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        if output.objects.is_empty() {
+            return Ok(Response::new(ImportSnapshotOutput {
+                obj_ids: Vec::new(),
+                offset: prefix.offset,
+            }));
+        }
+        let mut changes = Vec::new();
+        let mut obj_ids = Vec::new();
+        for obj in output.objects {
+            obj_ids.push(obj.id.clone());
+            changes.push(common::add(&prefix.user, obj));
+        }
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        //The whole import should undo in one step
+        let resp = undo_client
+            .begin_undo_event(TracedRequest::new(undo::BeginUndoEventInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        trace_response(resp)?;
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(ImportSnapshotOutput { obj_ids, offset }))
+    }
+
+    #[instrument]
+    async fn create_branch(
+        &self,
+        request: Request<CreateBranchInput>,
+    ) -> Result<Response<CreateBranchOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix_msg = msg
+            .prefix
+            .ok_or_else(|| Status::invalid_argument("Operation prefix is required"))?;
+        if msg.name.is_empty() {
+            return Err(Status::invalid_argument("Branch name is required"));
+        }
+        let file = common::branch_key(&prefix_msg.file, &msg.name)?;
+        let prefix = Prefix::for_reading(Some(prefix_msg), &self.obj_url).await?;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let resp = obj_client
+            .get_latest_offset(TracedRequest::new(objects::GetLatestOffsetInput {
+                file: file.clone(),
+            }))
+            .await;
+        match trace_response(resp) {
+            Ok(..) => {
+                return Err(Status::already_exists(format!(
+                    "Branch {} already exists",
+                    msg.name
+                )))
+            }
+            Err(e) if e.code() == tonic::Code::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let resp = submit_client
+            .create_branch(TracedRequest::new(submit::CreateBranchInput {
+                file,
+                user: prefix.user,
+                parent: prefix.file,
+                offset: prefix.offset,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(CreateBranchOutput {
+            offset: output.offset,
+        }))
+    }
+
+    #[instrument(skip(request))]
+    async fn merge_branch(
+        &self,
+        request: Request<MergeBranchInput>,
+    ) -> Result<Response<MergeBranchOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix_msg = msg
+            .prefix
+            .ok_or_else(|| Status::invalid_argument("Operation prefix is required"))?;
+        let source = common::branch_key(&prefix_msg.file, &msg.source)?;
+        let prefix = Prefix::new(Some(prefix_msg))?;
+        if source == prefix.file {
+            return Err(Status::invalid_argument("Can't merge a branch into itself"));
+        }
+        let mut resolutions = HashMap::new();
+        for resolution_msg in msg.resolutions {
+            match resolution_msg.resolution {
+                Some(resolution) => {
+                    resolutions.insert(resolution_msg.obj_id, resolution);
+                }
+                None => {
+                    return Err(Status::invalid_argument(format!(
+                        "No resolution given for object {}",
+                        resolution_msg.obj_id
+                    )))
+                }
+            }
+        }
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let resp = obj_client
+            .get_diverged_objects(TracedRequest::new(objects::GetDivergedObjectsInput {
+                ours_file: prefix.file.clone(),
+                ours_offset: prefix.offset,
+                theirs_file: source.clone(),
+                theirs_offset: msg.source_offset,
+            }))
+            .await;
+        let diverged = trace_response(resp)?;
+        let ours_changed: HashSet<&String> = diverged.ours.iter().collect();
+        let ours = common::get_changes(
+            &mut obj_client,
+            &prefix.file,
+            &diverged.theirs,
+            prefix.offset,
+        )
+        .await?;
+        let theirs = common::get_changes(
+            &mut obj_client,
+            &source,
+            &diverged.theirs,
+            msg.source_offset,
+        )
+        .await?;
+        let mut changes = Vec::new();
+        let mut obj_ids = Vec::new();
+        let mut conflicts = Vec::new();
+        for ((obj_id, ours), theirs) in diverged.theirs.iter().zip(ours).zip(theirs) {
+            let current = common::object_after(ours);
+            let mut target = common::object_after(theirs);
+            //Both sides making the same change isn't a conflict
+            if ours_changed.contains(obj_id) && current != target {
+                match resolutions.remove(obj_id) {
+                    Some(conflict_resolution_msg::Resolution::Ours(..)) => continue,
+                    Some(conflict_resolution_msg::Resolution::Theirs(..)) => (),
+                    Some(conflict_resolution_msg::Resolution::Merged(obj)) => {
+                        if obj.id != *obj_id {
+                            return Err(Status::invalid_argument(format!(
+                                "Merged object {} doesn't match conflicting object {}",
+                                obj.id, obj_id
+                            )));
+                        }
+                        target = Some(obj);
+                    }
+                    None => {
+                        conflicts.push(MergeConflictMsg {
+                            obj_id: obj_id.clone(),
+                            ours_file: prefix.file.clone(),
+                            ours_offset: prefix.offset,
+                            theirs_file: source.clone(),
+                            theirs_offset: msg.source_offset,
+                            base_file: diverged.base_file.clone(),
+                            base_offset: diverged.base_offset,
+                        });
+                        continue;
+                    }
+                }
+            }
+            if let Some(change) = common::change_to(&prefix.user, obj_id, &current, target) {
+                obj_ids.push(obj_id.clone());
+                changes.push(change);
+            }
+        }
+        if let Some(obj_id) = resolutions.keys().next() {
+            return Err(Status::invalid_argument(format!(
+                "Object {} isn't in conflict",
+                obj_id
+            )));
+        }
+        if !conflicts.is_empty() || changes.is_empty() {
+            return Ok(Response::new(MergeBranchOutput {
+                obj_ids: Vec::new(),
+                conflicts,
+                offset: prefix.offset,
+            }));
+        }
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        //The whole merge should undo in one step
+        let resp = undo_client
+            .begin_undo_event(TracedRequest::new(undo::BeginUndoEventInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        trace_response(resp)?;
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            changes,
+        )
+        .await?;
+        Ok(Response::new(MergeBranchOutput {
+            obj_ids,
+            conflicts,
+            offset,
+        }))
+    }
+
+    #[instrument]
+    async fn revert(
+        &self,
+        request: Request<RevertInput>,
+    ) -> Result<Response<RevertOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix = Prefix::new(msg.prefix)?;
+        let target = match msg.target {
+            Some(revert_input::Target::Offsets(range)) => {
+                undo::revert_input::Target::Offsets(undo::OffsetRangeMsg {
+                    start: range.start,
+                    end: range.end,
+                })
+            }
+            Some(revert_input::Target::Event(event)) => undo::revert_input::Target::Event(event),
+            None => return Err(Status::invalid_argument("Nothing given to revert")),
+        };
+        let mut undo_client = common::undo_client(self.undo_url.clone()).await?;
+        let resp = undo_client
+            .revert(TracedRequest::new(undo::RevertInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+                target: Some(target),
+                force: msg.force,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        if output.changes.is_empty() {
+            return Ok(Response::new(RevertOutput {
+                offset: prefix.offset,
+                conflicts: output.conflicts,
+            }));
+        }
+        //The revert should undo in one step
+        let resp = undo_client
+            .begin_undo_event(TracedRequest::new(undo::BeginUndoEventInput {
+                file: prefix.file.clone(),
+                user: prefix.user.clone(),
+            }))
+            .await;
+        trace_response(resp)?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let offset = common::submit_changes(
+            &mut submit_client,
+            prefix.file,
+            prefix.user,
+            prefix.offset,
+            output.changes,
+        )
+        .await?;
+        Ok(Response::new(RevertOutput {
+            offset,
+            conflicts: output.conflicts,
+        }))
+    }
+
+    #[instrument]
+    async fn create_tag(
+        &self,
+        request: Request<CreateTagInput>,
+    ) -> Result<Response<CreateTagOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix = Prefix::new(msg.prefix)?;
+        if msg.name.is_empty() {
+            return Err(Status::invalid_argument("Tag name is required"));
+        }
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let tags = common::get_tags(&mut obj_client, &prefix.file).await?;
+        let name = &msg.name;
+        if tags.iter().any(|tag| tag.name == *name) {
+            return Err(Status::already_exists(format!(
+                "Tag {} already exists",
+                msg.name
+            )));
+        }
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let resp = submit_client
+            .submit_tag(TracedRequest::new(submit::SubmitTagInput {
+                file: prefix.file,
+                user: prefix.user,
+                tag: Some(object_state::TagMsg {
+                    name: msg.name,
+                    offset: prefix.offset,
+                    description: msg.description,
+                    removed: false,
+                }),
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(CreateTagOutput {
+            offset: output.offset,
+        }))
+    }
+
+    #[instrument]
+    async fn list_tags(
+        &self,
+        request: Request<ListTagsInput>,
+    ) -> Result<Response<ListTagsOutput>, Status> {
+        let msg = request.into_inner();
+        let file = common::branch_key(&msg.file, &msg.branch)?;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let tags = common::get_tags(&mut obj_client, &file).await?;
+        Ok(Response::new(ListTagsOutput { tags }))
+    }
+
+    #[instrument]
+    async fn delete_tag(
+        &self,
+        request: Request<DeleteTagInput>,
+    ) -> Result<Response<DeleteTagOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix = Prefix::new(msg.prefix)?;
+        let name = &msg.name;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let tag = common::get_tags(&mut obj_client, &prefix.file)
+            .await?
+            .into_iter()
+            .find(|tag| tag.name == *name)
+            .ok_or_else(|| Status::not_found(format!("Tag {:?} not found", name)))?;
+        let mut submit_client = common::submit_client(self.submit_url.clone()).await?;
+        let resp = submit_client
+            .submit_tag(TracedRequest::new(submit::SubmitTagInput {
+                file: prefix.file,
+                user: prefix.user,
+                tag: Some(object_state::TagMsg {
+                    removed: true,
+                    ..tag
+                }),
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        Ok(Response::new(DeleteTagOutput {
+            offset: output.offset,
+        }))
+    }
+
+    #[instrument]
+    async fn diff_file(
+        &self,
+        request: Request<DiffFileInput>,
+    ) -> Result<Response<DiffFileOutput>, Status> {
+        let msg = request.into_inner();
+        let file = common::branch_key(&msg.file, &msg.branch)?;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let from =
+            common::offset_or_tag(&mut obj_client, &file, msg.from_offset, &msg.from_tag).await?;
+        let to = common::offset_or_tag(&mut obj_client, &file, msg.to_offset, &msg.to_tag).await?;
+        //The objects service already knows which objects changed between two points in a file's history
+        let resp = obj_client
+            .get_diverged_objects(TracedRequest::new(objects::GetDivergedObjectsInput {
+                ours_file: file.clone(),
+                ours_offset: from,
+                theirs_file: file.clone(),
+                theirs_offset: to,
+            }))
+            .await;
+        let diverged = trace_response(resp)?;
+        let mut obj_ids = diverged.ours;
+        obj_ids.extend(diverged.theirs);
+        let before = common::get_changes(&mut obj_client, &file, &obj_ids, from).await?;
+        let after = common::get_changes(&mut obj_client, &file, &obj_ids, to).await?;
+        let mut added = Vec::new();
+        let mut deleted = Vec::new();
+        let mut modified_ids = Vec::new();
+        let mut pairs = Vec::new();
+        for ((obj_id, before), after) in obj_ids.into_iter().zip(before).zip(after) {
+            match (common::object_after(before), common::object_after(after)) {
+                (None, Some(..)) => added.push(obj_id),
+                (Some(..), None) => deleted.push(obj_id),
+                (Some(before), Some(after)) => {
+                    //Changed and then changed back
+                    if before != after {
+                        modified_ids.push(obj_id);
+                        pairs.push(operations::ObjectPairMsg {
+                            before: Some(before),
+                            after: Some(after),
+                        });
+                    }
+                }
+                (None, None) => (),
+            }
+        }
+        let mut modified = Vec::new();
+        if !pairs.is_empty() {
+            let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+            let resp = ops_client
+                .diff_objects(TracedRequest::new(operations::DiffObjectsInput { pairs }))
+                .await;
+            let output = trace_response(resp)?;
+            for (obj_id, diff) in modified_ids.into_iter().zip(output.diffs) {
+                let properties = diff
+                    .properties
+                    .into_iter()
+                    .map(|property| PropertyDiffMsg {
+                        path: property.path,
+                        before: property.before,
+                        after: property.after,
+                    })
+                    .collect();
+                modified.push(ModifiedObjectMsg { obj_id, properties });
+            }
+        }
+        Ok(Response::new(DiffFileOutput {
+            added,
+            deleted,
+            modified,
+        }))
+    }
+
+    #[instrument]
+    async fn blame_object(
+        &self,
+        request: Request<BlameObjectInput>,
+    ) -> Result<Response<BlameObjectOutput>, Status> {
+        let msg = request.into_inner();
+        let prefix = Prefix::for_reading(msg.prefix, &self.obj_url).await?;
+        let mut obj_client = common::objects_client(self.obj_url.clone()).await?;
+        let resp = obj_client
+            .get_object_history(TracedRequest::new(objects::GetObjectHistoryInput {
+                file: prefix.file,
+                obj_id: msg.obj_id.clone(),
+                offset: prefix.offset,
+            }))
+            .await;
+        let history = trace_response(resp)?.entries;
+        //Only the object's current life counts, a deleted and re-added object starts again
+        let mut entries = Vec::new();
+        let mut versions = Vec::new();
+        for entry in history {
+            match common::object_after(entry.change.clone()) {
+                Some(obj) => {
+                    versions.push(obj);
+                    entries.push(entry);
+                }
+                None => {
+                    versions.clear();
+                    entries.clear();
+                }
+            }
+        }
+        if versions.is_empty() {
+            return Err(Status::not_found(format!(
+                "Object {} has been deleted",
+                msg.obj_id
+            )));
+        }
+        let mut ops_client = common::operations_client(self.ops_url.clone()).await?;
+        let resp = ops_client
+            .blame_object(TracedRequest::new(operations::BlameObjectInput {
+                versions,
+            }))
+            .await;
+        let output = trace_response(resp)?;
+        let mut properties = Vec::new();
+        for blame in output.properties {
+            let entry = entries.get(blame.version as usize).ok_or_else(|| {
+                Status::internal(format!("Blamed version {} doesn't exist", blame.version))
+            })?;
+            properties.push(PropertyBlameMsg {
+                path: blame.path,
+                value: blame.value,
+                user: entry
+                    .change
+                    .as_ref()
+                    .map(|change| change.user.clone())
+                    .unwrap_or_default(),
+                offset: entry.offset,
+                file: entry.file.clone(),
+            });
+        }
+        Ok(Response::new(BlameObjectOutput { properties }))
+    }
+}
+
+///Serves the client-facing api, calling out to the other services at the given urls
+pub async fn run(
+    run_url: SocketAddr,
+    undo_url: String,
+    obj_url: String,
+    ops_url: String,
+    submit_url: String,
+    rep_cache_url: String,
+) -> Result<(), tonic::transport::Error> {
+    let svc = api_server::ApiServer::new(ApiService {
+        undo_url,
+        obj_url,
+        ops_url,
+        submit_url,
+        rep_cache_url,
+    });
+    info!("Running on {:?}", run_url);
+    Server::builder().add_service(svc).serve(run_url).await
+}
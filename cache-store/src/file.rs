@@ -0,0 +1,140 @@
+use crate::memory::{apply, Entries};
+use crate::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Seek, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+struct Journaled {
+    entries: Entries,
+    journal: File,
+}
+
+///A store embedded in the process and kept on disk.
+///Every write is appended to a journal in the directory, much like Redis' append-only file,
+///and the journal is replayed into memory on open.
+#[derive(Debug)]
+pub struct FileStore {
+    state: Mutex<Journaled>,
+}
+
+impl FileStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<FileStore, StoreError> {
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join("journal");
+        let mut entries = Entries::new();
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(&path)?);
+            //Where the last complete command ends
+            let mut good_len = 0;
+            loop {
+                match bincode::deserialize_from::<_, Command>(&mut reader) {
+                    Ok(cmd) => {
+                        apply(&mut entries, cmd)?;
+                        good_len = reader.stream_position()?;
+                    }
+                    //A write cut short by a crash leaves a partial command at the end
+                    Err(e) => match *e {
+                        bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => {
+                            break
+                        }
+                        _ => return Err(StoreError::from(e)),
+                    },
+                }
+            }
+            //Cut the partial command off so new ones don't land after it
+            let file = OpenOptions::new().write(true).open(&path)?;
+            if file.metadata()?.len() > good_len {
+                file.set_len(good_len)?;
+            }
+        }
+        let journal = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(FileStore {
+            state: Mutex::new(Journaled { entries, journal }),
+        })
+    }
+}
+
+#[async_trait]
+impl Store for FileStore {
+    async fn query(&self, cmd: Command) -> Result<Value, StoreError> {
+        let mut state = self.state.lock().unwrap();
+        if !cmd.is_write() {
+            return apply(&mut state.entries, cmd);
+        }
+        let serialized = bincode::serialize(&cmd)?;
+        //Journal first, so memory never has a write the journal doesn't
+        let len = state.journal.metadata()?.len();
+        if let Err(e) = state.journal.write_all(&serialized) {
+            state.journal.set_len(len)?;
+            return Err(StoreError::from(e));
+        }
+        match apply(&mut state.entries, cmd) {
+            Ok(value) => Ok(value),
+            //A write that fails changes nothing, so it comes back out of the journal
+            Err(e) => {
+                state.journal.set_len(len)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = FileStore::open(dir.path()).unwrap();
+            store.set("offset", 3i64).await.unwrap();
+            store.lpush("list", vec![1u8]).await.unwrap();
+            store.lpush("list", vec![2u8]).await.unwrap();
+            store.sadd("set", "a").await.unwrap();
+            //Writes that fail aren't journaled
+            assert!(store.sadd("list", "b").await.is_err());
+        }
+
+        let store = FileStore::open(dir.path()).unwrap();
+        let offset: Option<i64> = store.get("offset").await.unwrap();
+        assert_eq!(offset, Some(3));
+        let list: Vec<Vec<u8>> = store.lrange("list", 0, -1).await.unwrap();
+        assert_eq!(list, vec![vec![2], vec![1]]);
+        let members: Vec<String> = store.smembers("set").await.unwrap();
+        assert_eq!(members, vec![String::from("a")]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_write() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = FileStore::open(dir.path()).unwrap();
+            store.set("first", 1i64).await.unwrap();
+            store.set("second", 2i64).await.unwrap();
+        }
+        //Cut the last command short, as a crash partway through writing it would
+        let path = dir.path().join("journal");
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+
+        {
+            let store = FileStore::open(dir.path()).unwrap();
+            let second: Option<i64> = store.get("second").await.unwrap();
+            assert_eq!(second, None);
+            store.set("third", 3i64).await.unwrap();
+        }
+        //The command written after the partial one reads back on the next open
+        let store = FileStore::open(dir.path()).unwrap();
+        let first: Option<i64> = store.get("first").await.unwrap();
+        assert_eq!(first, Some(1));
+        let third: Option<i64> = store.get("third").await.unwrap();
+        assert_eq!(third, Some(3));
+    }
+}
@@ -0,0 +1,162 @@
+//! Histories of something's versions kept in a list, newest first, like each object's representations.
+//! Histories are trimmed to a length, but a version that a tagged offset still reads is set aside before it's dropped,
+//! so reading at exactly the tagged offset still finds it.
+
+use crate::{Store, StoreCommands, StoreError};
+
+//Set once the history has been trimmed, to the offset of the newest version dropped
+fn trimmed(list: &str) -> String {
+    format!("{}:trimmed", list)
+}
+
+//The version a tagged offset reads, once it's been trimmed from the list
+fn tagged(list: &str, offset: i64) -> String {
+    format!("{}:tagged:{}", list, offset)
+}
+
+///What a history has as of an offset
+#[derive(Debug, PartialEq)]
+pub enum Version {
+    ///The newest version at or before the offset
+    Found(Vec<u8>),
+    ///The versions at or before the offset have been trimmed away
+    Trimmed,
+    ///Nothing was ever written at or before the offset
+    Missing,
+}
+
+///Pushes the newest version and trims the history to `keep` versions, setting aside the versions `tags` read.
+///`legacy` is a key an older version may have been set at before there were histories,
+///and `offset_of` reads the offset a version was written at.
+pub async fn push<E: From<StoreError>>(
+    conn: &dyn Store,
+    list: &str,
+    legacy: Option<&str>,
+    version: Vec<u8>,
+    keep: isize,
+    tags: &[i64],
+    offset_of: fn(&[u8]) -> Result<i64, E>,
+) -> Result<(), E> {
+    //Push to the left so the latest is first in the list
+    conn.lpush(list, version).await?;
+    let length: isize = conn.llen(list).await?;
+    if length <= keep {
+        return Ok(());
+    }
+    let oldest_kept: Vec<u8> = conn.lindex(list, keep - 1).await?;
+    let oldest_kept = offset_of(&oldest_kept)?;
+    let mut dropped: Vec<Vec<u8>> = conn.lrange(list, keep, -1).await?;
+    let already_trimmed: Option<i64> = conn.get(trimmed(list)).await?;
+    if let (Some(legacy), None) = (legacy, already_trimmed) {
+        let version: Option<Vec<u8>> = conn.get(legacy).await?;
+        dropped.extend(version);
+    }
+    let mut offsets = Vec::new();
+    for version in &dropped {
+        offsets.push(offset_of(version)?);
+    }
+    //Tags at or after the oldest version kept still read a version in the list
+    for tag in tags.iter().filter(|tag| **tag < oldest_kept) {
+        if let Some(index) = offsets.iter().position(|offset| offset <= tag) {
+            conn.set(tagged(list, *tag), dropped[index].clone()).await?;
+        }
+    }
+    conn.set(trimmed(list), offsets[0]).await?;
+    conn.ltrim(list, 0, keep - 1).await?;
+    Ok(())
+}
+
+///The newest version at or before the offset.
+///The version at `legacy` is only read if the history has never been trimmed, since the versions after it might have been.
+pub async fn find<E: From<StoreError>>(
+    conn: &dyn Store,
+    list: &str,
+    legacy: Option<&str>,
+    offset: i64,
+    offset_of: fn(&[u8]) -> Result<i64, E>,
+) -> Result<Version, E> {
+    let versions: Vec<Vec<u8>> = conn.lrange(list, 0, -1).await?;
+    for version in versions {
+        if offset_of(&version)? <= offset {
+            return Ok(Version::Found(version));
+        }
+    }
+    let already_trimmed: Option<i64> = conn.get(trimmed(list)).await?;
+    if already_trimmed.is_some() {
+        let version: Option<Vec<u8>> = conn.get(tagged(list, offset)).await?;
+        return Ok(version.map_or(Version::Trimmed, Version::Found));
+    }
+    if let Some(legacy) = legacy {
+        let version: Option<Vec<u8>> = conn.get(legacy).await?;
+        if let Some(version) = version {
+            if offset_of(&version)? <= offset {
+                return Ok(Version::Found(version));
+            }
+        }
+    }
+    Ok(Version::Missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStore;
+
+    fn offset_of(version: &[u8]) -> Result<i64, StoreError> {
+        Ok(String::from_utf8_lossy(version).parse().unwrap())
+    }
+
+    fn version(offset: i64) -> Vec<u8> {
+        offset.to_string().into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let conn = MemoryStore::new();
+        conn.set("legacy", version(1)).await.unwrap();
+        for offset in 2..5 {
+            push(
+                &conn,
+                "list",
+                Some("legacy"),
+                version(offset),
+                3,
+                &[],
+                offset_of,
+            )
+            .await
+            .unwrap();
+        }
+        let found = find(&conn, "list", Some("legacy"), 1, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Found(version(1)));
+        let found = find(&conn, "list", Some("legacy"), 0, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Missing);
+
+        //Offset 1 is tagged, and it reads the legacy version
+        for offset in 5..8 {
+            push(
+                &conn,
+                "list",
+                Some("legacy"),
+                version(offset),
+                3,
+                &[1, 3],
+                offset_of,
+            )
+            .await
+            .unwrap();
+        }
+        let length: isize = conn.llen("list").await.unwrap();
+        assert_eq!(length, 3);
+        let found = find(&conn, "list", Some("legacy"), 5, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Found(version(5)));
+        let found = find(&conn, "list", Some("legacy"), 3, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Found(version(3)));
+        let found = find(&conn, "list", Some("legacy"), 1, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Found(version(1)));
+        let found = find(&conn, "list", Some("legacy"), 2, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Trimmed);
+        let found = find(&conn, "list", Some("legacy"), 4, offset_of).await;
+        assert_eq!(found.unwrap(), Version::Trimmed);
+    }
+}
@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use redis::{FromRedisValue, ToRedisArgs, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+mod file;
+pub mod history;
+mod memory;
+mod redis_store;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+pub use redis_store::RedisStore;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("Redis error: {0}")]
+    RedisError(#[from] redis::RedisError),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Bincode error: {0}")]
+    BincodeError(#[from] bincode::Error),
+    #[error("Key {0} holds the wrong kind of value for this command")]
+    WrongType(String),
+    #[error("Expected one argument, got {0}")]
+    ArgumentCount(usize),
+    #[error("Invalid score {0}")]
+    InvalidScore(String),
+    #[error("Unsupported cache url {0}")]
+    UnsupportedUrl(String),
+}
+
+///The subset of Redis commands the caches use.
+///Replies come back as Redis values whatever the store, so they convert the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: Vec<u8>,
+    },
+    LPush {
+        key: String,
+        values: Vec<Vec<u8>>,
+    },
+    RPush {
+        key: String,
+        values: Vec<Vec<u8>>,
+    },
+    LLen {
+        key: String,
+    },
+    LIndex {
+        key: String,
+        index: i64,
+    },
+    LRange {
+        key: String,
+        start: i64,
+        stop: i64,
+    },
+    LRem {
+        key: String,
+        count: i64,
+        value: Vec<u8>,
+    },
+    LTrim {
+        key: String,
+        start: i64,
+        stop: i64,
+    },
+    SAdd {
+        key: String,
+        members: Vec<Vec<u8>>,
+    },
+    SRem {
+        key: String,
+        members: Vec<Vec<u8>>,
+    },
+    SMembers {
+        key: String,
+    },
+    HSet {
+        key: String,
+        field: Vec<u8>,
+        value: Vec<u8>,
+    },
+    HDel {
+        key: String,
+        fields: Vec<Vec<u8>>,
+    },
+    HVals {
+        key: String,
+    },
+    ZAdd {
+        key: String,
+        member: Vec<u8>,
+        score: f64,
+    },
+    ZRangeByScore {
+        key: String,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl Command {
+    ///Whether the command changes what's stored
+    pub fn is_write(&self) -> bool {
+        !matches!(
+            self,
+            Command::Get { .. }
+                | Command::LLen { .. }
+                | Command::LIndex { .. }
+                | Command::LRange { .. }
+                | Command::SMembers { .. }
+                | Command::HVals { .. }
+                | Command::ZRangeByScore { .. }
+        )
+    }
+}
+
+///Where a service keeps its cache.
+#[async_trait]
+pub trait Store: std::fmt::Debug + Send + Sync {
+    async fn query(&self, cmd: Command) -> Result<Value, StoreError>;
+}
+
+#[async_trait]
+impl<S: Store + ?Sized> Store for Arc<S> {
+    async fn query(&self, cmd: Command) -> Result<Value, StoreError> {
+        self.as_ref().query(cmd).await
+    }
+}
+
+fn single<T: ToRedisArgs>(value: T) -> Result<Vec<u8>, StoreError> {
+    let mut args = value.to_redis_args();
+    if args.len() == 1 {
+        Ok(args.remove(0))
+    } else {
+        Err(StoreError::ArgumentCount(args.len()))
+    }
+}
+
+fn score<T: ToRedisArgs>(value: T) -> Result<f64, StoreError> {
+    let arg = String::from_utf8_lossy(&single(value)?).into_owned();
+    arg.parse().map_err(|_| StoreError::InvalidScore(arg))
+}
+
+fn convert<T: FromRedisValue>(value: Value) -> Result<T, StoreError> {
+    Ok(T::from_redis_value(&value)?)
+}
+
+///Typed versions of the commands, along the lines of redis::AsyncCommands
+#[async_trait]
+pub trait StoreCommands: Store {
+    async fn get<K, RV>(&self, key: K) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        convert(self.query(Command::Get { key }).await?)
+    }
+
+    async fn set<K, V>(&self, key: K, value: V) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        V: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let value = single(value)?;
+        self.query(Command::Set { key, value }).await?;
+        Ok(())
+    }
+
+    async fn lpush<K, V>(&self, key: K, values: V) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        V: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let values = values.to_redis_args();
+        self.query(Command::LPush { key, values }).await?;
+        Ok(())
+    }
+
+    async fn rpush<K, V>(&self, key: K, values: V) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        V: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let values = values.to_redis_args();
+        self.query(Command::RPush { key, values }).await?;
+        Ok(())
+    }
+
+    async fn llen<K, RV>(&self, key: K) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        convert(self.query(Command::LLen { key }).await?)
+    }
+
+    async fn lindex<K, RV>(&self, key: K, index: isize) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        let index = index as i64;
+        convert(self.query(Command::LIndex { key, index }).await?)
+    }
+
+    async fn lrange<K, RV>(&self, key: K, start: isize, stop: isize) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        let (start, stop) = (start as i64, stop as i64);
+        convert(self.query(Command::LRange { key, start, stop }).await?)
+    }
+
+    async fn lrem<K, V>(&self, key: K, count: isize, value: V) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        V: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let count = count as i64;
+        let value = single(value)?;
+        self.query(Command::LRem { key, count, value }).await?;
+        Ok(())
+    }
+
+    async fn ltrim<K>(&self, key: K, start: isize, stop: isize) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+    {
+        let key = key.as_ref().into();
+        let (start, stop) = (start as i64, stop as i64);
+        self.query(Command::LTrim { key, start, stop }).await?;
+        Ok(())
+    }
+
+    async fn sadd<K, M>(&self, key: K, members: M) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        M: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let members = members.to_redis_args();
+        self.query(Command::SAdd { key, members }).await?;
+        Ok(())
+    }
+
+    async fn srem<K, M>(&self, key: K, members: M) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        M: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let members = members.to_redis_args();
+        self.query(Command::SRem { key, members }).await?;
+        Ok(())
+    }
+
+    async fn smembers<K, RV>(&self, key: K) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        convert(self.query(Command::SMembers { key }).await?)
+    }
+
+    async fn hset<K, F, V>(&self, key: K, field: F, value: V) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        F: ToRedisArgs + Send,
+        V: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let field = single(field)?;
+        let value = single(value)?;
+        self.query(Command::HSet { key, field, value }).await?;
+        Ok(())
+    }
+
+    async fn hdel<K, F>(&self, key: K, fields: F) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        F: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let fields = fields.to_redis_args();
+        self.query(Command::HDel { key, fields }).await?;
+        Ok(())
+    }
+
+    async fn hvals<K, RV>(&self, key: K) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+    {
+        let key = key.as_ref().into();
+        convert(self.query(Command::HVals { key }).await?)
+    }
+
+    async fn zadd<K, M, S>(&self, key: K, member: M, score: S) -> Result<(), StoreError>
+    where
+        K: AsRef<str> + Send,
+        M: ToRedisArgs + Send,
+        S: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let member = single(member)?;
+        let score = crate::score(score)?;
+        self.query(Command::ZAdd { key, member, score }).await?;
+        Ok(())
+    }
+
+    ///min and max can be numbers or "-inf"/"+inf", like in Redis
+    async fn zrangebyscore<K, RV, MIN, MAX>(
+        &self,
+        key: K,
+        min: MIN,
+        max: MAX,
+    ) -> Result<RV, StoreError>
+    where
+        K: AsRef<str> + Send,
+        RV: FromRedisValue,
+        MIN: ToRedisArgs + Send,
+        MAX: ToRedisArgs + Send,
+    {
+        let key = key.as_ref().into();
+        let min = score(min)?;
+        let max = score(max)?;
+        convert(self.query(Command::ZRangeByScore { key, min, max }).await?)
+    }
+}
+
+impl<S: Store + ?Sized> StoreCommands for S {}
+
+lazy_static! {
+    static ref SHARED_STORES: Mutex<HashMap<String, Arc<dyn Store>>> = Mutex::new(HashMap::new());
+}
+
+///Opens the store a cache url points to.
+///redis:// urls get a new connection each time.  memory://name is a store that lives as long as the process,
+///and file:// followed by a directory keeps the store on disk there.  Those two are shared by every caller
+///with the same url.
+pub async fn connect(url: &str) -> Result<Arc<dyn Store>, StoreError> {
+    if url.starts_with("redis://") || url.starts_with("rediss://") || url.starts_with("unix://") {
+        return Ok(Arc::new(RedisStore::connect(url).await?));
+    }
+    let mut shared = SHARED_STORES.lock().unwrap();
+    if let Some(store) = shared.get(url) {
+        return Ok(store.clone());
+    }
+    let store: Arc<dyn Store> = if url.starts_with("memory://") {
+        Arc::new(MemoryStore::new())
+    } else if let Some(dir) = url.strip_prefix("file://") {
+        Arc::new(FileStore::open(dir)?)
+    } else {
+        return Err(StoreError::UnsupportedUrl(String::from(url)));
+    };
+    shared.insert(String::from(url), store.clone());
+    Ok(store)
+}
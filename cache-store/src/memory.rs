@@ -0,0 +1,312 @@
+use crate::*;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+#[derive(Debug)]
+pub(crate) enum Entry {
+    Bytes(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Set(BTreeSet<Vec<u8>>),
+    Hash(BTreeMap<Vec<u8>, Vec<u8>>),
+    //Kept sorted by score, then member
+    SortedSet(Vec<(f64, Vec<u8>)>),
+}
+
+pub(crate) type Entries = HashMap<String, Entry>;
+
+fn bulk(values: impl IntoIterator<Item = Vec<u8>>) -> Value {
+    Value::Bulk(values.into_iter().map(Value::Data).collect())
+}
+
+//Turns a Redis list index, which counts back from the end when negative, into a position
+fn list_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index >= 0 && index < len as i64 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+//Turns a Redis start and stop, which are inclusive and count back from the end when negative, into positions
+fn list_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+macro_rules! entry_as {
+    ($entries:expr, $key:expr, $variant:ident, $default:expr) => {
+        match $entries
+            .entry($key.clone())
+            .or_insert_with(|| Entry::$variant($default))
+        {
+            Entry::$variant(value) => value,
+            _ => return Err(StoreError::WrongType($key)),
+        }
+    };
+}
+
+macro_rules! existing_as {
+    ($entries:expr, $key:expr, $variant:ident) => {
+        match $entries.get(&$key) {
+            Some(Entry::$variant(value)) => Some(value),
+            Some(_) => return Err(StoreError::WrongType($key)),
+            None => None,
+        }
+    };
+}
+
+//Redis drops keys whose collection has been emptied
+fn remove_if_empty(entries: &mut Entries, key: &str) {
+    let empty = match entries.get(key) {
+        Some(Entry::List(list)) => list.is_empty(),
+        Some(Entry::Set(set)) => set.is_empty(),
+        Some(Entry::Hash(hash)) => hash.is_empty(),
+        Some(Entry::SortedSet(set)) => set.is_empty(),
+        _ => false,
+    };
+    if empty {
+        entries.remove(key);
+    }
+}
+
+pub(crate) fn apply(entries: &mut Entries, cmd: Command) -> Result<Value, StoreError> {
+    match cmd {
+        Command::Get { key } => Ok(match existing_as!(entries, key, Bytes) {
+            Some(value) => Value::Data(value.clone()),
+            None => Value::Nil,
+        }),
+        Command::Set { key, value } => {
+            entries.insert(key, Entry::Bytes(value));
+            Ok(Value::Okay)
+        }
+        Command::LPush { key, values } => {
+            let list = entry_as!(entries, key, List, VecDeque::new());
+            for value in values {
+                list.push_front(value);
+            }
+            Ok(Value::Int(list.len() as i64))
+        }
+        Command::RPush { key, values } => {
+            let list = entry_as!(entries, key, List, VecDeque::new());
+            list.extend(values);
+            Ok(Value::Int(list.len() as i64))
+        }
+        Command::LLen { key } => Ok(Value::Int(
+            existing_as!(entries, key, List).map_or(0, |list| list.len() as i64),
+        )),
+        Command::LIndex { key, index } => {
+            let value = existing_as!(entries, key, List)
+                .and_then(|list| list_index(list.len(), index).map(|i| list[i].clone()));
+            Ok(value.map_or(Value::Nil, Value::Data))
+        }
+        Command::LRange { key, start, stop } => {
+            let list = match existing_as!(entries, key, List) {
+                Some(list) => list,
+                None => return Ok(bulk(vec![])),
+            };
+            match list_range(list.len(), start, stop) {
+                Some((start, stop)) => Ok(bulk(
+                    list.iter().skip(start).take(stop - start + 1).cloned(),
+                )),
+                None => Ok(bulk(vec![])),
+            }
+        }
+        Command::LTrim { key, start, stop } => {
+            let list = match entries.get_mut(&key) {
+                Some(Entry::List(list)) => list,
+                Some(_) => return Err(StoreError::WrongType(key)),
+                None => return Ok(Value::Okay),
+            };
+            match list_range(list.len(), start, stop) {
+                Some((start, stop)) => {
+                    list.truncate(stop + 1);
+                    list.drain(..start);
+                }
+                None => list.clear(),
+            }
+            remove_if_empty(entries, &key);
+            Ok(Value::Okay)
+        }
+        Command::LRem { key, count, value } => {
+            let list = match entries.get_mut(&key) {
+                Some(Entry::List(list)) => list,
+                Some(_) => return Err(StoreError::WrongType(key)),
+                None => return Ok(Value::Int(0)),
+            };
+            let limit = if count == 0 {
+                usize::MAX
+            } else {
+                count.unsigned_abs() as usize
+            };
+            let mut positions: Vec<usize> = list
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| **item == value)
+                .map(|(i, _)| i)
+                .collect();
+            if count < 0 {
+                positions.reverse();
+            }
+            positions.truncate(limit);
+            positions.sort_unstable();
+            for i in positions.iter().rev() {
+                list.remove(*i);
+            }
+            remove_if_empty(entries, &key);
+            Ok(Value::Int(positions.len() as i64))
+        }
+        Command::SAdd { key, members } => {
+            let set = entry_as!(entries, key, Set, BTreeSet::new());
+            let added = members
+                .into_iter()
+                .filter(|m| set.insert(m.clone()))
+                .count();
+            Ok(Value::Int(added as i64))
+        }
+        Command::SRem { key, members } => {
+            let set = match entries.get_mut(&key) {
+                Some(Entry::Set(set)) => set,
+                Some(_) => return Err(StoreError::WrongType(key)),
+                None => return Ok(Value::Int(0)),
+            };
+            let removed = members.iter().filter(|m| set.remove(*m)).count();
+            remove_if_empty(entries, &key);
+            Ok(Value::Int(removed as i64))
+        }
+        Command::SMembers { key } => Ok(bulk(
+            existing_as!(entries, key, Set)
+                .map(|set| set.iter().cloned().collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )),
+        Command::HSet { key, field, value } => {
+            let hash = entry_as!(entries, key, Hash, BTreeMap::new());
+            let added = hash.insert(field, value).is_none();
+            Ok(Value::Int(added as i64))
+        }
+        Command::HDel { key, fields } => {
+            let hash = match entries.get_mut(&key) {
+                Some(Entry::Hash(hash)) => hash,
+                Some(_) => return Err(StoreError::WrongType(key)),
+                None => return Ok(Value::Int(0)),
+            };
+            let removed = fields.iter().filter(|f| hash.remove(*f).is_some()).count();
+            remove_if_empty(entries, &key);
+            Ok(Value::Int(removed as i64))
+        }
+        Command::HVals { key } => Ok(bulk(
+            existing_as!(entries, key, Hash)
+                .map(|hash| hash.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )),
+        Command::ZAdd { key, member, score } => {
+            let set = entry_as!(entries, key, SortedSet, Vec::new());
+            let existing = set.iter().position(|(_, m)| *m == member);
+            if let Some(i) = existing {
+                set.remove(i);
+            }
+            let at = set
+                .iter()
+                .position(|(s, m)| (*s, m) > (score, &member))
+                .unwrap_or(set.len());
+            set.insert(at, (score, member));
+            Ok(Value::Int(existing.is_none() as i64))
+        }
+        Command::ZRangeByScore { key, min, max } => Ok(bulk(
+            existing_as!(entries, key, SortedSet)
+                .map(|set| {
+                    set.iter()
+                        .filter(|(score, _)| *score >= min && *score <= max)
+                        .map(|(_, member)| member.clone())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default(),
+        )),
+    }
+}
+
+///A store that lives as long as the process, for tests and running without Redis
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn query(&self, cmd: Command) -> Result<Value, StoreError> {
+        apply(&mut self.entries.lock().unwrap(), cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::new();
+        let missing: Option<i64> = store.get("offset").await.unwrap();
+        assert_eq!(missing, None);
+        store.set("offset", 5i64).await.unwrap();
+        let offset: Option<i64> = store.get("offset").await.unwrap();
+        assert_eq!(offset, Some(5));
+
+        store.lpush("list", vec![1u8]).await.unwrap();
+        store.lpush("list", vec![2u8]).await.unwrap();
+        store.rpush("list", vec![3u8]).await.unwrap();
+        let len: isize = store.llen("list").await.unwrap();
+        assert_eq!(len, 3);
+        let first: Vec<u8> = store.lindex("list", 0).await.unwrap();
+        assert_eq!(first, vec![2]);
+        let last: Vec<u8> = store.lindex("list", -1).await.unwrap();
+        assert_eq!(last, vec![3]);
+        let all: Vec<Vec<u8>> = store.lrange("list", 0, -1).await.unwrap();
+        assert_eq!(all, vec![vec![2], vec![1], vec![3]]);
+        store.lrem("list", 1, vec![1u8]).await.unwrap();
+        let all: Vec<Vec<u8>> = store.lrange("list", 0, -1).await.unwrap();
+        assert_eq!(all, vec![vec![2], vec![3]]);
+        store.rpush("list", vec![4u8]).await.unwrap();
+        store.ltrim("list", 0, 1).await.unwrap();
+        let all: Vec<Vec<u8>> = store.lrange("list", 0, -1).await.unwrap();
+        assert_eq!(all, vec![vec![2], vec![3]]);
+
+        store.sadd("set", vec!["a", "b"]).await.unwrap();
+        store.srem("set", "a").await.unwrap();
+        let members: Vec<String> = store.smembers("set").await.unwrap();
+        assert_eq!(members, vec![String::from("b")]);
+
+        store.hset("hash", "name", vec![1u8]).await.unwrap();
+        store.hset("hash", "other", vec![2u8]).await.unwrap();
+        store.hdel("hash", "name").await.unwrap();
+        let values: Vec<Vec<u8>> = store.hvals("hash").await.unwrap();
+        assert_eq!(values, vec![vec![2]]);
+
+        store.zadd("sorted", "late", 10i64).await.unwrap();
+        store.zadd("sorted", "early", 1i64).await.unwrap();
+        store.zadd("sorted", "middle", 5i64).await.unwrap();
+        let range: Vec<String> = store.zrangebyscore("sorted", 2i64, "+inf").await.unwrap();
+        assert_eq!(range, vec![String::from("middle"), String::from("late")]);
+
+        let wrong: Result<isize, StoreError> = store.llen("set").await;
+        assert!(wrong.is_err());
+    }
+}
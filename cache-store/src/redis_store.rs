@@ -0,0 +1,66 @@
+use crate::*;
+use redis::aio::MultiplexedConnection;
+
+fn to_cmd(cmd: Command) -> redis::Cmd {
+    match cmd {
+        Command::Get { key } => redis::cmd("GET").arg(key).clone(),
+        Command::Set { key, value } => redis::cmd("SET").arg(key).arg(value).clone(),
+        Command::LPush { key, values } => redis::cmd("LPUSH").arg(key).arg(values).clone(),
+        Command::RPush { key, values } => redis::cmd("RPUSH").arg(key).arg(values).clone(),
+        Command::LLen { key } => redis::cmd("LLEN").arg(key).clone(),
+        Command::LIndex { key, index } => redis::cmd("LINDEX").arg(key).arg(index).clone(),
+        Command::LRange { key, start, stop } => {
+            redis::cmd("LRANGE").arg(key).arg(start).arg(stop).clone()
+        }
+        Command::LRem { key, count, value } => {
+            redis::cmd("LREM").arg(key).arg(count).arg(value).clone()
+        }
+        Command::LTrim { key, start, stop } => {
+            redis::cmd("LTRIM").arg(key).arg(start).arg(stop).clone()
+        }
+        Command::SAdd { key, members } => redis::cmd("SADD").arg(key).arg(members).clone(),
+        Command::SRem { key, members } => redis::cmd("SREM").arg(key).arg(members).clone(),
+        Command::SMembers { key } => redis::cmd("SMEMBERS").arg(key).clone(),
+        Command::HSet { key, field, value } => {
+            redis::cmd("HSET").arg(key).arg(field).arg(value).clone()
+        }
+        Command::HDel { key, fields } => redis::cmd("HDEL").arg(key).arg(fields).clone(),
+        Command::HVals { key } => redis::cmd("HVALS").arg(key).clone(),
+        Command::ZAdd { key, member, score } => {
+            redis::cmd("ZADD").arg(key).arg(score).arg(member).clone()
+        }
+        Command::ZRangeByScore { key, min, max } => redis::cmd("ZRANGEBYSCORE")
+            .arg(key)
+            .arg(min)
+            .arg(max)
+            .clone(),
+    }
+}
+
+pub struct RedisStore {
+    conn: MultiplexedConnection,
+}
+
+impl std::fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisStore").finish()
+    }
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<RedisStore, StoreError> {
+        let client = redis::Client::open(url)?;
+        let (conn, fut) = client.get_multiplexed_async_connection().await?;
+        tokio::spawn(fut);
+        Ok(RedisStore { conn })
+    }
+}
+
+#[async_trait]
+impl Store for RedisStore {
+    async fn query(&self, cmd: Command) -> Result<Value, StoreError> {
+        //Multiplexed connections are cheap to clone and all share one socket
+        let mut conn = self.conn.clone();
+        Ok(to_cmd(cmd).query_async(&mut conn).await?)
+    }
+}
@@ -0,0 +1,218 @@
+use crate::memory::MemoryConsumer;
+use crate::*;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+///A log kept in append-only files in a directory, so it survives restarts without a Kafka cluster.
+///Each topic is a file of bincoded (key, payload) pairs, and an offset is a message's position in it.
+///Records are also kept in memory once a topic has been read, so this suits one process on a laptop.
+#[derive(Debug)]
+pub struct FileLog {
+    dir: PathBuf,
+    memory: MemoryLog,
+    //Appends hold the topic's file while writing so the file and memory agree on offsets
+    files: Mutex<HashMap<String, File>>,
+}
+
+impl FileLog {
+    pub fn open(dir: impl AsRef<Path>) -> Result<FileLog, LogError> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(FileLog {
+            dir: dir.as_ref().to_path_buf(),
+            memory: MemoryLog::new(),
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn log_path(&self, topic: &str) -> PathBuf {
+        self.dir.join(format!("{}.log", topic))
+    }
+
+    fn committed_path(&self, topic: &str, group_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}.committed", topic, group_id))
+    }
+
+    //Opens the topic's file, reading what's already in it the first time
+    fn with_file<T>(
+        &self,
+        topic: &str,
+        f: impl FnOnce(&mut File) -> Result<T, LogError>,
+    ) -> Result<T, LogError> {
+        let mut files = self.files.lock().unwrap();
+        if !files.contains_key(topic) {
+            let path = self.log_path(topic);
+            if path.exists() {
+                let mut reader = BufReader::new(File::open(&path)?);
+                //Where the last complete record ends
+                let mut good_len = 0;
+                loop {
+                    match bincode::deserialize_from::<_, (String, Vec<u8>)>(&mut reader) {
+                        Ok((key, payload)) => {
+                            self.memory.append_sync(topic, &key, vec![payload]);
+                            good_len = reader.stream_position()?;
+                        }
+                        //A write cut short by a crash leaves a partial record at the end
+                        Err(e) => match *e {
+                            bincode::ErrorKind::Io(ref io)
+                                if io.kind() == ErrorKind::UnexpectedEof =>
+                            {
+                                break
+                            }
+                            _ => return Err(LogError::from(e)),
+                        },
+                    }
+                }
+                //Cut the partial record off so new records don't land after it
+                let file = OpenOptions::new().write(true).open(&path)?;
+                if file.metadata()?.len() > good_len {
+                    file.set_len(good_len)?;
+                }
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            files.insert(String::from(topic), file);
+        }
+        f(files.get_mut(topic).unwrap())
+    }
+}
+
+#[async_trait]
+impl CommitLog for FileLog {
+    async fn append(
+        &self,
+        topic: &str,
+        key: &str,
+        payloads: Vec<Vec<u8>>,
+    ) -> Result<Vec<i64>, LogError> {
+        self.with_file(topic, |file| {
+            let mut writer = BufWriter::new(file);
+            for payload in &payloads {
+                bincode::serialize_into(&mut writer, &(key, payload))?;
+            }
+            writer.flush()?;
+            Ok(self.memory.append_sync(topic, key, payloads))
+        })
+    }
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group_id: &str,
+        start: StartAt,
+    ) -> Result<Box<dyn LogConsumer>, LogError> {
+        self.with_file(topic, |_| Ok(()))?;
+        let committed_path = self.committed_path(topic, group_id);
+        let start = match start {
+            StartAt::Committed => match std::fs::read_to_string(&committed_path) {
+                Ok(committed) => {
+                    let offset = committed
+                        .trim()
+                        .parse::<i64>()
+                        .map_err(|_| LogError::OffsetError(committed.clone()))?;
+                    StartAt::Offset(offset + 1)
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => StartAt::Offset(0),
+                Err(e) => return Err(LogError::from(e)),
+            },
+            StartAt::Offset(offset) => StartAt::Offset(offset),
+        };
+        Ok(Box::new(FileConsumer {
+            inner: self.memory.consume_sync(topic, group_id, start),
+            committed_path,
+        }))
+    }
+}
+
+pub struct FileConsumer {
+    inner: MemoryConsumer,
+    committed_path: PathBuf,
+}
+
+#[async_trait]
+impl LogConsumer for FileConsumer {
+    async fn next(&mut self) -> Option<Result<Record, LogError>> {
+        self.inner.next().await
+    }
+
+    async fn commit(&mut self, record: &Record) -> Result<(), LogError> {
+        std::fs::write(&self.committed_path, record.offset.to_string())?;
+        self.inner.commit(record).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_log() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let log = FileLog::open(dir.path()).unwrap();
+            let offsets = log
+                .append("changes", "file", vec![vec![1], vec![2, 2]])
+                .await
+                .unwrap();
+            assert_eq!(offsets, vec![0, 1]);
+            let mut consumer = log
+                .consume("changes", "group", StartAt::Committed)
+                .await
+                .unwrap();
+            let first = consumer.next().await.unwrap().unwrap();
+            consumer.commit(&first).await.unwrap();
+        }
+
+        //Reopening the directory keeps the records and the group's place
+        let log = FileLog::open(dir.path()).unwrap();
+        let offsets = log.append("changes", "file", vec![vec![3]]).await.unwrap();
+        assert_eq!(offsets, vec![2]);
+        let mut consumer = log
+            .consume("changes", "group", StartAt::Committed)
+            .await
+            .unwrap();
+        let second = consumer.next().await.unwrap().unwrap();
+        assert_eq!(second.offset, 1);
+        assert_eq!(second.payload, vec![2, 2]);
+        let mut from_start = log
+            .consume("changes", "other", StartAt::Offset(0))
+            .await
+            .unwrap();
+        assert_eq!(from_start.next().await.unwrap().unwrap().payload, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_write() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let log = FileLog::open(dir.path()).unwrap();
+            log.append("changes", "file", vec![vec![1], vec![2, 2]])
+                .await
+                .unwrap();
+        }
+        //Cut the last record short, as a crash partway through writing it would
+        let path = dir.path().join("changes.log");
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+
+        {
+            let log = FileLog::open(dir.path()).unwrap();
+            let offsets = log.append("changes", "file", vec![vec![3]]).await.unwrap();
+            assert_eq!(offsets, vec![1]);
+        }
+        //The record written after the partial one reads back on the next open
+        let log = FileLog::open(dir.path()).unwrap();
+        let mut consumer = log
+            .consume("changes", "group", StartAt::Offset(0))
+            .await
+            .unwrap();
+        assert_eq!(consumer.next().await.unwrap().unwrap().payload, vec![1]);
+        assert_eq!(consumer.next().await.unwrap().unwrap().payload, vec![3]);
+    }
+}
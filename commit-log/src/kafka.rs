@@ -0,0 +1,170 @@
+use crate::*;
+use futures::StreamExt;
+use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
+use rdkafka::consumer::stream_consumer::StreamConsumer;
+use rdkafka::consumer::{CommitMode, Consumer, DefaultConsumerContext};
+use rdkafka::message::{Message, OwnedMessage};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Offset, TopicPartitionList};
+use tokio::sync::mpsc;
+use tracing::*;
+
+//Kafka can take a while to come up alongside the services, so consumers hold off until then
+const STARTUP_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+
+pub struct KafkaLog {
+    brokers: String,
+    producer: FutureProducer,
+    created: std::time::Instant,
+}
+
+impl std::fmt::Debug for KafkaLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaLog")
+            .field("brokers", &self.brokers)
+            .finish()
+    }
+}
+
+impl KafkaLog {
+    pub fn new(brokers: &str) -> Result<KafkaLog, LogError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+        Ok(KafkaLog {
+            brokers: String::from(brokers),
+            producer,
+            created: std::time::Instant::now(),
+        })
+    }
+}
+
+fn to_record(m: &OwnedMessage) -> Result<Record, LogError> {
+    let partition = m.partition();
+    let offset = m.offset();
+    let payload = m
+        .payload()
+        .ok_or(LogError::PayloadError { partition, offset })?;
+    let key = m.key().ok_or(LogError::FileError { partition, offset })?;
+    Ok(Record {
+        key: String::from(std::str::from_utf8(key)?),
+        payload: payload.to_vec(),
+        partition,
+        offset,
+    })
+}
+
+#[async_trait]
+impl CommitLog for KafkaLog {
+    async fn append(
+        &self,
+        topic: &str,
+        key: &str,
+        payloads: Vec<Vec<u8>>,
+    ) -> Result<Vec<i64>, LogError> {
+        // All messages are sent one after the other without waiting for the results,
+        // then this waits until all delivery statuses have been received.
+        let futures = payloads
+            .iter()
+            .map(|payload| {
+                self.producer
+                    .send(FutureRecord::to(topic).payload(payload).key(key), 0)
+            })
+            .collect::<Vec<_>>();
+        let mut offsets = Vec::new();
+        for future in futures {
+            match future.await {
+                Ok(Ok((_, offset))) => offsets.push(offset),
+                Ok(Err((e, _))) => return Err(LogError::from(e)),
+                Err(_) => return Err(LogError::DeliveryCanceled),
+            }
+        }
+        debug!("Delivered {} messages for {}", offsets.len(), key);
+        Ok(offsets)
+    }
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group_id: &str,
+        start: StartAt,
+    ) -> Result<Box<dyn LogConsumer>, LogError> {
+        let ready = tokio::time::Instant::from_std(self.created + STARTUP_WAIT);
+        tokio::time::delay_until(ready).await;
+        let consumer: StreamConsumer<DefaultConsumerContext> = ClientConfig::new()
+            .set("group.id", group_id)
+            .set("bootstrap.servers", &self.brokers)
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "true")
+            .set_log_level(RDKafkaLogLevel::Debug)
+            .create()?;
+        match start {
+            StartAt::Committed => consumer.subscribe(&[topic])?,
+            StartAt::Offset(offset) => {
+                //Offsets are per partition, so start every partition there
+                let metadata =
+                    consumer.fetch_metadata(Some(topic), std::time::Duration::from_secs(10))?;
+                let mut assignment = TopicPartitionList::new();
+                for topic_metadata in metadata.topics() {
+                    for partition in topic_metadata.partitions() {
+                        assignment.add_partition_offset(
+                            topic,
+                            partition.id(),
+                            Offset::Offset(offset),
+                        );
+                    }
+                }
+                consumer.assign(&assignment)?;
+            }
+        }
+
+        // The message stream borrows the consumer, so it's read on its own task and handed over
+        // through a channel, while commits go straight to the consumer.
+        let consumer = Arc::new(consumer);
+        let (mut sender, receiver) = mpsc::channel(100);
+        let stream_consumer = consumer.clone();
+        tokio::spawn(async move {
+            let mut message_stream = stream_consumer.start();
+            while let Some(message) = message_stream.next().await {
+                let record = message
+                    .map_err(LogError::from)
+                    .and_then(|m| to_record(&m.detach()));
+                if sender.send(record).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Box::new(KafkaConsumer {
+            consumer,
+            topic: String::from(topic),
+            receiver,
+        }))
+    }
+}
+
+pub struct KafkaConsumer {
+    consumer: Arc<StreamConsumer<DefaultConsumerContext>>,
+    topic: String,
+    receiver: mpsc::Receiver<Result<Record, LogError>>,
+}
+
+#[async_trait]
+impl LogConsumer for KafkaConsumer {
+    async fn next(&mut self) -> Option<Result<Record, LogError>> {
+        self.receiver.recv().await
+    }
+
+    async fn commit(&mut self, record: &Record) -> Result<(), LogError> {
+        //Kafka's committed offset is the next one to read
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset(
+            &self.topic,
+            record.partition,
+            Offset::Offset(record.offset + 1),
+        );
+        self.consumer.commit(&offsets, CommitMode::Async)?;
+        Ok(())
+    }
+}
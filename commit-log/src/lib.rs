@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+mod file;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod memory;
+
+pub use file::FileLog;
+#[cfg(feature = "kafka")]
+pub use kafka::KafkaLog;
+pub use memory::MemoryLog;
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[cfg(feature = "kafka")]
+    #[error("Kafka error: {0}")]
+    KafkaError(#[from] rdkafka::error::KafkaError),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Bincode error: {0}")]
+    BincodeError(#[from] bincode::Error),
+    #[error("String error: {0}")]
+    StringError(#[from] std::str::Utf8Error),
+    #[error("Message from partition {partition} and offset {offset} has no payload")]
+    PayloadError { partition: i32, offset: i64 },
+    #[error("Message from partition {partition} and offset {offset} has no file key set")]
+    FileError { partition: i32, offset: i64 },
+    #[error("Message delivery was canceled")]
+    DeliveryCanceled,
+    #[error("Couldn't parse committed offset {0:?}")]
+    OffsetError(String),
+    #[error("Built without the kafka feature, so can't connect to brokers {0:?}")]
+    NoKafka(String),
+}
+
+///One message read back from the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub key: String,
+    pub payload: Vec<u8>,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+///Where a consumer starts reading a topic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartAt {
+    ///After the last offset the consumer group committed, or at the start of the topic for a new group
+    Committed,
+    ///At this offset, whatever the group has committed
+    Offset(i64),
+}
+
+///An ordered, keyed log of messages, like a Kafka topic.
+///Every service reads and writes changes through this, so they don't care where the log lives.
+#[async_trait]
+pub trait CommitLog: std::fmt::Debug + Send + Sync {
+    ///Appends the payloads to a topic in order, all with the same key, and returns each one's offset
+    async fn append(
+        &self,
+        topic: &str,
+        key: &str,
+        payloads: Vec<Vec<u8>>,
+    ) -> Result<Vec<i64>, LogError>;
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group_id: &str,
+        start: StartAt,
+    ) -> Result<Box<dyn LogConsumer>, LogError>;
+}
+
+#[async_trait]
+pub trait LogConsumer: Send {
+    ///Waits for the next record, returning None once the log has gone away
+    async fn next(&mut self) -> Option<Result<Record, LogError>>;
+
+    ///Marks the record as handled, so the consumer group restarts after it
+    async fn commit(&mut self, record: &Record) -> Result<(), LogError>;
+}
+
+#[cfg(feature = "kafka")]
+fn connect_kafka(brokers: &str) -> Result<Arc<dyn CommitLog>, LogError> {
+    Ok(Arc::new(KafkaLog::new(brokers)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn connect_kafka(brokers: &str) -> Result<Arc<dyn CommitLog>, LogError> {
+    Err(LogError::NoKafka(String::from(brokers)))
+}
+
+lazy_static! {
+    static ref SHARED_LOGS: Mutex<HashMap<String, Arc<dyn CommitLog>>> = Mutex::new(HashMap::new());
+}
+
+///Picks the log from the BROKER setting.
+///file:// followed by a directory keeps the log in local files, and memory://name keeps it in memory
+///for as long as the process runs.  Those two are shared by every caller with the same setting.
+///Anything else is a list of Kafka brokers.
+pub fn connect(brokers: &str) -> Result<Arc<dyn CommitLog>, LogError> {
+    if !brokers.starts_with("file://") && !brokers.starts_with("memory://") {
+        return connect_kafka(brokers);
+    }
+    let mut shared = SHARED_LOGS.lock().unwrap();
+    if let Some(log) = shared.get(brokers) {
+        return Ok(log.clone());
+    }
+    let log: Arc<dyn CommitLog> = if let Some(dir) = brokers.strip_prefix("file://") {
+        Arc::new(FileLog::open(dir)?)
+    } else {
+        Arc::new(MemoryLog::new())
+    };
+    shared.insert(String::from(brokers), log.clone());
+    Ok(log)
+}
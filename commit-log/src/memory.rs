@@ -0,0 +1,195 @@
+use crate::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+struct Topic {
+    records: Vec<Record>,
+    committed: HashMap<String, i64>,
+    //Broadcasts the number of records whenever one is appended
+    length: watch::Sender<i64>,
+    length_rcv: watch::Receiver<i64>,
+}
+
+impl Topic {
+    fn new() -> Topic {
+        let (length, length_rcv) = watch::channel(0);
+        Topic {
+            records: Vec::new(),
+            committed: HashMap::new(),
+            length,
+            length_rcv,
+        }
+    }
+}
+
+type Topics = Arc<Mutex<HashMap<String, Topic>>>;
+
+///A log that only lives as long as the process, for tests and running every service in one binary.
+///Clones share the same topics.
+#[derive(Clone, Default)]
+pub struct MemoryLog {
+    topics: Topics,
+}
+
+impl std::fmt::Debug for MemoryLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryLog").finish()
+    }
+}
+
+impl MemoryLog {
+    pub fn new() -> MemoryLog {
+        MemoryLog::default()
+    }
+
+    pub(crate) fn append_sync(&self, topic: &str, key: &str, payloads: Vec<Vec<u8>>) -> Vec<i64> {
+        let mut topics = self.topics.lock().unwrap();
+        let topic = topics
+            .entry(String::from(topic))
+            .or_insert_with(Topic::new);
+        let mut offsets = Vec::new();
+        for payload in payloads {
+            let offset = topic.records.len() as i64;
+            topic.records.push(Record {
+                key: String::from(key),
+                payload,
+                partition: 0,
+                offset,
+            });
+            offsets.push(offset);
+        }
+        //Nobody listening isn't an error, the receiver kept in the topic means this can't fail anyway
+        let _ = topic.length.broadcast(topic.records.len() as i64);
+        offsets
+    }
+
+    pub(crate) fn commit_sync(&self, topic: &str, group_id: &str, offset: i64) {
+        let mut topics = self.topics.lock().unwrap();
+        let topic = topics
+            .entry(String::from(topic))
+            .or_insert_with(Topic::new);
+        topic.committed.insert(String::from(group_id), offset);
+    }
+
+    pub(crate) fn consume_sync(&self, topic: &str, group_id: &str, start: StartAt) -> MemoryConsumer {
+        let mut topics = self.topics.lock().unwrap();
+        let state = topics
+            .entry(String::from(topic))
+            .or_insert_with(Topic::new);
+        let next = match start {
+            StartAt::Committed => state.committed.get(group_id).map_or(0, |offset| offset + 1),
+            StartAt::Offset(offset) => offset.max(0),
+        };
+        MemoryConsumer {
+            log: self.clone(),
+            topic: String::from(topic),
+            group_id: String::from(group_id),
+            next,
+            length: state.length_rcv.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl CommitLog for MemoryLog {
+    async fn append(
+        &self,
+        topic: &str,
+        key: &str,
+        payloads: Vec<Vec<u8>>,
+    ) -> Result<Vec<i64>, LogError> {
+        Ok(self.append_sync(topic, key, payloads))
+    }
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group_id: &str,
+        start: StartAt,
+    ) -> Result<Box<dyn LogConsumer>, LogError> {
+        Ok(Box::new(self.consume_sync(topic, group_id, start)))
+    }
+}
+
+pub struct MemoryConsumer {
+    log: MemoryLog,
+    topic: String,
+    group_id: String,
+    next: i64,
+    length: watch::Receiver<i64>,
+}
+
+impl MemoryConsumer {
+    fn try_next(&mut self) -> Option<Record> {
+        let topics = self.log.topics.lock().unwrap();
+        let record = topics
+            .get(&self.topic)?
+            .records
+            .get(self.next as usize)?
+            .clone();
+        self.next += 1;
+        Some(record)
+    }
+}
+
+#[async_trait]
+impl LogConsumer for MemoryConsumer {
+    async fn next(&mut self) -> Option<Result<Record, LogError>> {
+        loop {
+            if let Some(record) = self.try_next() {
+                return Some(Ok(record));
+            }
+            self.length.recv().await?;
+        }
+    }
+
+    async fn commit(&mut self, record: &Record) -> Result<(), LogError> {
+        self.log.commit_sync(&self.topic, &self.group_id, record.offset);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_log() {
+        let log = MemoryLog::new();
+        let offsets = log
+            .append("changes", "file", vec![vec![1], vec![2]])
+            .await
+            .unwrap();
+        assert_eq!(offsets, vec![0, 1]);
+
+        let mut consumer = log
+            .consume("changes", "group", StartAt::Committed)
+            .await
+            .unwrap();
+        let first = consumer.next().await.unwrap().unwrap();
+        assert_eq!(first.key, "file");
+        assert_eq!(first.payload, vec![1]);
+        consumer.commit(&first).await.unwrap();
+
+        //A new consumer in the same group picks up after the commit
+        let mut resumed = log
+            .consume("changes", "group", StartAt::Committed)
+            .await
+            .unwrap();
+        assert_eq!(resumed.next().await.unwrap().unwrap().offset, 1);
+
+        //It waits for records appended later
+        let waiting = tokio::spawn(async move { resumed.next().await.unwrap().unwrap() });
+        log.append("changes", "other", vec![vec![3]]).await.unwrap();
+        let third = waiting.await.unwrap();
+        assert_eq!(third.key, "other");
+        assert_eq!(third.offset, 2);
+
+        let mut from_offset = log
+            .consume("changes", "new group", StartAt::Offset(2))
+            .await
+            .unwrap();
+        assert_eq!(from_offset.next().await.unwrap().unwrap().payload, vec![3]);
+    }
+}
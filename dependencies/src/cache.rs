@@ -1,8 +1,8 @@
+use cache_store::history::{self, Version};
+use cache_store::{Store, StoreCommands};
 use indexmap::IndexSet;
 use tracing::*;
 use prost::Message;
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
 use thiserror::Error;
@@ -53,9 +53,11 @@ pub enum DepError {
     #[error("Prost decode error: {0}")]
     ProstDecodeError(#[from] prost::DecodeError),
     #[error("Redis error: {0:?}")]
-    DatabaseError(#[from] redis::RedisError),
+    DatabaseError(#[from] cache_store::StoreError),
     #[error("Bincode error: {0}")]
     BincodeError(#[from] bincode::Error),
+    #[error("References of {0} as of that offset are no longer kept")]
+    RefsTrimmed(String),
 }
 
 impl Into<tonic::Status> for DepError {
@@ -66,6 +68,7 @@ impl Into<tonic::Status> for DepError {
             | DepError::BincodeError(..)
             | DepError::ProstEncodeError(..)
             | DepError::ProstDecodeError(..) => tonic::Code::Internal,
+            DepError::RefsTrimmed(..) => tonic::Code::NotFound,
         };
         tonic::Status::new(code, msg)
     }
@@ -123,10 +126,76 @@ fn ref_id_subscribers(file: &str, ref_id: &RefID) -> String {
     format!("{}:{:?}:subs", file, ref_id)
 }
 
+///How many versions of each object's references are kept for reading older offsets and branches
+pub const REFS_HISTORY: isize = 100;
+
 fn obj_refs(file: &str, obj: &str) -> String {
+    format!("{}:{}:refs", file, obj)
+}
+
+//Before there was history, only the latest references were set here
+fn legacy_obj_refs(file: &str, obj: &str) -> String {
     format!("{}:{}:deps", file, obj)
 }
 
+fn branch_parent(file: &str) -> String {
+    format!("{}:branch", file)
+}
+
+async fn store_branch_parent(
+    conn: &dyn Store,
+    file: &str,
+    branch: &BranchMsg,
+) -> Result<(), DepError> {
+    debug!(
+        "Branch {} starts from {} at offset {}",
+        file, branch.parent, branch.offset
+    );
+    let mut serialized = Vec::new();
+    branch.encode(&mut serialized)?;
+    conn.set(branch_parent(file), serialized).await?;
+    Ok(())
+}
+
+async fn get_branch_parent(conn: &dyn Store, file: &str) -> Result<Option<BranchMsg>, DepError> {
+    let branch: Option<Vec<u8>> = conn.get(branch_parent(file)).await?;
+    match branch {
+        Some(bytes) => Ok(Some(BranchMsg::decode(bytes.as_ref())?)),
+        None => Ok(None),
+    }
+}
+
+fn tags(file: &str) -> String {
+    format!("{}:tags", file)
+}
+
+async fn update_tag(conn: &dyn Store, file: &str, tag: &TagMsg) -> Result<(), DepError> {
+    if tag.removed {
+        conn.hdel(tags(file), &tag.name).await?;
+    } else {
+        conn.hset(tags(file), &tag.name, tag.offset).await?;
+    }
+    Ok(())
+}
+
+//The offsets tagged in the file, along with those tagged in the files it branched from up to where it branched
+async fn get_tag_offsets(conn: &dyn Store, file: &str) -> Result<Vec<i64>, DepError> {
+    let mut offsets: Vec<i64> = conn.hvals(tags(file)).await?;
+    let mut file = String::from(file);
+    let mut before_or_equal = i64::MAX;
+    while let Some(branch) = get_branch_parent(conn, &file).await? {
+        before_or_equal = before_or_equal.min(branch.offset);
+        let inherited: Vec<i64> = conn.hvals(tags(&branch.parent)).await?;
+        offsets.extend(
+            inherited
+                .into_iter()
+                .filter(|offset| *offset <= before_or_equal),
+        );
+        file = branch.parent;
+    }
+    Ok(offsets)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Subscribers {
     offset: i64,
@@ -134,7 +203,7 @@ struct Subscribers {
 }
 
 async fn update_ref_id_subscribers(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     ref_id: &RefID,
     offset: i64,
@@ -152,23 +221,34 @@ async fn update_ref_id_subscribers(
     Ok(())
 }
 
+///Anything a branch hasn't changed by the given offset is read from its parent as of the offset the branch started at.
 async fn get_ref_id_subs(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     ref_id: &RefID,
     before_or_equal: i64,
 ) -> Result<HashSet<RefID>, DepError> {
     debug!("Getting subs for ref ID {:?} in file {}", ref_id, file);
-    let ref_id_subscribers = ref_id_subscribers(file, ref_id);
-    let cache_length: u64 = conn.llen(&ref_id_subscribers).await?;
-    for i in 0isize..cache_length as isize {
-        let raw_bytes: Vec<u8> = conn.lindex(&ref_id_subscribers, i).await?;
-        let entry: Subscribers = bincode::deserialize(&raw_bytes)?;
-        if entry.offset <= before_or_equal {
-            return Ok(entry.subs);
+    let mut file = String::from(file);
+    let mut before_or_equal = before_or_equal;
+    loop {
+        let ref_id_subscribers = ref_id_subscribers(&file, ref_id);
+        let cache_length: u64 = conn.llen(&ref_id_subscribers).await?;
+        for i in 0isize..cache_length as isize {
+            let raw_bytes: Vec<u8> = conn.lindex(&ref_id_subscribers, i).await?;
+            let entry: Subscribers = bincode::deserialize(&raw_bytes)?;
+            if entry.offset <= before_or_equal {
+                return Ok(entry.subs);
+            }
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                file = branch.parent;
+                before_or_equal = branch.offset;
+            }
+            None => return Ok(HashSet::new()),
         }
     }
-    Ok(HashSet::new())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -177,8 +257,13 @@ struct ObjRefs {
     serialized_refs: Vec<u8>,
 }
 
+fn refs_offset(raw_bytes: &[u8]) -> Result<i64, DepError> {
+    let entry: ObjRefs = bincode::deserialize(raw_bytes)?;
+    Ok(entry.offset)
+}
+
 async fn store_obj_refs(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj_id: &str,
     refs: &DependenciesMsg,
@@ -192,25 +277,47 @@ async fn store_obj_refs(
         offset,
         serialized_refs,
     };
-    conn.set(&obj_refs, bincode::serialize(&refs)?).await?;
-    Ok(())
+    let tag_offsets = get_tag_offsets(conn, file).await?;
+    history::push(
+        conn,
+        &obj_refs,
+        Some(&legacy_obj_refs(file, obj_id)),
+        bincode::serialize(&refs)?,
+        REFS_HISTORY,
+        &tag_offsets,
+        refs_offset,
+    )
+    .await
 }
 
 async fn get_obj_refs(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj_id: &str,
+    before_or_equal: i64,
 ) -> Result<Option<(i64, DependenciesMsg)>, DepError> {
     debug!("Getting refs for object {} from file {}", obj_id, file);
-    let obj_refs = obj_refs(file, obj_id);
-    let refs: Option<Vec<u8>> = conn.get(&obj_refs).await?;
-    match refs {
-        Some(raw_bytes) => {
-            let obj_refs: ObjRefs = bincode::deserialize(&raw_bytes)?;
-            let deserialized = DependenciesMsg::decode(obj_refs.serialized_refs.as_ref())?;
-            Ok(Some((obj_refs.offset, deserialized)))
+    let mut file = String::from(file);
+    let mut before_or_equal = before_or_equal;
+    loop {
+        let obj_refs = obj_refs(&file, obj_id);
+        let legacy = legacy_obj_refs(&file, obj_id);
+        match history::find(conn, &obj_refs, Some(&legacy), before_or_equal, refs_offset).await? {
+            Version::Found(raw_bytes) => {
+                let entry: ObjRefs = bincode::deserialize(&raw_bytes)?;
+                let deserialized = DependenciesMsg::decode(entry.serialized_refs.as_ref())?;
+                return Ok(Some((entry.offset, deserialized)));
+            }
+            Version::Trimmed => return Err(DepError::RefsTrimmed(String::from(obj_id))),
+            Version::Missing => (),
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                file = branch.parent;
+                before_or_equal = branch.offset;
+            }
+            None => return Ok(None),
         }
-        None => Ok(None),
     }
 }
 
@@ -253,7 +360,7 @@ fn get_ref_diffs(
 }
 
 async fn populate_changed_subs(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     refer: &ReferenceMsg,
@@ -292,7 +399,7 @@ async fn populate_changed_subs(
 }
 
 async fn add_deps(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj_id: &str,
     deps: &DependenciesMsg,
@@ -317,7 +424,7 @@ async fn add_deps(
 }
 
 async fn modify_deps(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj_id: &str,
     deps: &DependenciesMsg,
@@ -328,7 +435,7 @@ async fn modify_deps(
         deps, obj_id, file
     );
     let mut changed_subs = HashMap::new();
-    match get_obj_refs(conn, file, obj_id).await? {
+    match get_obj_refs(conn, file, obj_id, offset).await? {
         Some((_, old_deps)) => {
             let diffs = get_ref_diffs(&deps.references, &old_deps.references);
             for change_opt in diffs {
@@ -369,7 +476,7 @@ async fn modify_deps(
 }
 
 async fn delete_deps(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj_id: &str,
     deps: &DependenciesMsg,
@@ -410,7 +517,7 @@ async fn delete_deps(
 }
 
 async fn update_deps_inner(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     change: ChangeMsg,
@@ -428,18 +535,24 @@ async fn update_deps_inner(
                 }
             }
             change_msg::ChangeType::Delete(msg) => {
-                let prev_obj_refs = get_obj_refs(conn, file, &msg.id).await?;
+                let prev_obj_refs = get_obj_refs(conn, file, &msg.id, offset).await?;
                 if let Some((_, deps)) = prev_obj_refs {
                     delete_deps(conn, file, &msg.id, &deps, offset).await?;
                 }
             }
+            change_msg::ChangeType::Branch(branch) => {
+                store_branch_parent(conn, file, &branch).await?;
+            }
+            change_msg::ChangeType::Tag(tag) => {
+                update_tag(conn, file, &tag).await?;
+            }
         }
     }
     Ok(())
 }
 
 pub async fn update_deps(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     change: &[u8],
@@ -450,7 +563,7 @@ pub async fn update_deps(
 }
 
 async fn breadth_first_search(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     ref_id: RefID,
@@ -480,7 +593,7 @@ async fn breadth_first_search(
 }
 
 pub async fn get_all_deps(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     ref_ids: &Vec<RefIdMsg>,
@@ -497,3 +610,87 @@ pub async fn get_all_deps(
     }
     Ok(results)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs(id: &str) -> DependenciesMsg {
+        let id = RefIdMsg {
+            id: String::from(id),
+            ref_type: ref_id_msg::RefType::Existence as i32,
+            index: 0,
+        };
+        DependenciesMsg {
+            references: vec![OptionReferenceMsg {
+                reference: Some(ReferenceMsg {
+                    owner: Some(id.clone()),
+                    other: Some(id),
+                }),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refs_history() {
+        let conn = cache_store::MemoryStore::new();
+        let mut serialized_refs = Vec::new();
+        refs("legacy").encode(&mut serialized_refs).unwrap();
+        let legacy = ObjRefs {
+            offset: 1,
+            serialized_refs,
+        };
+        conn.set(
+            legacy_obj_refs("file", "obj"),
+            bincode::serialize(&legacy).unwrap(),
+        )
+        .await
+        .unwrap();
+        let (offset, found) = get_obj_refs(&conn, "file", "obj", i64::MAX)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(offset, 1);
+        assert_eq!(found, refs("legacy"));
+
+        for offset in 2..(REFS_HISTORY as i64 + 3) {
+            store_obj_refs(&conn, "file", "obj", &refs("new"), offset)
+                .await
+                .unwrap();
+        }
+        let length: isize = conn.llen(obj_refs("file", "obj")).await.unwrap();
+        assert_eq!(length, REFS_HISTORY);
+        let (offset, _) = get_obj_refs(&conn, "file", "obj", i64::MAX)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(offset, REFS_HISTORY as i64 + 2);
+        //The oldest refs were trimmed, so the legacy ones aren't what they were either
+        let trimmed = get_obj_refs(&conn, "file", "obj", 2).await;
+        assert!(matches!(trimmed, Err(DepError::RefsTrimmed(..))));
+    }
+
+    #[tokio::test]
+    async fn test_tagged_refs() {
+        let conn = cache_store::MemoryStore::new();
+        let tag = TagMsg {
+            name: String::from("Schematic design"),
+            offset: 3,
+            ..Default::default()
+        };
+        update_tag(&conn, "file", &tag).await.unwrap();
+        for offset in 2..(REFS_HISTORY as i64 + 10) {
+            store_obj_refs(&conn, "file", "obj", &refs(&offset.to_string()), offset)
+                .await
+                .unwrap();
+        }
+        let (offset, found) = get_obj_refs(&conn, "file", "obj", 3)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(offset, 3);
+        assert_eq!(found, refs("3"));
+        let untagged = get_obj_refs(&conn, "file", "obj", 4).await;
+        assert!(matches!(untagged, Err(DepError::RefsTrimmed(..))));
+    }
+}
@@ -0,0 +1,77 @@
+use cache_store::Store;
+use commit_log::{CommitLog, LogError, Record, StartAt};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::*;
+use tracing_futures::Instrument;
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("Commit log error: {0}")]
+    LogError(#[from] LogError),
+    #[error("Cache error: {0}")]
+    CacheError(#[from] crate::cache::DepError),
+}
+
+async fn handle_message(
+    redis_conn: &dyn Store,
+    record: &Record,
+) -> Result<(), UpdateError> {
+    crate::cache::update_deps(redis_conn, &record.key, record.offset, &record.payload)
+        .instrument(info_span!("update_deps"))
+        .await?;
+    Ok(())
+}
+
+async fn handle_stream(
+    redis_url: &str,
+    log: &dyn CommitLog,
+    group_id: &str,
+    topic: &str,
+) -> Result<(), UpdateError> {
+    let mut consumer = log.consume(topic, group_id, StartAt::Committed).await?;
+
+    while let Some(record) = consumer.next().await {
+        match crate::get_redis_conn(redis_url).await {
+            Ok(redis_conn) => match record {
+                Ok(record) => {
+                    if let Err(e) = handle_message(&redis_conn, &record)
+                        .instrument(info_span!("handle_message"))
+                        .await
+                    {
+                        let span = info_span!("handle_message error");
+                        let _enter = span.enter();
+                        error!("{}", e);
+                    }
+                    let span = info_span!("commit_message");
+                    let _enter = span.enter();
+                    if let Err(e) = consumer.commit(&record).await {
+                        error!("{}", e);
+                    }
+                }
+                Err(e) => {
+                    let span = info_span!("log message error");
+                    let _enter = span.enter();
+                    error!("{}", e);
+                }
+            },
+            Err(e) => {
+                let span = info_span!("redis connect error");
+                let _enter = span.enter();
+                error!("{}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn update_cache(
+    redis_url: String,
+    log: Arc<dyn CommitLog>,
+    group_id: String,
+    topic: String,
+) {
+    if let Err(e) = handle_stream(&redis_url, log.as_ref(), &group_id, &topic).await {
+        error!("{}", e);
+    }
+}
@@ -0,0 +1,69 @@
+use cache_store::Store;
+use commit_log::CommitLog;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use trace_lib::*;
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod cache;
+mod consume;
+#[cfg(test)]
+mod tests;
+use cache::*;
+use consume::*;
+
+mod dependencies {
+    tonic::include_proto!("dependencies");
+}
+
+use dependencies::*;
+
+#[instrument]
+async fn get_redis_conn(url: &str) -> Result<Arc<dyn Store>, tonic::Status> {
+    cache_store::connect(url)
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("{:?}", e)))
+}
+
+#[derive(Debug)]
+struct DepsService {
+    redis_url: String,
+}
+
+#[tonic::async_trait]
+impl dependencies_server::Dependencies for DepsService {
+    #[instrument]
+    async fn get_all_dependencies(
+        &self,
+        request: Request<GetAllDependenciesInput>,
+    ) -> Result<Response<GetAllDependenciesOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        info!("Get all dependencies: {:?}", msg);
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let references = cache::get_all_deps(&redis_conn, &msg.file, msg.offset, &msg.ids)
+            .instrument(info_span!("get_all_deps"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetAllDependenciesOutput { references }))
+    }
+}
+
+///Serves the dependency graph while keeping it up to date from the commit log
+pub async fn run(
+    addr: SocketAddr,
+    redis_url: String,
+    log: Arc<dyn CommitLog>,
+    group: String,
+    topic: String,
+) -> Result<(), tonic::transport::Error> {
+    tokio::spawn(update_cache(redis_url.clone(), log, group, topic));
+
+    let svc = dependencies_server::DependenciesServer::new(DepsService { redis_url });
+
+    info!("Running on {:?}", addr);
+    Server::builder().add_service(svc).serve(addr).await
+}
@@ -4,7 +4,6 @@
 use super::*;
 use change_msg::ChangeType;
 use prost::Message;
-use redis::aio::MultiplexedConnection;
 use ref_id_msg::RefType;
 use std::collections::HashSet;
 use uuid::Uuid;
@@ -22,21 +21,19 @@ macro_rules! set {
         };
     }
 
-async fn test_get_conn() -> MultiplexedConnection {
+async fn test_get_conn() -> Arc<dyn Store> {
     let _ = env_logger::Builder::new()
         .filter_module("dependencies", log::LevelFilter::Trace)
         .is_test(true)
         .try_init();
+    //Runs against a real Redis when REDIS_URL is set
     let env_opt = std::env::var("REDIS_URL");
     let redis_url = if let Ok(url) = env_opt {
         url
     } else {
-        String::from("redis://127.0.0.1:6379")
+        String::from("memory://tests")
     };
-    let client = redis::Client::open(redis_url).unwrap();
-    let (conn, fut) = client.get_multiplexed_async_connection().await.unwrap();
-    tokio::spawn(fut);
-    conn
+    cache_store::connect(&redis_url).await.unwrap()
 }
 
 fn ref_id_msg(id: String, ref_type: RefType, index: u64) -> RefIdMsg {
@@ -108,6 +105,17 @@ fn delete_change_msg(id: String) -> Vec<u8> {
     bytes
 }
 
+fn branch_change_msg(parent: String, offset: i64) -> Vec<u8> {
+    let msg = ChangeMsg {
+        user: "Doesn't matter".to_string(),
+        change_type: Some(ChangeType::Branch(BranchMsg { parent, offset })),
+        change_source: None,
+    };
+    let mut bytes = Vec::new();
+    msg.encode(&mut bytes).unwrap();
+    bytes
+}
+
 fn set_exists_within_range(
     mut set: HashSet<Vec<u8>>,
     base: &Vec<ReferenceMsg>,
@@ -139,7 +147,7 @@ fn equals(input: Vec<ReferenceMsg>, answers: Vec<HashSet<Vec<u8>>>) -> bool {
 #[tokio_macros::test]
 async fn test_deps_simple() {
     let file = Uuid::new_v4().to_string();
-    let mut conn = test_get_conn().await;
+    let conn = test_get_conn().await;
 
     let obj_0_id = Uuid::new_v4().to_string();
     let obj_0_pt_0 = ref_id_msg(obj_0_id.clone(), RefType::ProfilePoint, 0);
@@ -162,11 +170,11 @@ async fn test_deps_simple() {
         ],
     );
 
-    update_deps(&mut conn, &file, 0, &obj_0).await.unwrap();
+    update_deps(&conn, &file, 0, &obj_0).await.unwrap();
 
-    update_deps(&mut conn, &file, 1, &obj_1).await.unwrap();
+    update_deps(&conn, &file, 1, &obj_1).await.unwrap();
 
-    let results = get_all_deps(&mut conn, &file, 1, &vec![obj_0_pt_1.clone()])
+    let results = get_all_deps(&conn, &file, 1, &vec![obj_0_pt_1.clone()])
         .await
         .unwrap();
 
@@ -175,7 +183,7 @@ async fn test_deps_simple() {
         vec![set![ref_msg_bin(&obj_1_pt_0, &obj_0_pt_1)]]
     ));
 
-    let results = get_all_deps(&mut conn, &file, 1, &vec![obj_0_pt_0.clone()])
+    let results = get_all_deps(&conn, &file, 1, &vec![obj_0_pt_0.clone()])
         .await
         .unwrap();
 
@@ -191,7 +199,7 @@ async fn test_deps_simple() {
 #[tokio_macros::test]
 async fn test_deps_versioned() {
     let file = Uuid::new_v4().to_string();
-    let mut conn = test_get_conn().await;
+    let conn = test_get_conn().await;
 
     let obj_0_id = Uuid::new_v4().to_string();
     let obj_0_pt_0 = ref_id_msg(obj_0_id.clone(), RefType::ProfilePoint, 0);
@@ -212,8 +220,8 @@ async fn test_deps_versioned() {
         vec![opt_ref_msg(&obj_1_pt_1, &obj_0_pt_0)],
     );
 
-    update_deps(&mut conn, &file, 0, &obj_0).await.unwrap();
-    update_deps(&mut conn, &file, 1, &obj_1).await.unwrap();
+    update_deps(&conn, &file, 0, &obj_0).await.unwrap();
+    update_deps(&conn, &file, 1, &obj_1).await.unwrap();
     let obj_1 = modify_change_msg(
         obj_1_id.clone(),
         vec![
@@ -221,9 +229,9 @@ async fn test_deps_versioned() {
             opt_ref_msg(&obj_1_pt_0, &obj_1_pt_1),
         ],
     );
-    update_deps(&mut conn, &file, 2, &obj_1).await.unwrap();
+    update_deps(&conn, &file, 2, &obj_1).await.unwrap();
 
-    let results = get_all_deps(&mut conn, &file, 2, &vec![obj_0_pt_0.clone()])
+    let results = get_all_deps(&conn, &file, 2, &vec![obj_0_pt_0.clone()])
         .await
         .unwrap();
     trace!("results: {:#?}", results);
@@ -237,14 +245,14 @@ async fn test_deps_versioned() {
     ));
 
     let obj_0 = delete_change_msg(obj_0_id.clone());
-    update_deps(&mut conn, &file, 3, &obj_0).await.unwrap();
+    update_deps(&conn, &file, 3, &obj_0).await.unwrap();
 
-    let results = get_all_deps(&mut conn, &file, 3, &vec![obj_0_pt_0.clone()])
+    let results = get_all_deps(&conn, &file, 3, &vec![obj_0_pt_0.clone()])
         .await
         .unwrap();
     assert!(equals(results, vec![]));
 
-    let results = get_all_deps(&mut conn, &file, 3, &vec![obj_1_pt_1.clone()])
+    let results = get_all_deps(&conn, &file, 3, &vec![obj_1_pt_1.clone()])
         .await
         .unwrap();
     assert!(equals(
@@ -253,10 +261,68 @@ async fn test_deps_versioned() {
     ));
 }
 
+#[tokio_macros::test]
+async fn test_deps_branch() {
+    let file = Uuid::new_v4().to_string();
+    let branch = format!("{}@option", file);
+    let conn = test_get_conn().await;
+
+    let obj_0_id = Uuid::new_v4().to_string();
+    let obj_0_pt_0 = ref_id_msg(obj_0_id.clone(), RefType::ProfilePoint, 0);
+    let obj_1_id = Uuid::new_v4().to_string();
+    let obj_1_pt_1 = ref_id_msg(obj_1_id.clone(), RefType::ProfilePoint, 1);
+
+    let obj_0 = add_change_msg(obj_0_id.clone(), vec![]);
+    let obj_1 = add_change_msg(
+        obj_1_id.clone(),
+        vec![opt_ref_msg(&obj_1_pt_1, &obj_0_pt_0)],
+    );
+    update_deps(&conn, &file, 0, &obj_0).await.unwrap();
+    update_deps(&conn, &file, 1, &obj_1).await.unwrap();
+    update_deps(&conn, &branch, 0, &branch_change_msg(file.clone(), 1))
+        .await
+        .unwrap();
+
+    //The main line moves on after the branch was taken
+    let obj_1 = modify_change_msg(
+        obj_1_id.clone(),
+        vec![OptionReferenceMsg { reference: None }],
+    );
+    update_deps(&conn, &file, 2, &obj_1).await.unwrap();
+    let results = get_all_deps(&conn, &file, 2, &vec![obj_0_pt_0.clone()])
+        .await
+        .unwrap();
+    assert!(equals(results, vec![]));
+
+    //The branch still sees the main line as of offset 1
+    let results = get_all_deps(&conn, &branch, 0, &vec![obj_0_pt_0.clone()])
+        .await
+        .unwrap();
+    assert!(equals(
+        results,
+        vec![set![ref_msg_bin(&obj_1_pt_1, &obj_0_pt_0)]]
+    ));
+
+    //Changes on the branch don't show up on the main line
+    let obj_1 = delete_change_msg(obj_1_id.clone());
+    update_deps(&conn, &branch, 1, &obj_1).await.unwrap();
+    let results = get_all_deps(&conn, &branch, 1, &vec![obj_0_pt_0.clone()])
+        .await
+        .unwrap();
+    assert!(equals(results, vec![]));
+    let results = get_all_deps(&conn, &file, 1, &vec![obj_0_pt_0.clone()])
+        .await
+        .unwrap();
+    assert!(equals(
+        results,
+        vec![set![ref_msg_bin(&obj_1_pt_1, &obj_0_pt_0)]]
+    ));
+}
+
 #[tokio_macros::test]
 async fn test_get_all_deps() {
     let file = Uuid::new_v4().to_string();
-    let mut conn = test_get_conn().await;
+    let conn = test_get_conn().await;
 
     let wall_0_id = Uuid::new_v4().to_string();
     let wall_0_pt_1 = ref_id_msg(wall_0_id.clone(), RefType::ProfilePoint, 1);
@@ -319,13 +385,13 @@ async fn test_get_all_deps() {
             opt_ref_msg(&window_pt_1, &wall_1_line_0),
         ],
     );
-    update_deps(&mut conn, &file, 0, &wall_0).await.unwrap();
-    update_deps(&mut conn, &file, 1, &wall_1).await.unwrap();
-    update_deps(&mut conn, &file, 2, &dim_0).await.unwrap();
-    update_deps(&mut conn, &file, 3, &dim_1).await.unwrap();
-    update_deps(&mut conn, &file, 4, &window).await.unwrap();
+    update_deps(&conn, &file, 0, &wall_0).await.unwrap();
+    update_deps(&conn, &file, 1, &wall_1).await.unwrap();
+    update_deps(&conn, &file, 2, &dim_0).await.unwrap();
+    update_deps(&conn, &file, 3, &dim_1).await.unwrap();
+    update_deps(&conn, &file, 4, &window).await.unwrap();
 
-    let results = get_all_deps(&mut conn, &file, 4, &vec![wall_0_pt_1.clone()])
+    let results = get_all_deps(&conn, &file, 4, &vec![wall_0_pt_1.clone()])
         .await
         .unwrap();
     log::info!("Got results: {:#?}", results);
@@ -347,7 +413,7 @@ async fn test_get_all_deps() {
         ]
     ));
 
-    let results = get_all_deps(&mut conn, &file, 4, &vec![window_pt_0.clone()])
+    let results = get_all_deps(&conn, &file, 4, &vec![window_pt_0.clone()])
         .await
         .unwrap();
     log::info!("Got results: {:#?}", results);
@@ -359,7 +425,7 @@ async fn test_get_all_deps() {
         ]
     ));
 
-    let results = get_all_deps(&mut conn, &file, 0, &vec![window_pt_0.clone()])
+    let results = get_all_deps(&conn, &file, 0, &vec![window_pt_0.clone()])
         .await
         .unwrap();
     log::info!("Got results: {:#?}", results);
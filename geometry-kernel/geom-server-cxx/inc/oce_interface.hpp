@@ -1,7 +1,13 @@
+#include <cstdint>
 #include <vector>
 #include "gp_Pnt.hxx"
 
 namespace oce_interface
 {
-void make_prism(gp_Pnt first_pt, gp_Pnt second_pt, double width, double height, std::vector<double> &outPositions, std::vector<uint64_t> &outIndices);
+//Material indices, matching SIDE_MATERIAL and CAP_MATERIAL in obj-traits
+const uint32_t SIDE_MATERIAL = 0;
+const uint32_t CAP_MATERIAL = 1;
+
+void make_prism(gp_Pnt first_pt, gp_Pnt second_pt, double width, double height, std::vector<double> &outPositions, std::vector<uint64_t> &outIndices,
+                std::vector<double> &outNormals, std::vector<double> &outUVs, std::vector<uint32_t> &outMaterials);
 }
\ No newline at end of file
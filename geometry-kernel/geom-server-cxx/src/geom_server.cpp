@@ -14,6 +14,8 @@
 #include "geom_kernel.grpc.pb.h"
 #include "oce_interface.hpp"
 
+#include <Standard_Failure.hxx>
+
 using grpc::Server;
 using grpc::ServerBuilder;
 using grpc::ServerContext;
@@ -32,19 +34,38 @@ gp_Pnt GetPoint(const Point3Msg &pt)
 	return result;
 }
 
-void handle_eptr(std::exception_ptr eptr) // passing by value is ok
+//Fills in the prism, returning why it couldn't be made if it fails
+std::string BuildPrism(const MakePrismInput &request, MakePrismOutput *response)
 {
+	gp_Pnt firstPt = GetPoint(request.firstpt());
+	gp_Pnt secondPt = GetPoint(request.secondpt());
+	std::vector<double> positions;
+	std::vector<uint64_t> indices;
+	std::vector<double> normals;
+	std::vector<double> uvs;
+	std::vector<uint32_t> materials;
 	try
 	{
-		if (eptr)
-		{
-			std::rethrow_exception(eptr);
-		}
+		oce_interface::make_prism(firstPt, secondPt, request.width(), request.height(), positions, indices, normals, uvs, materials);
+	}
+	catch (const Standard_Failure &e)
+	{
+		return std::string("Couldn't make prism: ") + e.GetMessageString();
 	}
 	catch (const std::exception &e)
 	{
-		std::cout << "Caught exception \"" << e.what() << "\"\n";
+		return std::string("Couldn't make prism: ") + e.what();
 	}
+	catch (...)
+	{
+		return "Couldn't make prism";
+	}
+	*response->mutable_positions() = {positions.begin(), positions.end()};
+	*response->mutable_indices() = {indices.begin(), indices.end()};
+	*response->mutable_normals() = {normals.begin(), normals.end()};
+	*response->mutable_uvs() = {uvs.begin(), uvs.end()};
+	*response->mutable_material_indices() = {materials.begin(), materials.end()};
+	return "";
 }
 
 class GeomKernelImpl final : public GeometryKernel::Service
@@ -57,23 +78,40 @@ public:
 		Status result(StatusCode::UNKNOWN, "default");
 		if (request != nullptr && response != nullptr)
 		{
-			gp_Pnt firstPt = GetPoint(request->firstpt());
-			gp_Pnt secondPt = GetPoint(request->secondpt());
-			double width = request->width();
-			double height = request->height();
-			std::vector<double> positions;
-			std::vector<uint64_t> indices;
-			try
+			std::string error = BuildPrism(*request, response);
+			if (error.empty())
+			{
+				result = Status::OK;
+			}
+			else
 			{
-				oce_interface::make_prism(firstPt, secondPt, width, height, positions, indices);
+				std::cout << error << std::endl;
+				result = Status(StatusCode::INTERNAL, error);
 			}
-			catch (...)
+		}
+		else
+		{
+			std::cout << "Invalid args" << std::endl;
+			result = Status(StatusCode::INVALID_ARGUMENT, "args were null");
+		}
+		return result;
+	}
+
+	Status MakePrisms(ServerContext *context, const MakePrismsInput *request, MakePrismsOutput *response) override
+	{
+		Status result(StatusCode::UNKNOWN, "default");
+		if (request != nullptr && response != nullptr)
+		{
+			for (const MakePrismInput &prism : request->prisms())
 			{
-				std::exception_ptr p = std::current_exception();
-				handle_eptr(p);
+				MakePrismResultMsg *entry = response->add_results();
+				std::string error = BuildPrism(prism, entry->mutable_prism());
+				if (!error.empty())
+				{
+					std::cout << error << std::endl;
+					entry->set_error(error);
+				}
 			}
-			*response->mutable_positions() = {positions.begin(), positions.end()};
-			*response->mutable_indices() = {indices.begin(), indices.end()};
 			result = Status::OK;
 		}
 		else
@@ -1,3 +1,4 @@
+#include <cmath>
 #include <iostream>
 #include "oce_interface.hpp"
 #include "gp_Pnt.hxx"
@@ -26,7 +27,45 @@ void pushPt(std::vector<double> &outPositions, const gp_Pnt &pt)
     outPositions.push_back(pt.Z());
 }
 
-void oce_interface::make_prism(gp_Pnt gp_first, gp_Pnt gp_second, double width, double height, std::vector<double> &outPositions, std::vector<uint64_t> &outIndices)
+//Faces pointing mostly up or down are the top and bottom.  Their UVs are x and y,
+//and the sides' run along the face horizontally and up it with z, the same as the Rust LocalKernel.
+void pushTriangle(const gp_Pnt &first, const gp_Pnt &second, const gp_Pnt &third, uint64_t &curIndex, std::vector<double> &outPositions,
+                  std::vector<uint64_t> &outIndices, std::vector<double> &outNormals, std::vector<double> &outUVs, std::vector<uint32_t> &outMaterials)
+{
+    gp_Vec normal = gp_Vec(first, second).Crossed(gp_Vec(first, third)).Normalized();
+    bool isCap = std::abs(normal.Z()) > 0.5;
+    for (const gp_Pnt *pt : {&first, &second, &third})
+    {
+        pushPt(outPositions, *pt);
+        outIndices.push_back(curIndex++);
+        outNormals.push_back(normal.X());
+        outNormals.push_back(normal.Y());
+        outNormals.push_back(normal.Z());
+    }
+    if (isCap)
+    {
+        //Caps are planar in X/Y, so those are the UVs
+        for (const gp_Pnt *pt : {&first, &second, &third})
+        {
+            outUVs.push_back(pt->X());
+            outUVs.push_back(pt->Y());
+        }
+    }
+    else
+    {
+        //Only side faces have a horizontal direction to run U along
+        gp_Vec along = gp_Vec(-normal.Y(), normal.X(), 0.0).Normalized();
+        for (const gp_Pnt *pt : {&first, &second, &third})
+        {
+            outUVs.push_back(gp_Vec(pt->XYZ()).Dot(along));
+            outUVs.push_back(pt->Z());
+        }
+    }
+    outMaterials.push_back(isCap ? oce_interface::CAP_MATERIAL : oce_interface::SIDE_MATERIAL);
+}
+
+void oce_interface::make_prism(gp_Pnt gp_first, gp_Pnt gp_second, double width, double height, std::vector<double> &outPositions, std::vector<uint64_t> &outIndices,
+                               std::vector<double> &outNormals, std::vector<double> &outUVs, std::vector<uint32_t> &outMaterials)
 {
     std::cout << "Make prism" << std::endl;
     gp_Vec dir(gp_first, gp_second);
@@ -43,6 +82,9 @@ void oce_interface::make_prism(gp_Pnt gp_first, gp_Pnt gp_second, double width,
 
     outPositions.clear();
     outIndices.clear();
+    outNormals.clear();
+    outUVs.clear();
+    outMaterials.clear();
     TopExp_Explorer aExpFace;
     uint64_t curIndex = 0;
     for (aExpFace.Init(prism, TopAbs_FACE); aExpFace.More(); aExpFace.Next())
@@ -69,21 +111,11 @@ void oce_interface::make_prism(gp_Pnt gp_first, gp_Pnt gp_second, double width,
                 gp_Pnt aPnt3 = aNodes(third);
                 if (faceOrientation == TopAbs_Orientation::TopAbs_FORWARD)
                 {
-                    pushPt(outPositions, aPnt1);
-                    outIndices.push_back(curIndex++);
-                    pushPt(outPositions, aPnt2);
-                    outIndices.push_back(curIndex++);
-                    pushPt(outPositions, aPnt3);
-                    outIndices.push_back(curIndex++);
+                    pushTriangle(aPnt1, aPnt2, aPnt3, curIndex, outPositions, outIndices, outNormals, outUVs, outMaterials);
                 }
                 else
                 {
-                    pushPt(outPositions, aPnt3);
-                    outIndices.push_back(curIndex++);
-                    pushPt(outPositions, aPnt2);
-                    outIndices.push_back(curIndex++);
-                    pushPt(outPositions, aPnt1);
-                    outIndices.push_back(curIndex++);
+                    pushTriangle(aPnt3, aPnt2, aPnt1, curIndex, outPositions, outIndices, outNormals, outUVs, outMaterials);
                 }
             }
         }
@@ -10,7 +10,13 @@ TEST_CASE("Make prism")
     double height = 1;
     std::vector<double> outPos;
     std::vector<uint64_t> outIndices;
-    oce_interface::make_prism(first, second, width, height, outPos, outIndices);
+    std::vector<double> outNormals;
+    std::vector<double> outUVs;
+    std::vector<uint32_t> outMaterials;
+    oce_interface::make_prism(first, second, width, height, outPos, outIndices, outNormals, outUVs, outMaterials);
 	REQUIRE(outPos.size() > 0);
 	REQUIRE(outIndices.size() > 0);
+	REQUIRE(outNormals.size() == outPos.size());
+	REQUIRE(outUVs.size() == outIndices.size() * 2);
+	REQUIRE(outMaterials.size() == outIndices.size() / 3);
 }
\ No newline at end of file
@@ -2,11 +2,12 @@
 //! X is configurable.  
 
 use async_stream::try_stream;
+use cache_store::{Store, StoreCommands};
 use futures::stream::Stream;
 use prost::Message;
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
 use thiserror::Error;
 use tracing::*;
 
@@ -29,12 +30,16 @@ pub use objects::*;
 pub enum ObjError {
     #[error("Object {0} not found")]
     ObjNotFound(String),
+    #[error("File {0} not found")]
+    FileNotFound(String),
+    #[error("{0} and {1} don't share any history")]
+    UnrelatedFiles(String, String),
     #[error("Prost encode error: {0}")]
     ProstEncodeError(#[from] prost::EncodeError),
     #[error("Prost decode error: {0}")]
     ProstDecodeError(#[from] prost::DecodeError),
     #[error("Redis error: {0:?}")]
-    DatabaseError(#[from] redis::RedisError),
+    DatabaseError(#[from] cache_store::StoreError),
     #[error("Bincode error: {0}")]
     BincodeError(#[from] bincode::Error),
 }
@@ -47,7 +52,8 @@ impl Into<tonic::Status> for ObjError {
             | ObjError::BincodeError(..)
             | ObjError::ProstEncodeError(..)
             | ObjError::ProstDecodeError(..) => tonic::Code::Internal,
-            ObjError::ObjNotFound(..) => tonic::Code::NotFound,
+            ObjError::ObjNotFound(..) | ObjError::FileNotFound(..) => tonic::Code::NotFound,
+            ObjError::UnrelatedFiles(..) => tonic::Code::FailedPrecondition,
         };
         tonic::Status::new(code, msg)
     }
@@ -70,6 +76,19 @@ fn latest_obj_list(file: &str) -> String {
     format!("{}:obj_list", file)
 }
 
+//Every object that has ever been added, so a branch can work out what was alive at the offset it starts from
+fn all_obj_list(file: &str) -> String {
+    format!("{}:all_objs", file)
+}
+
+fn branch_parent(file: &str) -> String {
+    format!("{}:branch", file)
+}
+
+fn tags(file: &str) -> String {
+    format!("{}:tags", file)
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct ObjEntry {
     offset: i64,
@@ -77,7 +96,7 @@ struct ObjEntry {
 }
 
 async fn store_object_change(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     key: &str,
@@ -99,7 +118,7 @@ async fn store_object_change(
 }
 
 async fn store_file_offset(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
 ) -> Result<(), ObjError> {
@@ -110,7 +129,7 @@ async fn store_file_offset(
 }
 
 async fn update_latest_obj_list(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     obj: &ChangeMsg,
 ) -> Result<(), ObjError> {
@@ -118,17 +137,21 @@ async fn update_latest_obj_list(
     match &obj.change_type {
         Some(change_msg::ChangeType::Add(object)) => {
             conn.sadd(latest_list, &object.id).await?;
+            conn.sadd(all_obj_list(file), &object.id).await?;
         }
         Some(change_msg::ChangeType::Delete(msg)) => {
             conn.srem(latest_list, &msg.id).await?;
         }
-        Some(change_msg::ChangeType::Modify(..)) | None => (),
+        Some(change_msg::ChangeType::Modify(..))
+        | Some(change_msg::ChangeType::Branch(..))
+        | Some(change_msg::ChangeType::Tag(..))
+        | None => (),
     }
     Ok(())
 }
 
 pub fn get_latest_obj_list(
-    mut conn: MultiplexedConnection,
+    conn: Arc<dyn Store>,
     file: String,
 ) -> impl Stream<Item = Result<String, ObjError>> {
     try_stream! {
@@ -140,24 +163,26 @@ pub fn get_latest_obj_list(
     }
 }
 
-async fn get_object(
-    conn: &mut MultiplexedConnection,
+async fn get_branch_parent(
+    conn: &dyn Store,
+    file: &str,
+) -> Result<Option<BranchMsg>, ObjError> {
+    let branch: Option<Vec<u8>> = conn.get(branch_parent(file)).await?;
+    match branch {
+        Some(bytes) => Ok(Some(BranchMsg::decode(bytes.as_ref())?)),
+        None => Ok(None),
+    }
+}
+
+async fn get_object_in_file(
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     key: &str,
-) -> Result<Vec<u8>, ObjError> {
-    trace!(
-        "getting object {:?} in file {:?} at offset {}",
-        key,
-        file,
-        offset
-    );
+) -> Result<Option<Vec<u8>>, ObjError> {
     let obj_cache = obj_cache(file, key);
     let cache_length: isize = conn.llen(&obj_cache).await?;
     debug!("Cache length: {:?}", cache_length);
-    if cache_length == 0 {
-        return Err(ObjError::ObjNotFound(String::from(key)));
-    }
     for i in 0isize..cache_length {
         let serialized: Vec<u8> = conn.lindex(&obj_cache, i).await?;
         let entry: ObjEntry = bincode::deserialize(&serialized)?;
@@ -167,14 +192,128 @@ async fn get_object(
             offset
         );
         if entry.offset <= offset {
-            return Ok(entry.object);
+            return Ok(Some(entry.object));
+        }
+    }
+    Ok(None)
+}
+
+///If the object hasn't changed on a branch by the given offset, it's read from the branch's parent as of the offset the branch started at.
+async fn get_object(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+    key: &str,
+) -> Result<Vec<u8>, ObjError> {
+    trace!(
+        "getting object {:?} in file {:?} at offset {}",
+        key,
+        file,
+        offset
+    );
+    let mut file = String::from(file);
+    let mut offset = offset;
+    loop {
+        if let Some(object) = get_object_in_file(conn, &file, offset, key).await? {
+            return Ok(object);
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                trace!(
+                    "Falling back to parent {:?} at offset {}",
+                    branch.parent,
+                    branch.offset
+                );
+                file = branch.parent;
+                offset = branch.offset;
+            }
+            None => return Err(ObjError::ObjNotFound(String::from(key))),
+        }
+    }
+}
+
+fn is_live(change: &ChangeMsg) -> bool {
+    match change.change_type {
+        Some(change_msg::ChangeType::Add(..)) | Some(change_msg::ChangeType::Modify(..)) => true,
+        Some(change_msg::ChangeType::Delete(..))
+        | Some(change_msg::ChangeType::Branch(..))
+        | Some(change_msg::ChangeType::Tag(..))
+        | None => false,
+    }
+}
+
+//Every object alive in the file at the offset
+async fn get_live_objects(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+) -> Result<Vec<String>, ObjError> {
+    let mut candidates: HashSet<String> = conn.smembers(all_obj_list(file)).await?;
+    let latest: HashSet<String> = conn.smembers(latest_obj_list(file)).await?;
+    candidates.extend(latest);
+    let mut live = Vec::new();
+    for id in candidates {
+        match get_object(conn, file, offset, &id).await {
+            Ok(bytes) => {
+                if is_live(&ChangeMsg::decode(bytes.as_ref())?) {
+                    live.push(id);
+                }
+            }
+            Err(ObjError::ObjNotFound(..)) => (),
+            Err(e) => return Err(e),
         }
     }
-    Err(ObjError::ObjNotFound(String::from(key)))
+    live.sort();
+    Ok(live)
+}
+
+//Seeds the branch's object lists with everything alive in the parent at the offset the branch starts from
+async fn create_branch(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+    branch: &BranchMsg,
+) -> Result<(), ObjError> {
+    info!(
+        "Creating branch {:?} from {:?} at offset {}",
+        file, branch.parent, branch.offset
+    );
+    let live = get_live_objects(conn, &branch.parent, branch.offset).await?;
+    if !live.is_empty() {
+        conn.sadd(latest_obj_list(file), live.clone()).await?;
+        conn.sadd(all_obj_list(file), live).await?;
+    }
+    let mut serialized = Vec::new();
+    branch.encode(&mut serialized)?;
+    conn.set(branch_parent(file), serialized).await?;
+    store_file_offset(conn, file, offset).await?;
+    Ok(())
+}
+
+async fn update_tag(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+    tag: &TagMsg,
+) -> Result<(), ObjError> {
+    if tag.removed {
+        info!("Removing tag {:?} from {:?}", tag.name, file);
+        conn.hdel(tags(file), &tag.name).await?;
+    } else {
+        info!(
+            "Tagging {:?} at offset {} as {:?}",
+            file, tag.offset, tag.name
+        );
+        let mut serialized = Vec::new();
+        tag.encode(&mut serialized)?;
+        conn.hset(tags(file), &tag.name, serialized).await?;
+    }
+    store_file_offset(conn, file, offset).await?;
+    Ok(())
 }
 
 pub async fn update_object_cache(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     offset: i64,
     input: &[u8],
@@ -185,6 +324,12 @@ pub async fn update_object_cache(
         Some(change_msg::ChangeType::Add(object))
         | Some(change_msg::ChangeType::Modify(object)) => object.id.clone(),
         Some(change_msg::ChangeType::Delete(msg)) => msg.id.clone(),
+        Some(change_msg::ChangeType::Branch(branch)) => {
+            return create_branch(conn, file, offset, branch).await;
+        }
+        Some(change_msg::ChangeType::Tag(tag)) => {
+            return update_tag(conn, file, offset, tag).await;
+        }
         None => {
             return Err(ObjError::ObjNotFound(String::from(
                 "No change type specified",
@@ -198,7 +343,7 @@ pub async fn update_object_cache(
 }
 
 pub async fn get_objects(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     input: &GetObjectsInput,
 ) -> Result<Vec<OptionChangeMsg>, ObjError> {
     debug!("get_objects input: {:?}", input);
@@ -226,12 +371,170 @@ pub async fn get_objects(
 }
 
 pub async fn get_latest_offset(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     input: &GetLatestOffsetInput,
 ) -> Result<i64, ObjError> {
     let file_offset = file_offset(&input.file);
-    let offset: i64 = conn.get(file_offset).await?;
-    Ok(offset)
+    let offset: Option<i64> = conn.get(file_offset).await?;
+    offset.ok_or_else(|| ObjError::FileNotFound(input.file.clone()))
+}
+
+///Every object alive in the file as of the offset
+pub async fn get_obj_list(
+    conn: &dyn Store,
+    input: &GetObjectListInput,
+) -> Result<Vec<String>, ObjError> {
+    get_live_objects(conn, &input.file, input.offset).await
+}
+
+///Every change to an object up to the offset, oldest first, following a branch back into its parent
+pub async fn get_object_history(
+    conn: &dyn Store,
+    input: &GetObjectHistoryInput,
+) -> Result<Vec<ObjectHistoryEntryMsg>, ObjError> {
+    let mut entries = Vec::new();
+    let mut file = input.file.clone();
+    let mut offset = input.offset;
+    loop {
+        let serialized: Vec<Vec<u8>> = conn.lrange(obj_cache(&file, &input.obj_id), 0, -1).await?;
+        //Newest entries are first
+        for bytes in serialized {
+            let entry: ObjEntry = bincode::deserialize(&bytes)?;
+            if entry.offset <= offset {
+                entries.push(ObjectHistoryEntryMsg {
+                    file: file.clone(),
+                    offset: entry.offset,
+                    change: Some(ChangeMsg::decode(entry.object.as_ref())?),
+                });
+            }
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                file = branch.parent;
+                offset = branch.offset;
+            }
+            None => break,
+        }
+    }
+    if entries.is_empty() {
+        return Err(ObjError::ObjNotFound(input.obj_id.clone()));
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+///Every tag in the file, oldest offset first
+pub async fn get_tags(
+    conn: &dyn Store,
+    input: &GetTagsInput,
+) -> Result<Vec<TagMsg>, ObjError> {
+    let serialized: Vec<Vec<u8>> = conn.hvals(tags(&input.file)).await?;
+    let mut results = Vec::new();
+    for bytes in serialized {
+        results.push(TagMsg::decode(bytes.as_ref())?);
+    }
+    results.sort_by(|a, b| a.offset.cmp(&b.offset).then_with(|| a.name.cmp(&b.name)));
+    Ok(results)
+}
+
+//The file at the offset, followed by each parent it branched from at the offset it branched at
+async fn get_history(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+) -> Result<Vec<(String, i64)>, ObjError> {
+    let mut history = vec![(String::from(file), offset)];
+    let mut file = String::from(file);
+    while let Some(branch) = get_branch_parent(conn, &file).await? {
+        file = branch.parent;
+        history.push((file.clone(), branch.offset));
+    }
+    Ok(history)
+}
+
+//Objects changed in the file itself, not its parents, after one offset and up to and including another
+async fn get_changed_in_file(
+    conn: &dyn Store,
+    file: &str,
+    after: i64,
+    up_to: i64,
+) -> Result<HashSet<String>, ObjError> {
+    let mut candidates: HashSet<String> = conn.smembers(all_obj_list(file)).await?;
+    let latest: HashSet<String> = conn.smembers(latest_obj_list(file)).await?;
+    candidates.extend(latest);
+    let mut changed = HashSet::new();
+    for id in candidates {
+        let obj_cache = obj_cache(file, &id);
+        let cache_length: isize = conn.llen(&obj_cache).await?;
+        //Newest entries are first
+        for i in 0isize..cache_length {
+            let serialized: Vec<u8> = conn.lindex(&obj_cache, i).await?;
+            let entry: ObjEntry = bincode::deserialize(&serialized)?;
+            if entry.offset <= after {
+                break;
+            }
+            if entry.offset <= up_to {
+                changed.insert(id);
+                break;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+//Objects changed along a file's history since the point where it meets the base file
+async fn get_changed_since(
+    conn: &dyn Store,
+    history: &[(String, i64)],
+    base_offset: i64,
+) -> Result<Vec<String>, ObjError> {
+    let mut changed = HashSet::new();
+    if let Some(((base_file, up_to), branches)) = history.split_last() {
+        for (file, offset) in branches {
+            changed.extend(get_changed_in_file(conn, file, i64::MIN, *offset).await?);
+        }
+        changed.extend(get_changed_in_file(conn, base_file, base_offset, *up_to).await?);
+    }
+    let mut changed: Vec<String> = changed.into_iter().collect();
+    changed.sort();
+    Ok(changed)
+}
+
+///Finds the latest point two files or branches share, and the objects changed on each side since then
+pub async fn get_diverged_objects(
+    conn: &dyn Store,
+    input: &GetDivergedObjectsInput,
+) -> Result<GetDivergedObjectsOutput, ObjError> {
+    let ours = get_history(conn, &input.ours_file, input.ours_offset).await?;
+    let theirs = get_history(conn, &input.theirs_file, input.theirs_offset).await?;
+    let mut common = None;
+    for (ours_index, (file, _)) in ours.iter().enumerate() {
+        if let Some(theirs_index) = theirs.iter().position(|(other, _)| other == file) {
+            common = Some((ours_index, theirs_index));
+            break;
+        }
+    }
+    let (ours_index, theirs_index) = match common {
+        Some(indices) => indices,
+        None => {
+            return Err(ObjError::UnrelatedFiles(
+                input.ours_file.clone(),
+                input.theirs_file.clone(),
+            ))
+        }
+    };
+    let base_file = ours[ours_index].0.clone();
+    let base_offset = std::cmp::min(ours[ours_index].1, theirs[theirs_index].1);
+    debug!(
+        "Base of {:?} and {:?} is {:?} at offset {}",
+        input.ours_file, input.theirs_file, base_file, base_offset
+    );
+    Ok(GetDivergedObjectsOutput {
+        ours: get_changed_since(conn, &ours[..=ours_index], base_offset).await?,
+        theirs: get_changed_since(conn, &theirs[..=theirs_index], base_offset).await?,
+        base_file,
+        base_offset,
+    })
 }
 
 #[cfg(test)]
@@ -240,26 +543,24 @@ mod tests {
     use futures::StreamExt;
     use uuid::Uuid;
 
-    pub async fn test_get_conn() -> MultiplexedConnection {
+    pub async fn test_get_conn() -> Arc<dyn Store> {
         let _ = env_logger::Builder::new()
             .filter_module("objects", log::LevelFilter::Trace)
             .is_test(true)
             .try_init();
+        //Runs against a real Redis when REDIS_URL is set
         let env_opt = std::env::var("REDIS_URL");
         let redis_url = if let Ok(url) = env_opt {
             url
         } else {
-            String::from("redis://127.0.0.1:6379")
+            String::from("memory://tests")
         };
-        let client = redis::Client::open(redis_url).unwrap();
-        let (conn, fut) = client.get_multiplexed_async_connection().await.unwrap();
-        tokio::spawn(fut);
-        conn
+        cache_store::connect(&redis_url).await.unwrap()
     }
 
     #[tokio_macros::test]
     async fn test_cache() {
-        let mut conn = test_get_conn().await;
+        let conn = test_get_conn().await;
         let id = Uuid::new_v4().to_string();
         let file = Uuid::new_v4().to_string();
         let user = Uuid::new_v4().to_string();
@@ -277,13 +578,13 @@ mod tests {
         let offset_1 = 4;
 
         //Nothing in cache yet, this should error out
-        assert!(get_object(&mut conn, &file, offset_1, &id).await.is_err());
+        assert!(get_object(&conn, &file, offset_1, &id).await.is_err());
 
-        update_object_cache(&mut conn, &file, offset_1, &change_1_bytes)
+        update_object_cache(&conn, &file, offset_1, &change_1_bytes)
             .await
             .unwrap();
         assert_eq!(
-            get_object(&mut conn, &file, offset_1, &id).await.unwrap(),
+            get_object(&conn, &file, offset_1, &id).await.unwrap(),
             change_1_bytes
         );
 
@@ -299,11 +600,11 @@ mod tests {
         let mut change_2_bytes = Vec::new();
         change_2.encode(&mut change_2_bytes).unwrap();
         let offset_2 = 5;
-        update_object_cache(&mut conn, &file, offset_2, &change_2_bytes)
+        update_object_cache(&conn, &file, offset_2, &change_2_bytes)
             .await
             .unwrap();
         assert_eq!(
-            get_object(&mut conn, &file, offset_2, &id).await.unwrap(),
+            get_object(&conn, &file, offset_2, &id).await.unwrap(),
             change_2_bytes
         );
 
@@ -315,27 +616,27 @@ mod tests {
         let mut change_3_bytes = Vec::new();
         change_3.encode(&mut change_3_bytes).unwrap();
         let offset_3 = 6;
-        update_object_cache(&mut conn, &file, offset_3, &change_3_bytes)
+        update_object_cache(&conn, &file, offset_3, &change_3_bytes)
             .await
             .unwrap();
         assert_eq!(
-            get_object(&mut conn, &file, offset_3, &id).await.unwrap(),
+            get_object(&conn, &file, offset_3, &id).await.unwrap(),
             change_3_bytes
         );
 
         assert_eq!(
-            get_object(&mut conn, &file, offset_1, &id).await.unwrap(),
+            get_object(&conn, &file, offset_1, &id).await.unwrap(),
             change_1_bytes
         );
 
-        assert!(get_object(&mut conn, &file, offset_1 - 1, &id)
+        assert!(get_object(&conn, &file, offset_1 - 1, &id)
             .await
             .is_err());
     }
 
     #[tokio_macros::test]
     async fn test_get_latest_list() {
-        let mut conn = test_get_conn().await;
+        let conn = test_get_conn().await;
         let id_1 = Uuid::new_v4().to_string();
         let id_2 = Uuid::new_v4().to_string();
         let id_3 = Uuid::new_v4().to_string();
@@ -374,13 +675,13 @@ mod tests {
         };
         let mut change_3_bytes = Vec::new();
         change_3.encode(&mut change_3_bytes).unwrap();
-        update_object_cache(&mut conn, &file, 1, &change_1_bytes)
+        update_object_cache(&conn, &file, 1, &change_1_bytes)
             .await
             .unwrap();
-        update_object_cache(&mut conn, &file, 2, &change_2_bytes)
+        update_object_cache(&conn, &file, 2, &change_2_bytes)
             .await
             .unwrap();
-        update_object_cache(&mut conn, &file, 3, &change_3_bytes)
+        update_object_cache(&conn, &file, 3, &change_3_bytes)
             .await
             .unwrap();
 
@@ -396,4 +697,319 @@ mod tests {
         }
         assert_eq!(answer_set.len(), 0);
     }
+
+    fn encode_change(change_type: change_msg::ChangeType) -> Vec<u8> {
+        let change = ChangeMsg {
+            user: String::from("user"),
+            change_type: Some(change_type),
+            change_source: Some(change_msg::ChangeSource::UserAction(EmptyMsg {})),
+        };
+        let mut bytes = Vec::new();
+        change.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn add_msg(id: &str) -> change_msg::ChangeType {
+        change_msg::ChangeType::Add(ObjectMsg {
+            id: String::from(id),
+            dependencies: None,
+            obj_data: vec![],
+        })
+    }
+
+    #[tokio_macros::test]
+    async fn test_branch() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let branch = format!("{}@option", file);
+        let kept = Uuid::new_v4().to_string();
+        let deleted = Uuid::new_v4().to_string();
+        let later = Uuid::new_v4().to_string();
+        let kept_bytes = encode_change(add_msg(&kept));
+        update_object_cache(&conn, &file, 1, &kept_bytes)
+            .await
+            .unwrap();
+        update_object_cache(&conn, &file, 2, &encode_change(add_msg(&deleted)))
+            .await
+            .unwrap();
+        let delete = change_msg::ChangeType::Delete(DeleteMsg {
+            id: deleted.clone(),
+        });
+        update_object_cache(&conn, &file, 3, &encode_change(delete))
+            .await
+            .unwrap();
+        //Added to the main line after the branch was taken
+        update_object_cache(&conn, &file, 5, &encode_change(add_msg(&later)))
+            .await
+            .unwrap();
+
+        let branch_change = change_msg::ChangeType::Branch(BranchMsg {
+            parent: file.clone(),
+            offset: 4,
+        });
+        update_object_cache(&conn, &branch, 0, &encode_change(branch_change))
+            .await
+            .unwrap();
+        let latest = get_latest_offset(
+            &conn,
+            &GetLatestOffsetInput {
+                file: branch.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(latest, 0);
+
+        //Unchanged objects come from the parent as of the branch offset
+        assert_eq!(
+            get_object(&conn, &branch, 0, &kept).await.unwrap(),
+            kept_bytes
+        );
+        let stream = get_latest_obj_list(conn.clone(), branch.clone());
+        futures::pin_mut!(stream);
+        let mut ids = Vec::new();
+        while let Some(msg_res) = stream.next().await {
+            ids.push(msg_res.unwrap());
+        }
+        assert_eq!(ids, vec![kept.clone()]);
+
+        //Changes on the branch stay on the branch
+        let modify = change_msg::ChangeType::Modify(ObjectMsg {
+            id: kept.clone(),
+            dependencies: None,
+            obj_data: String::from("modified").into_bytes(),
+        });
+        let modify_bytes = encode_change(modify);
+        update_object_cache(&conn, &branch, 1, &modify_bytes)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_object(&conn, &branch, 1, &kept).await.unwrap(),
+            modify_bytes
+        );
+        assert_eq!(
+            get_object(&conn, &branch, 0, &kept).await.unwrap(),
+            kept_bytes
+        );
+        assert_eq!(
+            get_object(&conn, &file, 5, &kept).await.unwrap(),
+            kept_bytes
+        );
+    }
+
+    #[tokio_macros::test]
+    async fn test_diverged_objects() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let branch = format!("{}@option", file);
+        let untouched = Uuid::new_v4().to_string();
+        let ours_only = Uuid::new_v4().to_string();
+        let both = Uuid::new_v4().to_string();
+        let theirs_only = Uuid::new_v4().to_string();
+        for (offset, id) in [&untouched, &ours_only, &both].iter().enumerate() {
+            update_object_cache(&conn, &file, offset as i64, &encode_change(add_msg(id)))
+                .await
+                .unwrap();
+        }
+        let branch_change = change_msg::ChangeType::Branch(BranchMsg {
+            parent: file.clone(),
+            offset: 2,
+        });
+        update_object_cache(&conn, &branch, 0, &encode_change(branch_change))
+            .await
+            .unwrap();
+        let modify = |id: &String| {
+            encode_change(change_msg::ChangeType::Modify(ObjectMsg {
+                id: id.clone(),
+                dependencies: None,
+                obj_data: String::from("modified").into_bytes(),
+            }))
+        };
+        update_object_cache(&conn, &file, 3, &modify(&ours_only))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &file, 4, &modify(&both))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &branch, 1, &modify(&both))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &branch, 2, &encode_change(add_msg(&theirs_only)))
+            .await
+            .unwrap();
+
+        let input = GetDivergedObjectsInput {
+            ours_file: file.clone(),
+            ours_offset: 4,
+            theirs_file: branch.clone(),
+            theirs_offset: 2,
+        };
+        let output = get_diverged_objects(&conn, &input).await.unwrap();
+        assert_eq!(output.base_file, file);
+        assert_eq!(output.base_offset, 2);
+        let mut ours = vec![ours_only.clone(), both.clone()];
+        ours.sort();
+        let mut theirs = vec![both.clone(), theirs_only.clone()];
+        theirs.sort();
+        assert_eq!(output.ours, ours);
+        assert_eq!(output.theirs, theirs);
+
+        //Before the main line moved on, only the branch has diverged
+        let input = GetDivergedObjectsInput {
+            ours_file: file.clone(),
+            ours_offset: 2,
+            theirs_file: branch.clone(),
+            theirs_offset: 2,
+        };
+        let output = get_diverged_objects(&conn, &input).await.unwrap();
+        assert!(output.ours.is_empty());
+        assert_eq!(output.theirs, theirs);
+
+        let input = GetDivergedObjectsInput {
+            ours_file: file.clone(),
+            ours_offset: 4,
+            theirs_file: Uuid::new_v4().to_string(),
+            theirs_offset: 0,
+        };
+        assert!(get_diverged_objects(&conn, &input).await.is_err());
+    }
+
+    #[tokio_macros::test]
+    async fn test_tags() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let tag = |name: &str, offset, removed| {
+            encode_change(change_msg::ChangeType::Tag(TagMsg {
+                name: String::from(name),
+                offset,
+                description: String::new(),
+                removed,
+            }))
+        };
+        update_object_cache(&conn, &file, 3, &tag("Permit set", 2, false))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &file, 4, &tag("Schematic design", 1, false))
+            .await
+            .unwrap();
+        let input = GetTagsInput { file: file.clone() };
+        let tags = get_tags(&conn, &input).await.unwrap();
+        let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, vec!["Schematic design", "Permit set"]);
+        let latest = get_latest_offset(&conn, &GetLatestOffsetInput { file: file.clone() })
+            .await
+            .unwrap();
+        assert_eq!(latest, 4);
+
+        update_object_cache(&conn, &file, 5, &tag("Permit set", 2, true))
+            .await
+            .unwrap();
+        let tags = get_tags(&conn, &input).await.unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "Schematic design");
+        assert_eq!(tags[0].offset, 1);
+    }
+
+    #[tokio_macros::test]
+    async fn test_object_history() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let branch = format!("{}@option", file);
+        let id = Uuid::new_v4().to_string();
+        update_object_cache(&conn, &file, 1, &encode_change(add_msg(&id)))
+            .await
+            .unwrap();
+        let modify = ChangeMsg {
+            user: String::from("other user"),
+            change_type: Some(change_msg::ChangeType::Modify(ObjectMsg {
+                id: id.clone(),
+                dependencies: None,
+                obj_data: String::from("modified").into_bytes(),
+            })),
+            change_source: Some(change_msg::ChangeSource::Undo(String::from("event"))),
+        };
+        let mut modify_bytes = Vec::new();
+        modify.encode(&mut modify_bytes).unwrap();
+        update_object_cache(&conn, &file, 3, &modify_bytes)
+            .await
+            .unwrap();
+        let branch_change = change_msg::ChangeType::Branch(BranchMsg {
+            parent: file.clone(),
+            offset: 2,
+        });
+        update_object_cache(&conn, &branch, 0, &encode_change(branch_change))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &branch, 1, &modify_bytes)
+            .await
+            .unwrap();
+
+        let input = GetObjectHistoryInput {
+            file: file.clone(),
+            obj_id: id.clone(),
+            offset: i64::MAX,
+        };
+        let history = get_object_history(&conn, &input).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].offset, 1);
+        assert_eq!(history[1].offset, 3);
+        assert_eq!(history[1].change, Some(modify.clone()));
+
+        //The branch only sees its parent's history up to where it started
+        let input = GetObjectHistoryInput {
+            file: branch.clone(),
+            obj_id: id.clone(),
+            offset: i64::MAX,
+        };
+        let history = get_object_history(&conn, &input).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].file, file);
+        assert_eq!(history[0].offset, 1);
+        assert_eq!(history[1].file, branch);
+        assert_eq!(history[1].change.as_ref().unwrap().user, "other user");
+
+        let input = GetObjectHistoryInput {
+            file: file.clone(),
+            obj_id: id.clone(),
+            offset: 0,
+        };
+        assert!(get_object_history(&conn, &input).await.is_err());
+    }
+
+    #[tokio_macros::test]
+    async fn test_obj_list_at_offset() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let first = Uuid::new_v4().to_string();
+        let second = Uuid::new_v4().to_string();
+        update_object_cache(&conn, &file, 1, &encode_change(add_msg(&first)))
+            .await
+            .unwrap();
+        update_object_cache(&conn, &file, 2, &encode_change(add_msg(&second)))
+            .await
+            .unwrap();
+        let delete = change_msg::ChangeType::Delete(DeleteMsg { id: first.clone() });
+        update_object_cache(&conn, &file, 3, &encode_change(delete))
+            .await
+            .unwrap();
+        let list_at = |offset| GetObjectListInput {
+            file: file.clone(),
+            offset,
+        };
+        assert!(get_obj_list(&conn, &list_at(0))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_obj_list(&conn, &list_at(1)).await.unwrap(),
+            vec![first.clone()]
+        );
+        let mut both = vec![first.clone(), second.clone()];
+        both.sort();
+        assert_eq!(get_obj_list(&conn, &list_at(2)).await.unwrap(), both);
+        assert_eq!(
+            get_obj_list(&conn, &list_at(3)).await.unwrap(),
+            vec![second.clone()]
+        );
+    }
 }
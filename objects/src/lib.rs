@@ -0,0 +1,166 @@
+use cache_store::Store;
+use commit_log::CommitLog;
+use futures::StreamExt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use trace_lib::*;
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod cache;
+mod consume;
+use cache::*;
+use consume::*;
+
+#[instrument]
+async fn get_redis_conn(url: &str) -> Result<Arc<dyn Store>, tonic::Status> {
+    cache_store::connect(url)
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("{:?}", e)))
+}
+
+#[derive(Debug)]
+struct RepCacheService {
+    redis_url: String,
+}
+
+#[tonic::async_trait]
+impl objects_server::Objects for RepCacheService {
+    #[instrument]
+    async fn get_objects(
+        &self,
+        request: Request<GetObjectsInput>,
+    ) -> Result<Response<GetObjectsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let objects = cache::get_objects(&redis_conn, msg)
+            .instrument(info_span!("cache::get_objects"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetObjectsOutput { objects }))
+    }
+
+    #[instrument]
+    async fn get_latest_offset(
+        &self,
+        request: Request<GetLatestOffsetInput>,
+    ) -> Result<Response<GetLatestOffsetOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let offset = cache::get_latest_offset(&redis_conn, msg)
+            .instrument(info_span!("cache::get_latest_offset"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetLatestOffsetOutput { offset }))
+    }
+
+    type GetLatestObjectListStream = mpsc::Receiver<Result<GetLatestObjectListOutput, Status>>;
+
+    async fn get_latest_object_list(
+        &self,
+        request: Request<GetLatestObjectListInput>,
+    ) -> Result<Response<Self::GetLatestObjectListStream>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.into_inner();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let (mut tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let stream = cache::get_latest_obj_list(redis_conn, msg.file);
+            futures::pin_mut!(stream);
+            while let Some(msg_res) = stream.next().await {
+                match msg_res {
+                    Ok(obj_id) => {
+                        let msg = GetLatestObjectListOutput { obj_id };
+                        tx.send(Ok(msg)).await.unwrap();
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        tx.send(Err(to_status(e))).await.unwrap();
+                    }
+                }
+            }
+        });
+        Ok(Response::new(rx))
+    }
+
+    #[instrument]
+    async fn get_object_list(
+        &self,
+        request: Request<GetObjectListInput>,
+    ) -> Result<Response<GetObjectListOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let obj_ids = cache::get_obj_list(&redis_conn, msg)
+            .instrument(info_span!("cache::get_obj_list"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetObjectListOutput { obj_ids }))
+    }
+
+    #[instrument]
+    async fn get_object_history(
+        &self,
+        request: Request<GetObjectHistoryInput>,
+    ) -> Result<Response<GetObjectHistoryOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let entries = cache::get_object_history(&redis_conn, msg)
+            .instrument(info_span!("cache::get_object_history"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetObjectHistoryOutput { entries }))
+    }
+
+    #[instrument]
+    async fn get_tags(
+        &self,
+        request: Request<GetTagsInput>,
+    ) -> Result<Response<GetTagsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let tags = cache::get_tags(&redis_conn, msg)
+            .instrument(info_span!("cache::get_tags"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetTagsOutput { tags }))
+    }
+
+    #[instrument]
+    async fn get_diverged_objects(
+        &self,
+        request: Request<GetDivergedObjectsInput>,
+    ) -> Result<Response<GetDivergedObjectsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let output = cache::get_diverged_objects(&redis_conn, msg)
+            .instrument(info_span!("cache::get_diverged_objects"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(output))
+    }
+}
+
+///Serves the objects cache while keeping it up to date from the commit log
+pub async fn run(
+    run_url: SocketAddr,
+    redis_url: String,
+    log: Arc<dyn CommitLog>,
+    group: String,
+    topic: String,
+) -> Result<(), tonic::transport::Error> {
+    tokio::spawn(update_cache(redis_url.clone(), log, group, topic));
+
+    let svc = objects_server::ObjectsServer::new(RepCacheService { redis_url });
+
+    info!("Running on {:?}", run_url);
+    Server::builder().add_service(svc).serve(run_url).await
+}
@@ -0,0 +1,418 @@
+use crate::*;
+use cache_store::{Store, StoreCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::*;
+
+//Everything a tessellation depends on.  Each kind of kernel call gets its own variant.
+#[derive(Serialize)]
+enum Primitive<'a> {
+    Prism {
+        first_pt: &'a Point3f,
+        second_pt: &'a Point3f,
+        width: WorldCoord,
+        height: WorldCoord,
+    },
+}
+
+///Which kernel made a mesh, and which version of its meshes.
+///The store outlives builds and can be shared by different kernels, so meshes are only used by the same kernel and version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KernelIdentity {
+    pub name: String,
+    pub mesh_version: u32,
+}
+
+//The call serialized along with the kernel it went to, which is kept with the mesh, and its hash to find it by.
+//FNV keeps the hash the same across builds, so processes can share the store.
+fn hash_key(identity: &KernelIdentity, primitive: &Primitive) -> Result<(u64, Vec<u8>), ObjError> {
+    let bytes = bincode::serialize(&(identity, primitive)).map_err(other_error)?;
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(&bytes);
+    Ok((hasher.finish(), bytes))
+}
+
+fn store_key(key: u64) -> String {
+    format!("tessellation:{:016x}", key)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Tessellation {
+    //Two calls can hash the same, so a mesh is only used for the call that made it
+    primitive: Vec<u8>,
+    positions: Vec<WorldCoord>,
+    indices: Vec<u64>,
+    normals: Vec<WorldCoord>,
+    uvs: Vec<WorldCoord>,
+    material_indices: Vec<u32>,
+}
+
+//Least recently used entries are dropped once there are more than capacity
+#[derive(Debug)]
+struct MemoryTier {
+    capacity: usize,
+    entries: HashMap<u64, (Tessellation, u64)>,
+    by_use: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+impl MemoryTier {
+    fn get(&mut self, key: u64) -> Option<Tessellation> {
+        self.clock += 1;
+        let (mesh, used) = self.entries.get_mut(&key)?;
+        self.by_use.remove(used);
+        *used = self.clock;
+        self.by_use.insert(self.clock, key);
+        Some(mesh.clone())
+    }
+
+    fn insert(&mut self, key: u64, mesh: Tessellation) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key, (mesh, self.clock)) {
+            self.by_use.remove(&used);
+        }
+        self.by_use.insert(self.clock, key);
+        while self.entries.len() > self.capacity {
+            let (oldest_use, oldest) = match self.by_use.iter().next() {
+                Some((used, key)) => (*used, *key),
+                None => break,
+            };
+            self.by_use.remove(&oldest_use);
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub memory_hits: u64,
+    pub store_hits: u64,
+    pub misses: u64,
+}
+
+///Tessellations kept by a hash of the kernel call that made them.
+///The same inputs to the same version of a kernel always give the same mesh, so nothing ever needs invalidating.
+///Recently used meshes are kept in memory, and a store, if there is one, holds them for every process sharing it.
+#[derive(Debug)]
+pub struct TessellationCache {
+    memory: Mutex<MemoryTier>,
+    store: Option<Arc<dyn Store>>,
+    memory_hits: AtomicU64,
+    store_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TessellationCache {
+    pub fn new(capacity: usize, store: Option<Arc<dyn Store>>) -> TessellationCache {
+        TessellationCache {
+            memory: Mutex::new(MemoryTier {
+                capacity,
+                entries: HashMap::new(),
+                by_use: BTreeMap::new(),
+                clock: 0,
+            }),
+            store,
+            memory_hits: AtomicU64::new(0),
+            store_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            memory_hits: self.memory_hits.load(Ordering::Relaxed),
+            store_hits: self.store_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn get_from_store(&self, key: u64) -> Option<Tessellation> {
+        let store = self.store.as_ref()?;
+        //The store only saves calls to the kernel, so it failing isn't worth failing the update over
+        let bytes: Option<Vec<u8>> = match store.get(store_key(key)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Couldn't read tessellation {:016x}: {}", key, e);
+                None
+            }
+        };
+        bincode::deserialize(&bytes?).ok()
+    }
+
+    async fn get(&self, key: u64, primitive: &[u8]) -> Option<Tessellation> {
+        let from_memory = self.memory.lock().unwrap().get(key);
+        if let Some(mesh) = from_memory.filter(|mesh| mesh.primitive == primitive) {
+            self.memory_hits.fetch_add(1, Ordering::Relaxed);
+            return Some(mesh);
+        }
+        let from_store = self.get_from_store(key).await;
+        match from_store.filter(|mesh| mesh.primitive == primitive) {
+            Some(mesh) => {
+                self.store_hits.fetch_add(1, Ordering::Relaxed);
+                self.memory.lock().unwrap().insert(key, mesh.clone());
+                Some(mesh)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    async fn insert(&self, key: u64, mesh: Tessellation) {
+        if let Some(store) = &self.store {
+            match bincode::serialize(&mesh) {
+                Ok(bytes) => {
+                    if let Err(e) = store.set(store_key(key), bytes).await {
+                        warn!("Couldn't save tessellation {:016x}: {}", key, e);
+                    }
+                }
+                Err(e) => warn!("Couldn't serialize tessellation {:016x}: {}", key, e),
+            }
+        }
+        self.memory.lock().unwrap().insert(key, mesh);
+    }
+}
+
+///Wraps a kernel so that anything already in the cache doesn't go to the kernel again
+pub struct CachedKernel {
+    kernel: Box<dyn GeomKernel>,
+    identity: KernelIdentity,
+    cache: Arc<TessellationCache>,
+}
+
+impl CachedKernel {
+    pub fn new(
+        kernel: Box<dyn GeomKernel>,
+        identity: KernelIdentity,
+        cache: Arc<TessellationCache>,
+    ) -> CachedKernel {
+        CachedKernel {
+            kernel,
+            identity,
+            cache,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl GeomKernel for CachedKernel {
+    async fn make_prism(
+        &self,
+        first_pt: &Point3f,
+        second_pt: &Point3f,
+        width: f64,
+        height: f64,
+        results: &mut MeshData,
+    ) -> Result<(), ObjError> {
+        let (key, primitive) = hash_key(
+            &self.identity,
+            &Primitive::Prism {
+                first_pt,
+                second_pt,
+                width,
+                height,
+            },
+        )?;
+        if let Some(mesh) = self.cache.get(key, &primitive).await {
+            results.positions = mesh.positions;
+            results.indices = mesh.indices;
+            results.normals = mesh.normals;
+            results.uvs = mesh.uvs;
+            results.material_indices = mesh.material_indices;
+            return Ok(());
+        }
+        self.kernel
+            .make_prism(first_pt, second_pt, width, height, results)
+            .await?;
+        let mesh = Tessellation {
+            primitive,
+            positions: results.positions.clone(),
+            indices: results.indices.clone(),
+            normals: results.normals.clone(),
+            uvs: results.uvs.clone(),
+            material_indices: results.material_indices.clone(),
+        };
+        self.cache.insert(key, mesh).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    //Counts how many calls make it through to the kernel
+    struct CountingKernel {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl GeomKernel for CountingKernel {
+        async fn make_prism(
+            &self,
+            first_pt: &Point3f,
+            second_pt: &Point3f,
+            width: f64,
+            height: f64,
+            results: &mut MeshData,
+        ) -> Result<(), ObjError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            LocalKernel::new()
+                .make_prism(first_pt, second_pt, width, height, results)
+                .await
+        }
+    }
+
+    fn identity() -> KernelIdentity {
+        KernelIdentity {
+            name: String::from("local"),
+            mesh_version: LOCAL_MESH_VERSION,
+        }
+    }
+
+    async fn prism(kernel: &dyn GeomKernel, length: f64) -> MeshData {
+        let mut data = MeshData::default();
+        kernel
+            .make_prism(
+                &Point3f::new(0.0, 0.0, 0.0),
+                &Point3f::new(length, 0.0, 0.0),
+                0.5,
+                3.0,
+                &mut data,
+            )
+            .await
+            .unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn test_cached_kernel() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store: Arc<dyn Store> = Arc::new(cache_store::MemoryStore::new());
+        let cache = Arc::new(TessellationCache::new(1, Some(store.clone())));
+        let kernel = CachedKernel::new(
+            Box::new(CountingKernel {
+                calls: calls.clone(),
+            }),
+            identity(),
+            cache.clone(),
+        );
+
+        let first = prism(&kernel, 4.0).await;
+        let again = prism(&kernel, 4.0).await;
+        assert_eq!(first, again);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        //Only one mesh fits in memory, so the first is pushed out to the store
+        prism(&kernel, 5.0).await;
+        prism(&kernel, 4.0).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                memory_hits: 1,
+                store_hits: 1,
+                misses: 2,
+            }
+        );
+
+        //Another process sharing the store doesn't have to call its kernel either
+        let other_cache = Arc::new(TessellationCache::new(10, Some(store)));
+        let other_kernel = CachedKernel::new(
+            Box::new(CountingKernel {
+                calls: calls.clone(),
+            }),
+            identity(),
+            other_cache.clone(),
+        );
+        assert_eq!(prism(&other_kernel, 5.0).await, prism(&kernel, 5.0).await);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(other_cache.stats().store_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_hash_collision() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = Arc::new(TessellationCache::new(10, None));
+        let kernel = CachedKernel::new(
+            Box::new(CountingKernel {
+                calls: calls.clone(),
+            }),
+            identity(),
+            cache.clone(),
+        );
+        let first = prism(&kernel, 4.0).await;
+        //Pretend a different call hashed the same as this one and left its mesh here
+        let (key, _) = hash_key(
+            &identity(),
+            &Primitive::Prism {
+                first_pt: &Point3f::new(0.0, 0.0, 0.0),
+                second_pt: &Point3f::new(5.0, 0.0, 0.0),
+                width: 0.5,
+                height: 3.0,
+            },
+        )
+        .unwrap();
+        let (_, other_primitive) = hash_key(
+            &identity(),
+            &Primitive::Prism {
+                first_pt: &Point3f::new(0.0, 0.0, 0.0),
+                second_pt: &Point3f::new(4.0, 0.0, 0.0),
+                width: 0.5,
+                height: 3.0,
+            },
+        )
+        .unwrap();
+        let mesh = Tessellation {
+            primitive: other_primitive,
+            positions: first.positions.clone(),
+            indices: first.indices.clone(),
+            normals: first.normals.clone(),
+            uvs: first.uvs.clone(),
+            material_indices: first.material_indices.clone(),
+        };
+        cache.insert(key, mesh).await;
+        let second = prism(&kernel, 5.0).await;
+        assert_ne!(first.positions, second.positions);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_kernel_identity() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store: Arc<dyn Store> = Arc::new(cache_store::MemoryStore::new());
+        let kernel = |identity: KernelIdentity| {
+            CachedKernel::new(
+                Box::new(CountingKernel {
+                    calls: calls.clone(),
+                }),
+                identity,
+                Arc::new(TessellationCache::new(10, Some(store.clone()))),
+            )
+        };
+        prism(&kernel(identity()), 4.0).await;
+        prism(&kernel(identity()), 4.0).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        //Meshes from another kernel, or an older version of this one, aren't used
+        let other = KernelIdentity {
+            name: String::from("http://geometry-kernel:5000"),
+            mesh_version: LOCAL_MESH_VERSION,
+        };
+        prism(&kernel(other), 4.0).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let older = KernelIdentity {
+            name: String::from("local"),
+            mesh_version: LOCAL_MESH_VERSION - 1,
+        };
+        prism(&kernel(older), 4.0).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -56,13 +56,15 @@ impl Data for Door {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, conn: &dyn GeomKernel, lod: Lod) -> Result<UpdateOutput, ObjError> {
         let mut data = MeshData {
-            positions: Vec::with_capacity(24),
-            indices: Vec::with_capacity(36),
             metadata: Some(json!({
                 "type": "Door",
                 "traits": ["ReferTo", "Position", "UpdateFromRefs"],
+                "materials": [
+                    { "name": "Door Face", "color": RGBA::new(160, 120, 80, 1.0) },
+                    { "name": "Door Cut", "color": RGBA::new(120, 90, 60, 1.0) }
+                ],
                 "obj": {
                     "Width": self.width,
                     "Height": self.height,
@@ -70,7 +72,12 @@ impl Data for Door {
                     "Second": self.dir.line.pt_2
                 }
             })),
+            ..Default::default()
         };
+        if lod == Lod::BoundingBox {
+            bounding_box_mesh(&self.get_axis_aligned_bounding_box(), &mut data);
+            return Ok(UpdateOutput::Mesh { data });
+        }
         let rotated = rotate_point_through_angle_2d(
             &self.dir.line.pt_1,
             &self.dir.line.pt_2,
@@ -82,7 +89,8 @@ impl Data for Door {
             self.width,
             self.height,
             &mut data,
-        ).await?;
+        )
+        .await?;
         Ok(UpdateOutput::Mesh { data: data })
     }
 
@@ -1,4 +1,8 @@
 use crate::*;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::{mpsc, oneshot};
 use tracing_futures::Instrument;
 
 mod geom {
@@ -23,41 +27,226 @@ fn to_pt_msg(pt: &Point3f) -> Point3Msg {
     }
 }
 
+///Goes up whenever the meshes made from a geometry kernel service's output change, so cached meshes from older builds aren't used
+pub const SERVICE_MESH_VERSION: u32 = 1;
+
+//Requests that queue up while a batch is in flight go out together in the next one, up to this many
+const MAX_BATCH: usize = 1000;
+
+struct PrismRequest {
+    input: MakePrismInput,
+    output: oneshot::Sender<Result<MakePrismOutput, tonic::Status>>,
+}
+
+///A handle on the geometry kernel at a url.
+///Every handle on the same url shares one channel, and prisms asked for at the same time are sent
+///together in one MakePrisms call.
 #[derive(Clone)]
 pub struct GeomConn {
-    conn: GeometryKernelClient<tonic::transport::Channel>,
+    url: String,
+    requests: mpsc::UnboundedSender<PrismRequest>,
+}
+
+lazy_static! {
+    static ref CONNECTIONS: Mutex<HashMap<String, GeomConn>> = Mutex::new(HashMap::new());
+}
+
+//Each prism in a batch succeeds or fails on its own
+fn from_prism_result(result: MakePrismResultMsg) -> Result<MakePrismOutput, tonic::Status> {
+    match result.result {
+        Some(make_prism_result_msg::Result::Prism(prism)) => Ok(prism),
+        Some(make_prism_result_msg::Result::Error(error)) => Err(tonic::Status::internal(error)),
+        None => Err(tonic::Status::internal(
+            "Geometry kernel returned no result",
+        )),
+    }
+}
+
+//Sends whatever requests have queued up as one batch, then does the same again once that's done
+async fn send_batches<F, Fut>(mut send: F, mut requests: mpsc::UnboundedReceiver<PrismRequest>)
+where
+    F: FnMut(MakePrismsInput) -> Fut,
+    Fut: std::future::Future<Output = Result<MakePrismsOutput, tonic::Status>>,
+{
+    while let Some(first) = requests.recv().await {
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH {
+            match requests.try_recv() {
+                Ok(request) => batch.push(request),
+                Err(_) => break,
+            }
+        }
+        let (prisms, outputs): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|request| (request.input, request.output))
+            .unzip();
+        let count = outputs.len();
+        match send(MakePrismsInput { prisms })
+            .instrument(info_span!("make_prisms", count))
+            .await
+        {
+            Ok(output) if output.results.len() == count => {
+                for (sender, result) in outputs.into_iter().zip(output.results) {
+                    //The caller may have given up waiting, which is fine
+                    let _ = sender.send(from_prism_result(result));
+                }
+            }
+            Ok(output) => {
+                let status = tonic::Status::internal(format!(
+                    "Asked for {} prisms but got {}",
+                    count,
+                    output.results.len()
+                ));
+                for sender in outputs {
+                    let _ = sender.send(Err(status.clone()));
+                }
+            }
+            Err(status) => {
+                for output in outputs {
+                    let _ = output.send(Err(status.clone()));
+                }
+            }
+        }
+    }
 }
 
 pub async fn new_geom_conn(url: String) -> Result<GeomConn, ObjError> {
-    Ok(GeomConn {
-        conn: GeometryKernelClient::connect(url).await?,
-    })
+    if let Some(conn) = CONNECTIONS.lock().unwrap().get(&url) {
+        return Ok(conn.clone());
+    }
+    let client = GeometryKernelClient::connect(url.clone()).await?;
+    let send = move |prisms| {
+        let mut client = client.clone();
+        async move { trace_response(client.make_prisms(TracedRequest::new(prisms)).await) }
+    };
+    let (requests, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(send_batches(send, receiver));
+    let conn = GeomConn { url, requests };
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .insert(conn.url.clone(), conn.clone());
+    Ok(conn)
+}
+
+///Picks the kernel from the GEOM_URL setting, along with what its meshes are cached under.
+///local:// tessellates in process with LocalKernel, anything else is the url of a geometry kernel service.
+pub async fn connect_kernel(
+    url: String,
+) -> Result<(Box<dyn GeomKernel>, KernelIdentity), ObjError> {
+    if url.starts_with("local://") {
+        let identity = KernelIdentity {
+            name: String::from("local"),
+            mesh_version: LOCAL_MESH_VERSION,
+        };
+        Ok((Box::new(LocalKernel::new()), identity))
+    } else {
+        let identity = KernelIdentity {
+            name: url.clone(),
+            mesh_version: SERVICE_MESH_VERSION,
+        };
+        Ok((Box::new(new_geom_conn(url).await?), identity))
+    }
 }
 
 #[async_trait::async_trait]
 impl GeomKernel for GeomConn {
     async fn make_prism(
-        &mut self,
+        &self,
         first_pt: &Point3f,
         second_pt: &Point3f,
         width: f64,
         height: f64,
         results: &mut MeshData,
     ) -> Result<(), ObjError> {
-        let input = TracedRequest::new(MakePrismInput {
+        let input = MakePrismInput {
             first_pt: Some(to_pt_msg(first_pt)),
             second_pt: Some(to_pt_msg(second_pt)),
             width,
             height,
-        });
-        let resp = self
-            .conn
-            .make_prism(input)
+        };
+        let (output, receiver) = oneshot::channel();
+        if self.requests.send(PrismRequest { input, output }).is_err() {
+            //The runtime the batches were sent from has gone, so the next handle reconnects
+            CONNECTIONS.lock().unwrap().remove(&self.url);
+            return Err(ObjError::Other(format!(
+                "Connection to geometry kernel at {} was closed",
+                self.url
+            )));
+        }
+        let output = receiver
             .instrument(info_span!("make_prism"))
-            .await;
-        let output = trace_response(resp)?;
+            .await
+            .map_err(other_error)??;
         results.positions = output.positions;
         results.indices = output.indices;
+        results.normals = output.normals;
+        results.uvs = output.uvs;
+        results.material_indices = output.material_indices;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_batch_prisms() {
+        //Sends each prism's width back as its only position, and counts the batches.
+        //Prisms with no width fail, like a degenerate wall would.
+        let batches = Arc::new(AtomicUsize::new(0));
+        let counter = batches.clone();
+        let send = move |input: MakePrismsInput| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let results = input
+                .prisms
+                .iter()
+                .map(|prism| {
+                    let result = if prism.width == 0.0 {
+                        make_prism_result_msg::Result::Error(String::from("No width"))
+                    } else {
+                        make_prism_result_msg::Result::Prism(MakePrismOutput {
+                            positions: vec![prism.width],
+                            indices: vec![0],
+                            ..Default::default()
+                        })
+                    };
+                    MakePrismResultMsg {
+                        result: Some(result),
+                    }
+                })
+                .collect();
+            async move {
+                tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+                Ok(MakePrismsOutput { results })
+            }
+        };
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(send_batches(send, receiver));
+        let conn = GeomConn {
+            url: String::from("fake"),
+            requests,
+        };
+
+        let first = Point3f::new(0.0, 0.0, 0.0);
+        let second = Point3f::new(1.0, 0.0, 0.0);
+        let prisms = (0..50).map(|i| {
+            let conn = &conn;
+            async move {
+                let mut data = MeshData::default();
+                conn.make_prism(&first, &second, i as f64, 1.0, &mut data)
+                    .await
+                    .map(|()| data.positions)
+            }
+        });
+        let results = futures::future::join_all(prisms).await;
+        assert!(results[0].is_err());
+        for (i, positions) in results.into_iter().enumerate().skip(1) {
+            assert_eq!(positions.unwrap(), vec![i as f64]);
+        }
+        assert!(batches.load(Ordering::SeqCst) < 50);
+    }
+}
@@ -1,15 +1,19 @@
 pub use obj_traits::*;
 
+mod cached_kernel;
 mod door;
 mod geom_kernel;
+mod local_kernel;
 mod sheet;
 mod symbol_def;
 mod symbol_instance;
 mod viewport;
 mod visibility_group;
 mod wall;
+pub use cached_kernel::{CacheStats, CachedKernel, KernelIdentity, TessellationCache};
 pub use door::Door;
-pub use geom_kernel::{new_geom_conn, GeomConn};
+pub use geom_kernel::{connect_kernel, new_geom_conn, GeomConn, SERVICE_MESH_VERSION};
+pub use local_kernel::{bounding_box_mesh, LocalKernel, LOCAL_MESH_VERSION};
 pub use sheet::Sheet;
 pub use symbol_def::SymbolDef;
 pub use symbol_instance::SymbolInstance;
@@ -0,0 +1,212 @@
+use crate::*;
+use cgmath::{EuclideanSpace, InnerSpace};
+
+///Tessellates in process instead of calling out to the OpenCascade geometry kernel.
+///Meshes come back laid out the same way: every triangle has its own three vertices, in world coordinates,
+///wound counter-clockwise when seen from outside.
+#[derive(Debug, Clone, Default)]
+pub struct LocalKernel {}
+
+///Goes up whenever the meshes LocalKernel makes change, so cached meshes from older builds aren't used
+pub const LOCAL_MESH_VERSION: u32 = 1;
+
+impl LocalKernel {
+    pub fn new() -> LocalKernel {
+        LocalKernel {}
+    }
+}
+
+//Faces that point mostly up or down are the top and bottom of the prism.
+//Their UVs are x and y, and the sides' run along the face horizontally and up it with z.
+//Triangles with no area don't face anywhere, so they're left out rather than given a NaN normal.
+fn push_triangle(results: &mut MeshData, pts: [&Point3f; 3]) {
+    let cross = (pts[1] - pts[0]).cross(pts[2] - pts[0]);
+    let area = cross.magnitude2();
+    if area == 0.0 || area.is_nan() {
+        return;
+    }
+    let normal = cross.normalize();
+    let is_cap = normal.z.abs() > 0.5;
+    let along = Vector3f::new(-normal.y, normal.x, 0.0).normalize();
+    for pt in pts.iter() {
+        results.positions.push(pt.x);
+        results.positions.push(pt.y);
+        results.positions.push(pt.z);
+        results.indices.push(results.indices.len() as u64);
+        results.normals.push(normal.x);
+        results.normals.push(normal.y);
+        results.normals.push(normal.z);
+        if is_cap {
+            results.uvs.push(pt.x);
+            results.uvs.push(pt.y);
+        } else {
+            results.uvs.push(pt.to_vec().dot(along));
+            results.uvs.push(pt.z);
+        }
+    }
+    results.material_indices.push(if is_cap {
+        CAP_MATERIAL
+    } else {
+        SIDE_MATERIAL
+    });
+}
+
+//Extrudes a convex footprint, listed counter-clockwise from above, straight up by height
+fn extrude(footprint: &[Point3f], height: WorldCoord, results: &mut MeshData) {
+    let vert_offset = Vector3f::new(0.0, 0.0, height);
+    let top: Vec<Point3f> = footprint.iter().map(|pt| pt + vert_offset).collect();
+    for i in 1..footprint.len() - 1 {
+        //The bottom faces down, so it winds the other way
+        push_triangle(results, [&footprint[0], &footprint[i + 1], &footprint[i]]);
+        push_triangle(results, [&top[0], &top[i], &top[i + 1]]);
+    }
+    for i in 0..footprint.len() {
+        let j = (i + 1) % footprint.len();
+        push_triangle(results, [&footprint[i], &footprint[j], &top[j]]);
+        push_triangle(results, [&footprint[i], &top[j], &top[i]]);
+    }
+}
+
+///A box filling the cube, laid out like a prism from the kernel.
+///It's simple enough to not need a kernel, so it's used for bounding box levels of detail whatever the kernel.
+pub fn bounding_box_mesh(cube: &Cube, results: &mut MeshData) {
+    let low = &cube.bottom_left;
+    let high = &cube.top_right;
+    let footprint = [
+        Point3f::new(low.x, low.y, low.z),
+        Point3f::new(high.x, low.y, low.z),
+        Point3f::new(high.x, high.y, low.z),
+        Point3f::new(low.x, high.y, low.z),
+    ];
+    extrude(&footprint, high.z - low.z, results);
+}
+
+#[async_trait::async_trait]
+impl GeomKernel for LocalKernel {
+    async fn make_prism(
+        &self,
+        first_pt: &Point3f,
+        second_pt: &Point3f,
+        width: f64,
+        height: f64,
+        results: &mut MeshData,
+    ) -> Result<(), ObjError> {
+        if first_pt.x == second_pt.x && first_pt.y == second_pt.y {
+            return Err(ObjError::Other(String::from(
+                "Can't make a prism between points with the same location",
+            )));
+        }
+        if !(width > 0.0 && height > 0.0) {
+            return Err(ObjError::Other(format!(
+                "Can't make a prism {} wide and {} high",
+                width, height
+            )));
+        }
+        let (first, second, third, fourth) = offset_line(first_pt, second_pt, width);
+        results.positions.clear();
+        results.indices.clear();
+        results.normals.clear();
+        results.uvs.clear();
+        results.material_indices.clear();
+        extrude(&[first, second, third, fourth], height, results);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_make_prism() {
+        let kernel = LocalKernel::new();
+        let mut data = MeshData::default();
+        kernel
+            .make_prism(
+                &Point3f::new(0.0, 0.0, 0.0),
+                &Point3f::new(4.0, 0.0, 0.0),
+                0.5,
+                3.0,
+                &mut data,
+            )
+            .await
+            .unwrap();
+        //Six faces of two triangles each
+        assert_eq!(data.indices.len(), 36);
+        assert_eq!(data.positions.len(), 36 * 3);
+        assert_eq!(data.normals.len(), 36 * 3);
+        assert_eq!(data.uvs.len(), 36 * 2);
+        let caps = data
+            .material_indices
+            .iter()
+            .filter(|material| **material == CAP_MATERIAL)
+            .count();
+        assert_eq!(data.material_indices.len(), 12);
+        assert_eq!(caps, 4);
+        let pts: Vec<Point3f> = data
+            .positions
+            .chunks(3)
+            .map(|pt| Point3f::new(pt[0], pt[1], pt[2]))
+            .collect();
+        let center = Point3f::new(2.0, 0.0, 1.5);
+        for (tri, normals) in pts.chunks(3).zip(data.normals.chunks(9)) {
+            for pt in tri {
+                assert!(pt.x >= 0.0 && pt.x <= 4.0);
+                assert!(pt.y >= -0.5 && pt.y <= 0.5);
+                assert!(pt.z >= 0.0 && pt.z <= 3.0);
+            }
+            //Every triangle faces away from the middle of the prism
+            let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]);
+            assert!(normal.dot(tri[0] - center) > 0.0);
+            let given = Vector3f::new(normals[0], normals[1], normals[2]);
+            assert!((given - normal.normalize()).magnitude() < 1e-9);
+        }
+
+        let same = kernel
+            .make_prism(
+                &Point3f::new(1.0, 1.0, 0.0),
+                &Point3f::new(1.0, 1.0, 0.0),
+                0.5,
+                3.0,
+                &mut data,
+            )
+            .await;
+        assert!(same.is_err());
+        for (width, height) in &[(0.0, 3.0), (0.5, 0.0), (-0.5, 3.0), (0.5, f64::NAN)] {
+            let flat = kernel
+                .make_prism(
+                    &Point3f::new(0.0, 0.0, 0.0),
+                    &Point3f::new(4.0, 0.0, 0.0),
+                    *width,
+                    *height,
+                    &mut data,
+                )
+                .await;
+            assert!(flat.is_err());
+        }
+    }
+
+    #[test]
+    fn test_flat_bounding_box_mesh() {
+        //A slab with no height only has its top and bottom
+        let mut data = MeshData::default();
+        let slab = Cube {
+            bottom_left: Point3f::new(0.0, 0.0, 1.0),
+            top_right: Point3f::new(4.0, 2.0, 1.0),
+        };
+        bounding_box_mesh(&slab, &mut data);
+        assert_eq!(data.indices.len(), 4 * 3);
+        assert_eq!(data.material_indices.len(), 4);
+        assert!(data.normals.iter().all(|n| n.is_finite()));
+
+        //A line has nothing with any area
+        let mut data = MeshData::default();
+        let line = Cube {
+            bottom_left: Point3f::new(0.0, 0.0, 0.0),
+            top_right: Point3f::new(4.0, 0.0, 0.0),
+        };
+        bounding_box_mesh(&line, &mut data);
+        assert!(data.indices.is_empty());
+        assert!(data.normals.is_empty());
+    }
+}
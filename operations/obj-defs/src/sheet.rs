@@ -31,7 +31,7 @@ impl Data for Sheet {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, _conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, _conn: &dyn GeomKernel, _lod: Lod) -> Result<UpdateOutput, ObjError> {
         Ok(UpdateOutput::Other {
             data: json! ({
                 "type": "Sheet",
@@ -26,6 +26,14 @@ impl SymbolDef {
         }
     }
 
+    pub fn get_sym_file(&self) -> FileID {
+        self.sym_file
+    }
+
+    pub fn get_change(&self) -> ChangeID {
+        self.change
+    }
+
     pub fn set_bbox(&mut self, sym_file: FileID, change: ChangeID, bbox: Cube) {
         self.sym_file = sym_file;
         self.change = change;
@@ -44,7 +52,7 @@ impl Data for SymbolDef {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, _conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, _conn: &dyn GeomKernel, _lod: Lod) -> Result<UpdateOutput, ObjError> {
         Ok(UpdateOutput::FileRef {
             file: self.sym_file,
         })
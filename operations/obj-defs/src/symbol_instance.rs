@@ -40,7 +40,7 @@ impl Data for SymbolInstance {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, _conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, _conn: &dyn GeomKernel, _lod: Lod) -> Result<UpdateOutput, ObjError> {
         let source_id = match self.bbox.refer {
             Some(sym_def) => Some(sym_def.id),
             None => None,
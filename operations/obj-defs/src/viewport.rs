@@ -39,6 +39,10 @@ impl Viewport {
             scale,
         }
     }
+
+    pub fn get_sheet(&self) -> Option<ObjID> {
+        self.sheet
+    }
 }
 
 #[async_trait::async_trait]
@@ -52,7 +56,7 @@ impl Data for Viewport {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, _conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, _conn: &dyn GeomKernel, _lod: Lod) -> Result<UpdateOutput, ObjError> {
         match self.sheet {
             Some(sheet_id) => Ok(UpdateOutput::Other {
                 data: json! ({
@@ -1,4 +1,5 @@
 use crate::*;
+use cgmath::InnerSpace;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -46,6 +47,85 @@ impl Wall {
         let eighth = fourth + vert_offset;
         (first, second, third, fourth, fifth, sixth, seventh, eighth)
     }
+
+    //Where each opening starts and ends along the wall and how high its sill and top are, ordered along the wall
+    fn get_opening_spans(&self) -> Vec<(WorldCoord, WorldCoord, WorldCoord, WorldCoord)> {
+        let start = self.first_pt.info;
+        let dir = self.second_pt.info - start;
+        let length = dir.magnitude();
+        if length == 0.0 {
+            return Vec::new();
+        }
+        let dir = dir / length;
+        let mut spans = Vec::new();
+        for open in self.openings.iter().flatten() {
+            let first = (open.info.pt_1 - start).dot(dir);
+            let second = (open.info.pt_2 - start).dot(dir);
+            let begin = first.min(second).max(0.0);
+            let end = first.max(second).min(length);
+            let sill = (open.info.pt_1.z.min(open.info.pt_2.z) - start.z).max(0.0);
+            let top = (open.info.pt_3.z - start.z).min(self.height);
+            if end > begin && top > sill {
+                spans.push((begin, end, sill, top));
+            }
+        }
+        spans.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        spans
+    }
+
+    //The wall is split into solid pieces between the openings, with a lintel over each and a sill under any that don't start at the floor
+    async fn make_wall_with_openings(
+        &self,
+        geom_conn: &dyn GeomKernel,
+        data: &mut MeshData,
+    ) -> Result<(), ObjError> {
+        let spans = self.get_opening_spans();
+        if spans.is_empty() {
+            return geom_conn
+                .make_prism(
+                    &self.first_pt.info,
+                    &self.second_pt.info,
+                    self.width,
+                    self.height,
+                    data,
+                )
+                .await;
+        }
+        let start = self.first_pt.info;
+        let dir = self.second_pt.info - start;
+        let length = dir.magnitude();
+        let along = |dist: WorldCoord, up: WorldCoord| {
+            start + dir * (dist / length) + Vector3f::new(0.0, 0.0, up)
+        };
+        let mut pieces = Vec::new();
+        let mut done = 0.0;
+        for (begin, end, sill, top) in spans {
+            if begin > done {
+                pieces.push((along(done, 0.0), along(begin, 0.0), self.height));
+            }
+            if sill > 0.0 {
+                pieces.push((along(begin, 0.0), along(end, 0.0), sill));
+            }
+            if top < self.height {
+                pieces.push((along(begin, top), along(end, top), self.height - top));
+            }
+            done = end.max(done);
+        }
+        if done < length {
+            pieces.push((along(done, 0.0), self.second_pt.info, self.height));
+        }
+        let meshes = pieces.iter().map(|(first, second, height)| async move {
+            let mut piece = MeshData::default();
+            geom_conn
+                .make_prism(first, second, self.width, *height, &mut piece)
+                .await?;
+            Ok::<_, ObjError>(piece)
+        });
+        for piece in futures::future::try_join_all(meshes).await? {
+            data.append(piece);
+        }
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -59,13 +139,16 @@ impl Data for Wall {
         self.id = ObjID::new_v4();
     }
 
-    async fn update(&self, geom_conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    async fn update(&self, geom_conn: &dyn GeomKernel, lod: Lod) -> Result<UpdateOutput, ObjError> {
         let mut data = MeshData {
-            positions: Vec::new(),
-            indices: Vec::new(),
             metadata: Some(json! ({
                 "type": "Wall",
                 "traits": ["Position"],
+                //In material index order, sides then top and bottom
+                "materials": [
+                    { "name": "Wall Face", "color": RGBA::new(230, 226, 216, 1.0) },
+                    { "name": "Wall Cut", "color": RGBA::new(120, 120, 120, 1.0) }
+                ],
                 "obj": {
                     "Width": self.width,
                     "Height": self.height,
@@ -73,16 +156,25 @@ impl Data for Wall {
                     "Second": self.second_pt.info
                 }
             })),
+            ..Default::default()
         };
-        geom_conn
-            .make_prism(
-                &self.first_pt.info,
-                &self.second_pt.info,
-                self.width,
-                self.height,
-                &mut data,
-            )
-            .await?;
+        match lod {
+            Lod::BoundingBox => {
+                bounding_box_mesh(&self.get_axis_aligned_bounding_box(), &mut data);
+            }
+            Lod::Simplified => {
+                geom_conn
+                    .make_prism(
+                        &self.first_pt.info,
+                        &self.second_pt.info,
+                        self.width,
+                        self.height,
+                        &mut data,
+                    )
+                    .await?;
+            }
+            Lod::Full => self.make_wall_with_openings(geom_conn, &mut data).await?,
+        }
         Ok(UpdateOutput::Mesh { data })
     }
 
@@ -488,3 +580,42 @@ impl DrawingViews for Wall {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn triangle_count(wall: &Wall, lod: Lod) -> usize {
+        match wall.update(&LocalKernel::new(), lod).await.unwrap() {
+            UpdateOutput::Mesh { data } => data.material_indices.len(),
+            other => panic!("Expected a mesh, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_levels_of_detail() {
+        let mut wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(10.0, 0.0, 0.0),
+            0.5,
+            3.0,
+        );
+        wall.openings.push(Some(UpdatableInfo::new(Plane::new(
+            Point3f::new(4.0, 0.0, 0.0),
+            Point3f::new(5.0, 0.0, 0.0),
+            Point3f::new(5.0, 0.0, 2.0),
+        ))));
+        assert_eq!(triangle_count(&wall, Lod::BoundingBox).await, 12);
+        assert_eq!(triangle_count(&wall, Lod::Simplified).await, 12);
+        //Solid on either side of the opening, with a lintel over it
+        assert_eq!(triangle_count(&wall, Lod::Full).await, 36);
+
+        //A window also has a sill under it
+        wall.openings[0] = Some(UpdatableInfo::new(Plane::new(
+            Point3f::new(4.0, 0.0, 1.0),
+            Point3f::new(5.0, 0.0, 1.0),
+            Point3f::new(5.0, 0.0, 2.0),
+        )));
+        assert_eq!(triangle_count(&wall, Lod::Full).await, 48);
+    }
+}
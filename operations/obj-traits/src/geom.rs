@@ -338,11 +338,21 @@ impl DrawElement2D {
     }
 }
 
-///3D triangle mesh data to draw in the model view
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+///3D triangle mesh data to draw in the model view.
+///normals, uvs and material_indices are left empty when whatever made the mesh doesn't provide them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct MeshData {
     pub positions: Vec<WorldCoord>,
     pub indices: Vec<u64>,
+    ///Three per position
+    #[serde(default)]
+    pub normals: Vec<WorldCoord>,
+    ///Two per position
+    #[serde(default)]
+    pub uvs: Vec<WorldCoord>,
+    ///One per triangle, indexing the "materials" palette in the metadata
+    #[serde(default)]
+    pub material_indices: Vec<u32>,
     pub metadata: Option<serde_json::Value>,
 }
 
@@ -353,6 +363,17 @@ impl MeshData {
         self.positions.push(pt.z);
         self.positions.push(-pt.y);
     }
+
+    ///Adds the triangles of another mesh to this one, keeping this one's metadata
+    pub fn append(&mut self, other: MeshData) {
+        let offset = (self.positions.len() / 3) as u64;
+        self.positions.extend(other.positions);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + offset));
+        self.normals.extend(other.normals);
+        self.uvs.extend(other.uvs);
+        self.material_indices.extend(other.material_indices);
+    }
 }
 
 ///2D line representations of objects to draw in viewports
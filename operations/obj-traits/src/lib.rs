@@ -33,6 +33,25 @@ pub enum UpdateOutput {
     Other { data: serde_json::Value },
 }
 
+///How much detail a representation has.  Coarser levels are cheaper to send and draw,
+///so clients can use them for objects that only take up a little of the screen.
+///The values are representation.LevelOfDetail's, which is how levels go over the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, IntoEnumIterator)]
+pub enum Lod {
+    ///Just the bounding box
+    BoundingBox = 2,
+    ///The overall shape, without details like openings
+    Simplified = 1,
+    Full = 0,
+}
+
+impl Lod {
+    ///The level a representation.LevelOfDetail value stands for
+    pub fn from_i32(value: i32) -> Option<Lod> {
+        Lod::into_enum_iter().find(|lod| *lod as i32 == value)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ObjError {
     #[error("Object {0:?} not found")]
@@ -97,10 +116,17 @@ pub fn other_error<T: std::fmt::Debug>(err: T) -> ObjError {
     ObjError::Other(format!("{:?}", err))
 }
 
+///Material index for the faces running around the sides of a prism
+pub const SIDE_MATERIAL: u32 = 0;
+///Material index for the top and bottom of a prism
+pub const CAP_MATERIAL: u32 = 1;
+
 #[async_trait::async_trait]
 pub trait GeomKernel: Send + Sync {
+    ///Fills in positions and indices, along with flat normals, UVs in world units and a material index
+    ///per triangle, which is CAP_MATERIAL for the top and bottom and SIDE_MATERIAL for everything else.
     async fn make_prism(
-        &mut self,
+        &self,
         first_pt: &Point3f,
         second_pt: &Point3f,
         width: f64,
@@ -197,7 +223,8 @@ pub trait Data: std::fmt::Debug + Send + Sync + downcast_rs::Downcast {
     fn get_id(&self) -> &ObjID;
     ///Used when copy/pasting an object, we need a new ID for the copied object
     fn reset_id(&mut self);
-    async fn update(&self, conn: &mut dyn GeomKernel) -> Result<UpdateOutput, ObjError> {
+    ///Objects without coarser representations give the same output at every level of detail
+    async fn update(&self, conn: &dyn GeomKernel, lod: Lod) -> Result<UpdateOutput, ObjError> {
         Ok(UpdateOutput::Empty)
     }
 
@@ -0,0 +1,224 @@
+use crate::*;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+///One property that differs between two versions of an object.
+///path is a JSON pointer into the object's serialization.
+///before or after is None when the property only exists on one side.
+#[derive(Debug, PartialEq)]
+pub struct PropertyDiff {
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+//Escapes a key for use in a JSON pointer
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_values(path: &str, before: &Value, after: &Value, diffs: &mut Vec<PropertyDiff>) {
+    match (before, after) {
+        (Value::Object(before_map), Value::Object(after_map)) => {
+            for (key, before_value) in before_map {
+                let path = format!("{}/{}", path, escape(key));
+                match after_map.get(key) {
+                    Some(after_value) => diff_values(&path, before_value, after_value, diffs),
+                    None => diffs.push(PropertyDiff {
+                        path,
+                        before: Some(before_value.clone()),
+                        after: None,
+                    }),
+                }
+            }
+            for (key, after_value) in after_map {
+                if !before_map.contains_key(key) {
+                    diffs.push(PropertyDiff {
+                        path: format!("{}/{}", path, escape(key)),
+                        before: None,
+                        after: Some(after_value.clone()),
+                    });
+                }
+            }
+        }
+        //Arrays that change length are reported whole, since matching up their elements is guesswork
+        (Value::Array(before_values), Value::Array(after_values))
+            if before_values.len() == after_values.len() =>
+        {
+            for (index, (before_value, after_value)) in
+                before_values.iter().zip(after_values).enumerate()
+            {
+                diff_values(
+                    &format!("{}/{}", path, index),
+                    before_value,
+                    after_value,
+                    diffs,
+                );
+            }
+        }
+        _ => {
+            if before != after {
+                diffs.push(PropertyDiff {
+                    path: String::from(path),
+                    before: Some(before.clone()),
+                    after: Some(after.clone()),
+                });
+            }
+        }
+    }
+}
+
+///Compares the serializations of two versions of an object, property by property
+pub fn diff_objects(before: &DataBox, after: &DataBox) -> Result<Vec<PropertyDiff>, ObjError> {
+    let before = serde_json::to_value(before)?;
+    let after = serde_json::to_value(after)?;
+    let mut diffs = Vec::new();
+    diff_values("", &before, &after, &mut diffs);
+    Ok(diffs)
+}
+
+///The version of an object that last changed one of its properties
+#[derive(Debug, PartialEq)]
+pub struct PropertyBlame {
+    pub path: String,
+    pub value: Value,
+    pub version: usize,
+}
+
+//Every value that isn't an object or array, keyed by its JSON pointer
+fn flatten(path: String, value: Value, leaves: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(format!("{}/{}", path, escape(&key)), value, leaves);
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                flatten(format!("{}/{}", path, index), value, leaves);
+            }
+        }
+        _ => {
+            leaves.insert(path, value);
+        }
+    }
+}
+
+///Finds which version last changed each property of the newest one.
+///versions are oldest first, and the blamed version is an index into them.
+pub fn blame_versions(versions: &[DataBox]) -> Result<Vec<PropertyBlame>, ObjError> {
+    let mut blamed: BTreeMap<String, (Value, usize)> = BTreeMap::new();
+    for (version, obj) in versions.iter().enumerate() {
+        let mut leaves = BTreeMap::new();
+        flatten(String::new(), serde_json::to_value(obj)?, &mut leaves);
+        let mut next = BTreeMap::new();
+        for (path, value) in leaves {
+            let blame = match blamed.remove(&path) {
+                Some((prev, prev_version)) if prev == value => (value, prev_version),
+                _ => (value, version),
+            };
+            next.insert(path, blame);
+        }
+        blamed = next;
+    }
+    Ok(blamed
+        .into_iter()
+        .map(|(path, (value, version))| PropertyBlame {
+            path,
+            value,
+            version,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_objects() {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(4.0, 0.0, 0.0),
+            0.1,
+            3.0,
+        );
+        let mut wider = wall.clone();
+        wider.width = 0.2;
+        let before: DataBox = Box::new(wall);
+        let after: DataBox = Box::new(wider);
+        let diffs = diff_objects(&before, &after).unwrap();
+        assert_eq!(
+            diffs,
+            vec![PropertyDiff {
+                path: String::from("/width"),
+                before: Some(json!(0.1)),
+                after: Some(json!(0.2)),
+            }]
+        );
+        assert!(diff_objects(&before, &before).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_values() {
+        let before = json!({"a/b": 1, "list": [1, 2], "gone": true, "short": [1]});
+        let after = json!({"a/b": 1, "list": [1, 3], "new": null, "short": [1, 2]});
+        let mut diffs = Vec::new();
+        diff_values("", &before, &after, &mut diffs);
+        assert_eq!(
+            diffs,
+            vec![
+                PropertyDiff {
+                    path: String::from("/gone"),
+                    before: Some(json!(true)),
+                    after: None,
+                },
+                PropertyDiff {
+                    path: String::from("/list/1"),
+                    before: Some(json!(2)),
+                    after: Some(json!(3)),
+                },
+                PropertyDiff {
+                    path: String::from("/short"),
+                    before: Some(json!([1])),
+                    after: Some(json!([1, 2])),
+                },
+                PropertyDiff {
+                    path: String::from("/new"),
+                    before: None,
+                    after: Some(json!(null)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blame_versions() {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(4.0, 0.0, 0.0),
+            0.1,
+            3.0,
+        );
+        let mut wider = wall.clone();
+        wider.width = 0.2;
+        let mut taller = wider.clone();
+        taller.height = 4.0;
+        let mut narrower_again = taller.clone();
+        narrower_again.width = 0.1;
+        let versions: Vec<DataBox> = vec![
+            Box::new(wall),
+            Box::new(wider),
+            Box::new(taller),
+            Box::new(narrower_again),
+        ];
+        let blame = blame_versions(&versions).unwrap();
+        let find = |path: &str| blame.iter().find(|blame| blame.path == path).unwrap();
+        assert_eq!(find("/width").version, 3);
+        assert_eq!(find("/width").value, json!(0.1));
+        assert_eq!(find("/height").version, 2);
+        assert_eq!(find("/type").version, 0);
+        assert!(blame.windows(2).all(|pair| pair[0].path < pair[1].path));
+    }
+}
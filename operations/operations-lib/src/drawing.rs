@@ -0,0 +1,331 @@
+use crate::*;
+use std::f64::consts::PI;
+
+///Millimeters per point, line thickness is specified in points.
+pub const MM_PER_POINT: f64 = 25.4 / 72.0;
+///Distance between hatch lines on the printed sheet, in millimeters.
+pub const HATCH_SPACING: f64 = 2.0;
+
+///A drawing element placed on a sheet.  Coordinates are in millimeters from the bottom left corner of the sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedElement {
+    pub obj_id: ObjID,
+    pub obj_type: String,
+    pub element: DrawElement2D,
+}
+
+///Everything needed to print a sheet.  Width and height are in millimeters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetLayout {
+    pub name: String,
+    pub width: f64,
+    pub height: f64,
+    pub elements: Vec<PlacedElement>,
+}
+
+pub fn get_view(views: &dyn DrawingViews, view: &ViewType) -> Option<DrawingData> {
+    match view {
+        ViewType::Top => Some(views.get_top()),
+        ViewType::Front => Some(views.get_front()),
+        ViewType::Left => Some(views.get_left()),
+        ViewType::Right => Some(views.get_right()),
+        ViewType::Back => Some(views.get_back()),
+        ViewType::Bottom => Some(views.get_bottom()),
+        ViewType::Custom { .. } => None,
+    }
+}
+
+///The object's type name, which is the same "type" the update metadata reports.
+pub fn get_obj_type(obj: &DataBox) -> String {
+    match serde_json::to_value(obj) {
+        Ok(json) => match json.get("type").and_then(|t| t.as_str()) {
+            Some(obj_type) => String::from(obj_type),
+            None => String::from("Unknown"),
+        },
+        Err(e) => {
+            warn!("Couldn't serialize object {}: {:?}", obj.get_id(), e);
+            String::from("Unknown")
+        }
+    }
+}
+
+///Collects the drawing elements of every object for a single axis view, still in world coordinates.
+pub fn collect_view(objs: &Vec<DataBox>, view: &ViewType) -> Vec<PlacedElement> {
+    let mut results = Vec::new();
+    for obj in objs {
+        if let Some(views) = obj.as_drawing_views() {
+            if let Some(data) = get_view(views, view) {
+                let obj_type = get_obj_type(obj);
+                for element in data.elements {
+                    results.push(PlacedElement {
+                        obj_id: *obj.get_id(),
+                        obj_type: obj_type.clone(),
+                        element,
+                    });
+                }
+            }
+        }
+    }
+    results
+}
+
+///Walks the viewports on the sheet and places every drawable object's view into sheet coordinates.
+pub fn layout_sheet(sheet_id: &ObjID, objs: &Vec<DataBox>) -> Result<SheetLayout, ObjError> {
+    let sheet = objs
+        .iter()
+        .find(|obj| obj.get_id() == sheet_id)
+        .ok_or(ObjError::ObjNotFound(*sheet_id))?;
+    let sheet = sheet
+        .downcast_ref::<Sheet>()
+        .ok_or(ObjError::ObjWrongType(*sheet_id, String::from("Sheet")))?;
+    let width = sheet.print_size.x * 1000.0;
+    let height = sheet.print_size.y * 1000.0;
+    let mut elements = Vec::new();
+    for obj in objs {
+        if let Some(viewport) = obj.downcast_ref::<Viewport>() {
+            if viewport.get_sheet() != Some(*sheet_id) {
+                continue;
+            }
+            if let ViewType::Custom { .. } = viewport.view {
+                warn!(
+                    "Viewport {} has a custom view, which can't be exported yet",
+                    viewport.get_id()
+                );
+                continue;
+            }
+            let view_elements = collect_view(objs, &viewport.view);
+            if let Some((min, max)) = get_extents(&view_elements) {
+                //The top left of the view's extents lines up with the viewport origin
+                let scale = viewport.scale * 1000.0;
+                let top_left = Point2f::new(
+                    viewport.origin.x * 1000.0,
+                    height - viewport.origin.y * 1000.0,
+                );
+                let offset = Point2f::new(top_left.x - min.x * scale, top_left.y - max.y * scale);
+                for mut placed in view_elements {
+                    placed.element.element =
+                        transform_element(&placed.element.element, scale, &offset);
+                    elements.push(placed);
+                }
+            }
+        }
+    }
+    Ok(SheetLayout {
+        name: sheet.name.clone(),
+        width,
+        height,
+        elements,
+    })
+}
+
+///Scales the element uniformly, then translates it by offset
+pub fn transform_element(element: &Element2D, scale: f64, offset: &Point2f) -> Element2D {
+    let xform = |pt: &Point2f| Point2f::new(pt.x * scale + offset.x, pt.y * scale + offset.y);
+    match element {
+        Element2D::Line(line) => {
+            Element2D::Line(Line2D::new(xform(&line.first), xform(&line.second)))
+        }
+        Element2D::Arc(arc) => Element2D::Arc(Arc2D::new(
+            xform(&arc.center),
+            arc.radius * scale,
+            arc.start_angle,
+            arc.end_angle,
+        )),
+        Element2D::Rect(rect) => Element2D::Rect(Rect2D::new(
+            xform(&rect.bottom_left),
+            xform(&rect.top_right),
+        )),
+        Element2D::Poly(poly) => Element2D::Poly(Poly2D::new(poly.pts.iter().map(xform).collect())),
+    }
+}
+
+///Returns the corners of the 2D bounding box of the element
+pub fn element_extents(element: &Element2D) -> (Point2f, Point2f) {
+    let pts = match element {
+        Element2D::Line(line) => vec![line.first, line.second],
+        Element2D::Arc(arc) => {
+            let (start, sweep) = arc_sweep(arc);
+            let mut pts = vec![arc_point(arc, start), arc_point(arc, start + sweep)];
+            //Include any quadrant points the arc passes through
+            let mut quadrant = (start / (PI / 2.0)).ceil() * (PI / 2.0);
+            while quadrant < start + sweep {
+                pts.push(arc_point(arc, quadrant));
+                quadrant += PI / 2.0;
+            }
+            pts
+        }
+        Element2D::Rect(rect) => vec![rect.bottom_left, rect.top_right],
+        Element2D::Poly(poly) => poly.pts.clone(),
+    };
+    let mut min = Point2f::new(f64::MAX, f64::MAX);
+    let mut max = Point2f::new(f64::MIN, f64::MIN);
+    for pt in pts {
+        min.x = min.x.min(pt.x);
+        min.y = min.y.min(pt.y);
+        max.x = max.x.max(pt.x);
+        max.y = max.y.max(pt.y);
+    }
+    (min, max)
+}
+
+///Returns the corners of the bounding box around all the elements, or None if there aren't any
+pub fn get_extents(elements: &Vec<PlacedElement>) -> Option<(Point2f, Point2f)> {
+    let mut result: Option<(Point2f, Point2f)> = None;
+    for placed in elements {
+        let (min, max) = element_extents(&placed.element.element);
+        result = match result {
+            Some((cur_min, cur_max)) => Some((
+                Point2f::new(cur_min.x.min(min.x), cur_min.y.min(min.y)),
+                Point2f::new(cur_max.x.max(max.x), cur_max.y.max(max.y)),
+            )),
+            None => Some((min, max)),
+        };
+    }
+    result
+}
+
+///Returns the start angle and the counterclockwise sweep of the arc, with the sweep in (0, 2PI]
+pub fn arc_sweep(arc: &Arc2D) -> (f64, f64) {
+    let start = arc.start_angle.0;
+    let mut sweep = (arc.end_angle.0 - start) % (2.0 * PI);
+    if sweep <= 0.0 {
+        sweep += 2.0 * PI;
+    }
+    (start, sweep)
+}
+
+pub fn arc_point(arc: &Arc2D, angle: f64) -> Point2f {
+    Point2f::new(
+        arc.center.x + arc.radius * angle.cos(),
+        arc.center.y + arc.radius * angle.sin(),
+    )
+}
+
+///Splits the arc into cubic bezier segments of at most 90 degrees.
+///Each segment is (start, first control, second control, end).
+pub fn arc_to_beziers(arc: &Arc2D) -> Vec<(Point2f, Point2f, Point2f, Point2f)> {
+    let (start, sweep) = arc_sweep(arc);
+    let num_segs = (sweep / (PI / 2.0)).ceil().max(1.0) as usize;
+    let seg_angle = sweep / num_segs as f64;
+    let k = 4.0 / 3.0 * (seg_angle / 4.0).tan() * arc.radius;
+    let mut results = Vec::new();
+    for i in 0..num_segs {
+        let a_0 = start + seg_angle * i as f64;
+        let a_1 = a_0 + seg_angle;
+        let p_0 = arc_point(arc, a_0);
+        let p_3 = arc_point(arc, a_1);
+        let p_1 = Point2f::new(p_0.x - k * a_0.sin(), p_0.y + k * a_0.cos());
+        let p_2 = Point2f::new(p_3.x + k * a_1.sin(), p_3.y - k * a_1.cos());
+        results.push((p_0, p_1, p_2, p_3));
+    }
+    results
+}
+
+///Dash pattern in millimeters for a named line type.  Unknown names get a plain dash.
+pub fn dash_pattern(name: &str) -> Vec<f64> {
+    match name.to_lowercase().as_str() {
+        "hidden" => vec![3.0, 1.5],
+        "center" => vec![6.0, 1.5, 1.5, 1.5],
+        "phantom" => vec![6.0, 1.5, 1.5, 1.5, 1.5, 1.5],
+        "dot" | "dotted" => vec![0.5, 1.0],
+        "dashdot" => vec![3.0, 1.0, 0.5, 1.0],
+        _ => vec![4.0, 2.0],
+    }
+}
+
+///Hatch line angles in degrees for a named hatch.  Unknown names get a single diagonal hatch.
+pub fn hatch_angles(name: &str) -> Vec<f64> {
+    match name.to_lowercase().as_str() {
+        "horizontal" => vec![0.0],
+        "vertical" => vec![90.0],
+        "cross" | "ansi37" => vec![45.0, 135.0],
+        "grid" => vec![0.0, 90.0],
+        _ => vec![45.0],
+    }
+}
+
+///Parallel hatch lines at the given angle covering the box between min and max.
+///Anything outside the element is expected to be clipped by the writer.
+pub fn hatch_segments(
+    min: &Point2f,
+    max: &Point2f,
+    angle_degrees: f64,
+    spacing: f64,
+) -> Vec<(Point2f, Point2f)> {
+    let angle = angle_degrees.to_radians();
+    let dir = (angle.cos(), angle.sin());
+    let norm = (-dir.1, dir.0);
+    let corners = [
+        (min.x, min.y),
+        (max.x, min.y),
+        (max.x, max.y),
+        (min.x, max.y),
+    ];
+    let along: Vec<f64> = corners.iter().map(|c| c.0 * dir.0 + c.1 * dir.1).collect();
+    let across: Vec<f64> = corners
+        .iter()
+        .map(|c| c.0 * norm.0 + c.1 * norm.1)
+        .collect();
+    let (along_min, along_max) = (
+        minimum_of_list(&along).unwrap(),
+        maximum_of_list(&along).unwrap(),
+    );
+    let (across_min, across_max) = (
+        minimum_of_list(&across).unwrap(),
+        maximum_of_list(&across).unwrap(),
+    );
+    let mut results = Vec::new();
+    let mut dist = (across_min / spacing).ceil() * spacing;
+    while dist <= across_max {
+        let start = Point2f::new(
+            norm.0 * dist + dir.0 * along_min,
+            norm.1 * dist + dir.1 * along_min,
+        );
+        let end = Point2f::new(
+            norm.0 * dist + dir.0 * along_max,
+            norm.1 * dist + dir.1 * along_max,
+        );
+        results.push((start, end));
+        dist += spacing;
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_sheet() {
+        let sheet = Sheet::new(String::from("A1"), Point2f::new(0.4, 0.3));
+        let sheet_id = *sheet.get_id();
+        let viewport = Viewport::new(sheet_id, ViewType::Top, Point2f::new(0.1, 0.1), 0.01);
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(10.0, 0.0, 0.0),
+            1.0,
+            3.0,
+        );
+        let objs: Vec<DataBox> = vec![Box::new(sheet), Box::new(viewport), Box::new(wall)];
+        let layout = layout_sheet(&sheet_id, &objs).unwrap();
+        assert_eq!(layout.width, 400.0);
+        assert_eq!(layout.height, 300.0);
+        assert_eq!(layout.elements.len(), 1);
+        assert_eq!(layout.elements[0].obj_type, "Wall");
+        let (min, max) = get_extents(&layout.elements).unwrap();
+        assert!((min.x - 100.0).abs() < 1e-9);
+        assert!((max.y - 200.0).abs() < 1e-9);
+        assert!((max.x - 200.0).abs() < 1e-9);
+        assert!((min.y - 180.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_arc_to_beziers() {
+        let arc = Arc2D::new(Point2f::new(0.0, 0.0), 1.0, radians(0.0), radians(PI));
+        let segs = arc_to_beziers(&arc);
+        assert_eq!(segs.len(), 2);
+        assert!((segs[0].0.x - 1.0).abs() < 1e-9);
+        assert!((segs[1].3.x + 1.0).abs() < 1e-9);
+        assert!(segs[1].3.y.abs() < 1e-9);
+    }
+}
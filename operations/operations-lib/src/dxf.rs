@@ -0,0 +1,497 @@
+use crate::*;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DxfUnits {
+    ///Used for sheets, which are laid out in millimeters
+    Millimeters,
+    ///Used for model views, which are in world coordinates
+    Meters,
+}
+
+impl DxfUnits {
+    //Dash patterns are in printed millimeters.  Model views assume they'll be printed at 1:100.
+    fn dash_divisor(self) -> f64 {
+        match self {
+            DxfUnits::Millimeters => 1.0,
+            DxfUnits::Meters => 10.0,
+        }
+    }
+}
+
+//The standard AutoCAD colors, 7 is black or white depending on the background.
+const ACI_COLORS: [(i32, (u8, u8, u8)); 10] = [
+    (1, (255, 0, 0)),
+    (2, (255, 255, 0)),
+    (3, (0, 255, 0)),
+    (4, (0, 255, 255)),
+    (5, (0, 0, 255)),
+    (6, (255, 0, 255)),
+    (7, (255, 255, 255)),
+    (7, (0, 0, 0)),
+    (8, (128, 128, 128)),
+    (9, (192, 192, 192)),
+];
+
+///Closest AutoCAD color index to the given color
+pub fn nearest_aci(color: &RGBA) -> i32 {
+    let dist = |rgb: &(u8, u8, u8)| {
+        let r = rgb.0 as i32 - color.r as i32;
+        let g = rgb.1 as i32 - color.g as i32;
+        let b = rgb.2 as i32 - color.b as i32;
+        r * r + g * g + b * b
+    };
+    ACI_COLORS
+        .iter()
+        .min_by_key(|(_, rgb)| dist(rgb))
+        .map(|(index, _)| *index)
+        .unwrap()
+}
+
+fn ltype_name(line_type: &LineType) -> String {
+    match line_type {
+        LineType::Solid => String::from("CONTINUOUS"),
+        LineType::Dashed { name } => name
+            .to_uppercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect(),
+    }
+}
+
+fn layer_name(obj_type: &str) -> String {
+    obj_type
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn pair<T: std::fmt::Display>(out: &mut String, code: i32, value: T) {
+    writeln!(out, "{:>3}\n{}", code, value).unwrap();
+}
+
+fn write_ltypes(out: &mut String, elements: &[PlacedElement], units: DxfUnits) {
+    let mut dashed = BTreeSet::new();
+    for placed in elements {
+        if let LineType::Dashed { name } = &placed.element.line_type {
+            dashed.insert(name.clone());
+        }
+    }
+    pair(out, 0, "TABLE");
+    pair(out, 2, "LTYPE");
+    pair(out, 70, dashed.len() + 1);
+    pair(out, 0, "LTYPE");
+    pair(out, 2, "CONTINUOUS");
+    pair(out, 70, 0);
+    pair(out, 3, "Solid line");
+    pair(out, 72, 65);
+    pair(out, 73, 0);
+    pair(out, 40, 0.0);
+    let mut written = BTreeSet::new();
+    for name in dashed {
+        let ltype = ltype_name(&LineType::Dashed { name: name.clone() });
+        if !written.insert(ltype.clone()) {
+            continue;
+        }
+        let pattern: Vec<f64> = dash_pattern(&name)
+            .iter()
+            .map(|len| len / units.dash_divisor())
+            .collect();
+        pair(out, 0, "LTYPE");
+        pair(out, 2, ltype);
+        pair(out, 70, 0);
+        pair(out, 3, &name);
+        pair(out, 72, 65);
+        pair(out, 73, pattern.len());
+        pair(out, 40, pattern.iter().sum::<f64>());
+        for (i, len) in pattern.iter().enumerate() {
+            //Even entries are dashes and odd entries are gaps, which DXF wants negative
+            let signed = if i % 2 == 0 { *len } else { -len };
+            pair(out, 49, signed);
+        }
+    }
+    pair(out, 0, "ENDTAB");
+}
+
+fn write_layers(out: &mut String, elements: &[PlacedElement]) {
+    let layers: BTreeSet<String> = elements
+        .iter()
+        .map(|placed| layer_name(&placed.obj_type))
+        .collect();
+    pair(out, 0, "TABLE");
+    pair(out, 2, "LAYER");
+    pair(out, 70, layers.len() + 1);
+    for layer in std::iter::once(String::from("0")).chain(layers) {
+        pair(out, 0, "LAYER");
+        pair(out, 2, layer);
+        pair(out, 70, 0);
+        pair(out, 62, 7);
+        pair(out, 6, "CONTINUOUS");
+    }
+    pair(out, 0, "ENDTAB");
+}
+
+fn write_common(out: &mut String, entity: &str, placed: &PlacedElement) {
+    pair(out, 0, entity);
+    pair(out, 8, layer_name(&placed.obj_type));
+    pair(out, 6, ltype_name(&placed.element.line_type));
+    pair(out, 62, nearest_aci(&placed.element.line_color));
+}
+
+fn write_polyline(out: &mut String, placed: &PlacedElement, pts: &[Point2f]) {
+    write_common(out, "POLYLINE", placed);
+    pair(out, 66, 1);
+    pair(out, 10, 0.0);
+    pair(out, 20, 0.0);
+    pair(out, 30, 0.0);
+    pair(out, 70, 1);
+    for pt in pts {
+        pair(out, 0, "VERTEX");
+        pair(out, 8, layer_name(&placed.obj_type));
+        pair(out, 10, pt.x);
+        pair(out, 20, pt.y);
+        pair(out, 30, 0.0);
+    }
+    pair(out, 0, "SEQEND");
+    pair(out, 8, layer_name(&placed.obj_type));
+}
+
+fn write_entity(out: &mut String, placed: &PlacedElement) {
+    match &placed.element.element {
+        Element2D::Line(line) => {
+            write_common(out, "LINE", placed);
+            pair(out, 10, line.first.x);
+            pair(out, 20, line.first.y);
+            pair(out, 30, 0.0);
+            pair(out, 11, line.second.x);
+            pair(out, 21, line.second.y);
+            pair(out, 31, 0.0);
+        }
+        Element2D::Arc(arc) => {
+            let (start, sweep) = arc_sweep(arc);
+            write_common(out, "ARC", placed);
+            pair(out, 10, arc.center.x);
+            pair(out, 20, arc.center.y);
+            pair(out, 30, 0.0);
+            pair(out, 40, arc.radius);
+            pair(out, 50, start.to_degrees());
+            pair(out, 51, (start + sweep).to_degrees());
+        }
+        Element2D::Rect(rect) => {
+            let pts = [
+                rect.bottom_left,
+                Point2f::new(rect.top_right.x, rect.bottom_left.y),
+                rect.top_right,
+                Point2f::new(rect.bottom_left.x, rect.top_right.y),
+            ];
+            write_polyline(out, placed, &pts);
+        }
+        Element2D::Poly(poly) => {
+            if !poly.pts.is_empty() {
+                write_polyline(out, placed, &poly.pts);
+            }
+        }
+    }
+}
+
+///Writes the elements out as an ASCII DXF (R12), with a layer per object type.
+///R12 has no $INSUNITS, so the units only decide how long dashes are.
+pub fn elements_to_dxf(elements: &Vec<PlacedElement>, units: DxfUnits) -> String {
+    let mut out = String::new();
+    pair(&mut out, 0, "SECTION");
+    pair(&mut out, 2, "HEADER");
+    pair(&mut out, 9, "$ACADVER");
+    pair(&mut out, 1, "AC1009");
+    pair(&mut out, 0, "ENDSEC");
+
+    pair(&mut out, 0, "SECTION");
+    pair(&mut out, 2, "TABLES");
+    write_ltypes(&mut out, elements, units);
+    write_layers(&mut out, elements);
+    pair(&mut out, 0, "ENDSEC");
+
+    pair(&mut out, 0, "SECTION");
+    pair(&mut out, 2, "ENTITIES");
+    for placed in elements {
+        write_entity(&mut out, placed);
+    }
+    pair(&mut out, 0, "ENDSEC");
+    pair(&mut out, 0, "EOF");
+    out
+}
+
+//Meters per drawing unit for the $INSUNITS values we understand.  Unitless drawings are assumed to be in meters.
+fn meters_per_unit(insunits: i32) -> f64 {
+    match insunits {
+        1 => 0.0254,
+        2 => 0.3048,
+        4 => 0.001,
+        5 => 0.01,
+        _ => 1.0,
+    }
+}
+
+fn read_pairs(data: &str) -> Vec<(i32, &str)> {
+    let mut results = Vec::new();
+    let mut lines = data.lines();
+    while let (Some(code), Some(value)) = (lines.next(), lines.next()) {
+        match code.trim().parse::<i32>() {
+            Ok(code) => results.push((code, value.trim())),
+            Err(e) => {
+                warn!("Bad DXF group code {:?}: {:?}", code, e);
+                break;
+            }
+        }
+    }
+    results
+}
+
+fn parse_coord(value: &str) -> Result<f64, ObjError> {
+    value
+        .parse::<f64>()
+        .map_err(|e| ObjError::Other(format!("Bad DXF coordinate {:?}: {:?}", value, e)))
+}
+
+//One entity's worth of group codes
+struct DxfEntity<'a> {
+    name: &'a str,
+    codes: Vec<(i32, &'a str)>,
+}
+
+impl<'a> DxfEntity<'a> {
+    fn get(&self, code: i32) -> Option<&'a str> {
+        self.codes.iter().find(|(c, _)| *c == code).map(|(_, v)| *v)
+    }
+
+    fn get_coord(&self, code: i32) -> Result<f64, ObjError> {
+        match self.get(code) {
+            Some(value) => parse_coord(value),
+            None => Ok(0.0),
+        }
+    }
+
+    fn layer(&self) -> &'a str {
+        self.get(8).unwrap_or("0")
+    }
+}
+
+fn read_entities<'a>(pairs: &[(i32, &'a str)]) -> Vec<DxfEntity<'a>> {
+    let mut results = Vec::new();
+    let mut in_entities = false;
+    let mut cur: Option<DxfEntity> = None;
+    let mut iter = pairs.iter().peekable();
+    while let Some((code, value)) = iter.next() {
+        if *code == 0 {
+            if let Some(entity) = cur.take() {
+                results.push(entity);
+            }
+            match *value {
+                "SECTION" => {
+                    if let Some((2, name)) = iter.peek() {
+                        in_entities = *name == "ENTITIES";
+                    }
+                }
+                "ENDSEC" => in_entities = false,
+                name => {
+                    if in_entities {
+                        cur = Some(DxfEntity {
+                            name,
+                            codes: Vec::new(),
+                        });
+                    }
+                }
+            }
+        } else if let Some(entity) = &mut cur {
+            entity.codes.push((*code, value));
+        }
+    }
+    results
+}
+
+fn get_insunits(pairs: &[(i32, &str)]) -> i32 {
+    for window in pairs.windows(2) {
+        if window[0] == (9, "$INSUNITS") {
+            return window[1].1.parse().unwrap_or(0);
+        }
+    }
+    0
+}
+
+fn polyline_segments(pts: &[Point3f], closed: bool) -> Vec<(Point3f, Point3f)> {
+    let mut results = Vec::new();
+    for pair in pts.windows(2) {
+        results.push((pair[0], pair[1]));
+    }
+    if closed && pts.len() > 2 {
+        results.push((pts[pts.len() - 1], pts[0]));
+    }
+    results
+}
+
+///Reads the straight segments out of the LINE, LWPOLYLINE and POLYLINE entities on the given layers, in meters.
+///If no layers are given, every layer is read.  Polyline bulges are read as straight segments.
+pub fn read_dxf_segments(
+    data: &str,
+    layers: &[String],
+) -> Result<Vec<(Point3f, Point3f)>, ObjError> {
+    let pairs = read_pairs(data);
+    let scale = meters_per_unit(get_insunits(&pairs));
+    let entities = read_entities(&pairs);
+    let wanted =
+        |layer: &str| layers.is_empty() || layers.iter().any(|l| l.eq_ignore_ascii_case(layer));
+    let mut segments = Vec::new();
+    let mut iter = entities.iter();
+    while let Some(entity) = iter.next() {
+        match entity.name {
+            "LINE" if wanted(entity.layer()) => {
+                segments.push((
+                    Point3f::new(
+                        entity.get_coord(10)?,
+                        entity.get_coord(20)?,
+                        entity.get_coord(30)?,
+                    ),
+                    Point3f::new(
+                        entity.get_coord(11)?,
+                        entity.get_coord(21)?,
+                        entity.get_coord(31)?,
+                    ),
+                ));
+            }
+            "LWPOLYLINE" if wanted(entity.layer()) => {
+                let elevation = entity.get_coord(38)?;
+                let closed = entity.get_coord(70)? as i32 & 1 == 1;
+                let mut pts = Vec::new();
+                for (code, value) in &entity.codes {
+                    match code {
+                        10 => pts.push(Point3f::new(parse_coord(value)?, 0.0, elevation)),
+                        20 => {
+                            if let Some(pt) = pts.last_mut() {
+                                pt.y = parse_coord(value)?;
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                segments.extend(polyline_segments(&pts, closed));
+            }
+            "POLYLINE" => {
+                let closed = entity.get_coord(70)? as i32 & 1 == 1;
+                let mut pts = Vec::new();
+                for vertex in &mut iter {
+                    if vertex.name != "VERTEX" {
+                        break;
+                    }
+                    pts.push(Point3f::new(
+                        vertex.get_coord(10)?,
+                        vertex.get_coord(20)?,
+                        vertex.get_coord(30)?,
+                    ));
+                }
+                if wanted(entity.layer()) {
+                    segments.extend(polyline_segments(&pts, closed));
+                }
+            }
+            _ => (),
+        }
+    }
+    let results = segments
+        .into_iter()
+        .map(|(first, second)| (first * scale, second * scale))
+        .filter(|(first, second)| first != second)
+        .collect();
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elements_to_dxf() {
+        let mut arc = DrawElement2D::new_default(Element2D::Arc(Arc2D::new(
+            Point2f::new(1.0, 1.0),
+            2.0,
+            radians(0.0),
+            radians(std::f64::consts::PI / 2.0),
+        )));
+        arc.line_color = RGBA::new(250, 10, 10, 1.0);
+        arc.line_type = LineType::Dashed {
+            name: String::from("Hidden"),
+        };
+        let rect = DrawElement2D::new_default(Element2D::Rect(Rect2D::new(
+            Point2f::new(0.0, 0.0),
+            Point2f::new(2.0, 1.0),
+        )));
+        let elements = vec![
+            PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Door"),
+                element: arc,
+            },
+            PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Wall"),
+                element: rect,
+            },
+        ];
+        let dxf = elements_to_dxf(&elements, DxfUnits::Meters);
+        let pairs: Vec<&str> = dxf.lines().map(|l| l.trim()).collect();
+        assert_eq!(pairs.last(), Some(&"EOF"));
+        let layers: Vec<&str> = pairs
+            .windows(4)
+            .filter(|w| w[0] == "0" && w[1] == "LAYER" && w[2] == "2")
+            .map(|w| w[3])
+            .collect();
+        assert_eq!(layers, vec!["0", "Door", "Wall"]);
+        let arc_start = pairs.iter().position(|p| *p == "ARC").unwrap();
+        assert_eq!(
+            &pairs[arc_start + 1..arc_start + 7],
+            &["8", "Door", "6", "HIDDEN", "62", "1"]
+        );
+        assert_eq!(pairs.iter().filter(|p| **p == "VERTEX").count(), 4);
+        assert!(pairs.contains(&"-0.15"));
+    }
+
+    #[test]
+    fn test_read_dxf_segments() {
+        let wall = DrawElement2D::new_default(Element2D::Rect(Rect2D::new(
+            Point2f::new(0.0, 0.0),
+            Point2f::new(2000.0, 1000.0),
+        )));
+        let door = DrawElement2D::new_default(Element2D::Line(Line2D::new(
+            Point2f::new(0.0, 0.0),
+            Point2f::new(500.0, 0.0),
+        )));
+        let elements = vec![
+            PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Wall"),
+                element: wall,
+            },
+            PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Door"),
+                element: door,
+            },
+        ];
+        let dxf = elements_to_dxf(&elements, DxfUnits::Millimeters);
+        assert!(!dxf.contains("$INSUNITS"));
+        //Newer versions say what units they're in
+        let dxf = dxf.replacen("AC1009\n", "AC1015\n  9\n$INSUNITS\n 70\n4\n", 1);
+        let segments = read_dxf_segments(&dxf, &[String::from("wall")]).unwrap();
+        assert_eq!(segments.len(), 4);
+        assert_eq!(
+            segments[0],
+            (Point3f::new(0.0, 0.0, 0.0), Point3f::new(2.0, 0.0, 0.0))
+        );
+        assert_eq!(segments[3].1, Point3f::new(0.0, 0.0, 0.0));
+        let all = read_dxf_segments(&dxf, &[]).unwrap();
+        assert_eq!(all.len(), 5);
+
+        let lwpoly = "0\nSECTION\n2\nENTITIES\n0\nLWPOLYLINE\n8\nSurvey\n90\n3\n70\n0\n10\n0\n20\n0\n10\n5\n20\n0\n10\n5\n20\n5\n0\nENDSEC\n0\nEOF\n";
+        let segments = read_dxf_segments(lwpoly, &[String::from("Survey")]).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].1, Point3f::new(5.0, 5.0, 0.0));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::*;
+use cgmath::Vector4;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const TRIANGLES: u32 = 4;
+//Symbols nested deeper than this are assumed to contain themselves
+pub(crate) const MAX_SYMBOL_DEPTH: usize = 16;
+
+///Mesh positions are already in graphics space (y up), but instance transforms are in world space (z up).
+///This converts an instance transform so it can be applied to meshes in graphics space.
+pub fn to_graphic_transform(mat: &TransMat) -> TransMat {
+    let to_graphic = TransMat::from_cols(
+        Vector4::new(1.0, 0.0, 0.0, 0.0),
+        Vector4::new(0.0, 0.0, -1.0, 0.0),
+        Vector4::new(0.0, 1.0, 0.0, 0.0),
+        Vector4::new(0.0, 0.0, 0.0, 1.0),
+    );
+    let from_graphic = TransMat::from_cols(
+        Vector4::new(1.0, 0.0, 0.0, 0.0),
+        Vector4::new(0.0, 0.0, 1.0, 0.0),
+        Vector4::new(0.0, -1.0, 0.0, 0.0),
+        Vector4::new(0.0, 0.0, 0.0, 1.0),
+    );
+    to_graphic * mat * from_graphic
+}
+
+fn extras(id: &ObjID, metadata: &Option<Value>) -> Value {
+    json!({
+        "id": id.to_string(),
+        "metadata": metadata.clone().unwrap_or(Value::Null),
+    })
+}
+
+#[derive(Default)]
+struct GltfBuilder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    //Symbol contents are written once and shared by every instance of them
+    mesh_cache: HashMap<ObjID, Option<usize>>,
+}
+
+impl GltfBuilder {
+    fn add_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        while self.buffer.len() % 4 != 0 {
+            self.buffer.push(0);
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    fn add_mesh(&mut self, data: &MeshData) -> Option<usize> {
+        let count = data.positions.len() / 3;
+        if count == 0 || data.indices.is_empty() {
+            return None;
+        }
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let mut positions = Vec::with_capacity(count * 12);
+        for (i, coord) in data.positions[..count * 3].iter().enumerate() {
+            let coord = *coord as f32;
+            min[i % 3] = min[i % 3].min(coord);
+            max[i % 3] = max[i % 3].max(coord);
+            positions.extend_from_slice(&coord.to_le_bytes());
+        }
+        let mut indices = Vec::with_capacity(data.indices.len() * 4);
+        for index in &data.indices {
+            indices.extend_from_slice(&(*index as u32).to_le_bytes());
+        }
+        let position_view = self.add_view(&positions, ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": position_view,
+            "componentType": FLOAT,
+            "count": count,
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        let position_accessor = self.accessors.len() - 1;
+        let index_view = self.add_view(&indices, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": index_view,
+            "componentType": UNSIGNED_INT,
+            "count": data.indices.len(),
+            "type": "SCALAR",
+        }));
+        let index_accessor = self.accessors.len() - 1;
+        self.meshes.push(json!({
+            "primitives": [{
+                "attributes": { "POSITION": position_accessor },
+                "indices": index_accessor,
+                "mode": TRIANGLES,
+            }]
+        }));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn add_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn add_output(
+        &mut self,
+        id: &ObjID,
+        output: &UpdateOutput,
+        symbols: &HashMap<ObjID, Vec<(ObjID, UpdateOutput)>>,
+        depth: usize,
+    ) -> Option<usize> {
+        match output {
+            UpdateOutput::Mesh { data } => {
+                let mesh = match self.mesh_cache.get(id) {
+                    Some(mesh) => *mesh,
+                    None => {
+                        let mesh = self.add_mesh(data);
+                        self.mesh_cache.insert(*id, mesh);
+                        mesh
+                    }
+                }?;
+                Some(self.add_node(json!({
+                    "name": id.to_string(),
+                    "mesh": mesh,
+                    "extras": extras(id, &data.metadata),
+                })))
+            }
+            UpdateOutput::Instance { data } => {
+                let mut children = Vec::new();
+                let contents = data.source.as_ref().and_then(|source| symbols.get(source));
+                match contents {
+                    Some(contents) if depth < MAX_SYMBOL_DEPTH => {
+                        for (child_id, child) in contents {
+                            if let Some(node) = self.add_output(child_id, child, symbols, depth + 1)
+                            {
+                                children.push(node);
+                            }
+                        }
+                    }
+                    _ => warn!("Contents of symbol instance {} not found, skipping", id),
+                }
+                let transform = to_graphic_transform(&data.transform);
+                let matrix: &[f64; 16] = transform.as_ref();
+                let mut node = json!({
+                    "name": id.to_string(),
+                    "matrix": matrix.to_vec(),
+                    "extras": extras(id, &data.metadata),
+                });
+                if !children.is_empty() {
+                    node["children"] = json!(children);
+                }
+                Some(self.add_node(node))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn push_chunk(glb: &mut Vec<u8>, chunk_type: u32, data: &[u8], pad: u8) {
+    let padded = (data.len() + 3) / 4 * 4;
+    glb.extend_from_slice(&(padded as u32).to_le_bytes());
+    glb.extend_from_slice(&chunk_type.to_le_bytes());
+    glb.extend_from_slice(data);
+    glb.resize(glb.len() + padded - data.len(), pad);
+}
+
+///Writes the meshes out as a binary glTF 2.0 file, one node per object carrying its id and metadata as extras.
+///Symbol instances become nodes with the instance transform, whose children share the meshes of the symbol's contents.
+///symbols maps each symbol definition to the outputs of the objects in its symbol file.
+pub fn outputs_to_glb(
+    outputs: &[(ObjID, UpdateOutput)],
+    symbols: &HashMap<ObjID, Vec<(ObjID, UpdateOutput)>>,
+) -> Result<Vec<u8>, ObjError> {
+    let mut builder = GltfBuilder::default();
+    let mut roots = Vec::new();
+    for (id, output) in outputs {
+        if let Some(node) = builder.add_output(id, output, symbols, 0) {
+            roots.push(node);
+        }
+    }
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "kafka-cad" },
+        "scene": 0,
+        "scenes": [{}],
+    });
+    //glTF doesn't allow empty arrays, so leave these out when there's no geometry
+    if !builder.nodes.is_empty() {
+        gltf["scenes"] = json!([{ "nodes": roots }]);
+        gltf["nodes"] = json!(builder.nodes);
+    }
+    if !builder.meshes.is_empty() {
+        gltf["meshes"] = json!(builder.meshes);
+        gltf["accessors"] = json!(builder.accessors);
+        gltf["bufferViews"] = json!(builder.buffer_views);
+        gltf["buffers"] = json!([{ "byteLength": builder.buffer.len() }]);
+    }
+    let json_bytes = serde_json::to_vec(&gltf)?;
+
+    let mut glb = Vec::new();
+    glb.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+    glb.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    //Total length goes here once we know it
+    glb.extend_from_slice(&0u32.to_le_bytes());
+    push_chunk(&mut glb, CHUNK_JSON, &json_bytes, b' ');
+    if !builder.buffer.is_empty() {
+        push_chunk(&mut glb, CHUNK_BIN, &builder.buffer, 0);
+    }
+    let length = glb.len() as u32;
+    glb[8..12].copy_from_slice(&length.to_le_bytes());
+    Ok(glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn triangle() -> UpdateOutput {
+        UpdateOutput::Mesh {
+            data: MeshData {
+                positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+                indices: vec![0, 1, 2],
+                metadata: Some(json!({ "type": "Wall" })),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_outputs_to_glb() {
+        let wall_id = ObjID::new_v4();
+        let def_id = ObjID::new_v4();
+        let content_id = ObjID::new_v4();
+        let instance = |x: f64| UpdateOutput::Instance {
+            data: InstanceData {
+                transform: TransMat::from_translation(Vector3f::new(x, 2.0, 3.0)),
+                bbox: Cube::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 1.0, 1.0)),
+                source: Some(def_id),
+                metadata: None,
+            },
+        };
+        let outputs = vec![
+            (wall_id, triangle()),
+            (ObjID::new_v4(), instance(1.0)),
+            (ObjID::new_v4(), instance(5.0)),
+            (ObjID::new_v4(), UpdateOutput::Empty),
+        ];
+        let mut symbols = HashMap::new();
+        symbols.insert(def_id, vec![(content_id, triangle())]);
+        let glb = outputs_to_glb(&outputs, &symbols).unwrap();
+
+        assert_eq!(read_u32(&glb, 0), GLB_MAGIC);
+        assert_eq!(read_u32(&glb, 8) as usize, glb.len());
+        let json_len = read_u32(&glb, 12) as usize;
+        assert_eq!(read_u32(&glb, 16), CHUNK_JSON);
+        let gltf: Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        assert_eq!(read_u32(&glb, 24 + json_len), CHUNK_BIN);
+        //Both instances share the symbol's mesh
+        assert_eq!(gltf["meshes"].as_array().unwrap().len(), 2);
+        assert_eq!(gltf["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(gltf["scenes"][0]["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(gltf["buffers"][0]["byteLength"], 2 * (36 + 12));
+        let wall_node = &gltf["nodes"][0];
+        assert_eq!(wall_node["extras"]["id"], wall_id.to_string());
+        assert_eq!(wall_node["extras"]["metadata"]["type"], "Wall");
+        let instance_node = &gltf["nodes"][2];
+        assert_eq!(instance_node["children"], json!([1]));
+        let matrix = instance_node["matrix"].as_array().unwrap();
+        assert_eq!(matrix[12], 1.0);
+        assert_eq!(matrix[13], 3.0);
+        assert_eq!(matrix[14], -2.0);
+        assert_eq!(gltf["nodes"][3]["mesh"], gltf["nodes"][1]["mesh"]);
+        assert_eq!(gltf["accessors"][0]["max"], json!([1.0, 1.0, 0.0]));
+    }
+}
@@ -0,0 +1,1363 @@
+use crate::*;
+use cgmath::{InnerSpace, Transform};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const IFC_GUID_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_$";
+//Base elevations closer than this end up on the same storey
+const LEVEL_TOLERANCE: f64 = 0.001;
+//How far an opening sticks out past each face of its wall, so the void cuts cleanly
+const OPENING_MARGIN: f64 = 0.01;
+
+///Compresses a UUID into the 22 character base 64 form IFC uses for GlobalIds.
+pub fn to_ifc_guid(id: &ObjID) -> String {
+    let num = id.as_u128();
+    let mut guid = String::with_capacity(22);
+    //The first character only holds the top 2 bits, the other 21 hold 6 bits each
+    guid.push(IFC_GUID_CHARS[(num >> 126) as usize] as char);
+    for i in (0..21).rev() {
+        guid.push(IFC_GUID_CHARS[((num >> (i * 6)) & 0x3f) as usize] as char);
+    }
+    guid
+}
+
+fn real(val: f64) -> String {
+    let mut out = val.to_string();
+    if !out.contains('.') {
+        out.push('.');
+    }
+    out
+}
+
+fn text(val: &str) -> String {
+    let mut out = String::from("'");
+    for c in val.chars() {
+        match c {
+            '\'' => out.push_str("''"),
+            '\\' => out.push_str("\\\\"),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut buf = [0; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    write!(out, "\\X2\\{:04X}\\X0\\", unit).unwrap();
+                }
+            }
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn list(ids: &[usize]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+    format!("({})", ids.join(","))
+}
+
+struct StepWriter {
+    entities: Vec<String>,
+}
+
+impl StepWriter {
+    fn add(&mut self, entity: String) -> usize {
+        self.entities.push(entity);
+        self.entities.len()
+    }
+
+    fn point(&mut self, x: f64, y: f64, z: f64) -> usize {
+        self.add(format!(
+            "IFCCARTESIANPOINT(({},{},{}))",
+            real(x),
+            real(y),
+            real(z)
+        ))
+    }
+
+    fn direction(&mut self, x: f64, y: f64, z: f64) -> usize {
+        self.add(format!(
+            "IFCDIRECTION(({},{},{}))",
+            real(x),
+            real(y),
+            real(z)
+        ))
+    }
+
+    fn axis(&mut self, origin: &Point3f, x_dir: &Vector3f) -> usize {
+        let pt = self.point(origin.x, origin.y, origin.z);
+        let z_axis = self.direction(0.0, 0.0, 1.0);
+        let x_axis = self.direction(x_dir.x, x_dir.y, 0.0);
+        self.add(format!(
+            "IFCAXIS2PLACEMENT3D(#{},#{},#{})",
+            pt, z_axis, x_axis
+        ))
+    }
+
+    fn placement(
+        &mut self,
+        relative_to: Option<usize>,
+        origin: &Point3f,
+        x_dir: &Vector3f,
+    ) -> usize {
+        let axis = self.axis(origin, x_dir);
+        let relative_to = match relative_to {
+            Some(id) => format!("#{}", id),
+            None => String::from("$"),
+        };
+        self.add(format!("IFCLOCALPLACEMENT({},#{})", relative_to, axis))
+    }
+
+    //A box running along the local x axis from the placement origin, centered on it in y
+    fn extruded_box(&mut self, context: usize, length: f64, thickness: f64, height: f64) -> usize {
+        let center = self.add(format!(
+            "IFCCARTESIANPOINT(({},{}))",
+            real(length / 2.0),
+            real(0.0)
+        ));
+        let position = self.add(format!("IFCAXIS2PLACEMENT2D(#{},$)", center));
+        let profile = self.add(format!(
+            "IFCRECTANGLEPROFILEDEF(.AREA.,$,#{},{},{})",
+            position,
+            real(length),
+            real(thickness)
+        ));
+        let up = self.direction(0.0, 0.0, 1.0);
+        let solid = self.add(format!(
+            "IFCEXTRUDEDAREASOLID(#{},$,#{},{})",
+            profile,
+            up,
+            real(height)
+        ));
+        let body = self.add(format!(
+            "IFCSHAPEREPRESENTATION(#{},'Body','SweptSolid',(#{}))",
+            context, solid
+        ));
+        self.add(format!("IFCPRODUCTDEFINITIONSHAPE($,$,(#{}))", body))
+    }
+}
+
+fn new_guid() -> String {
+    to_ifc_guid(&ObjID::new_v4())
+}
+
+fn is_finite(pt: &Point3f) -> bool {
+    pt.x.is_finite() && pt.y.is_finite() && pt.z.is_finite()
+}
+
+fn level_index(levels: &mut Vec<f64>, elevation: f64) -> usize {
+    match levels
+        .iter()
+        .position(|level| (level - elevation).abs() < LEVEL_TOLERANCE)
+    {
+        Some(index) => index,
+        None => {
+            levels.push(elevation);
+            levels.len() - 1
+        }
+    }
+}
+
+///Writes the walls and doors in objs out as an IFC4 STEP file.
+///Walls get a storey for each distinct base elevation, and doors go on their host wall's storey.
+///Anything else, such as sheets, isn't part of the building model and is left out.
+pub fn objects_to_ifc(objs: &Vec<DataBox>, name: &str) -> String {
+    let mut walls = Vec::new();
+    let mut doors = Vec::new();
+    for obj in objs {
+        if let Some(wall) = obj.downcast_ref::<Wall>() {
+            if wall.first_pt.info == wall.second_pt.info {
+                warn!("Wall {} has no length, skipping", wall.get_id());
+                continue;
+            }
+            if !is_finite(&wall.first_pt.info) || !is_finite(&wall.second_pt.info) {
+                warn!("Wall {} isn't anywhere, skipping", wall.get_id());
+                continue;
+            }
+            walls.push(wall);
+        } else if let Some(door) = obj.downcast_ref::<Door>() {
+            if door.dir.length <= 0.0 {
+                warn!("Door {} has no length, skipping", door.get_id());
+                continue;
+            }
+            if !is_finite(&door.dir.line.pt_1) || !is_finite(&door.dir.line.pt_2) {
+                warn!("Door {} isn't anywhere, skipping", door.get_id());
+                continue;
+            }
+            doors.push(door);
+        }
+    }
+
+    let mut levels = Vec::new();
+    let wall_levels: Vec<usize> = walls
+        .iter()
+        .map(|wall| level_index(&mut levels, wall.first_pt.info.z))
+        .collect();
+    let door_hosts: Vec<Option<usize>> = doors
+        .iter()
+        .map(|door| match &door.dir.refer {
+            Some(refer) => walls.iter().position(|wall| *wall.get_id() == refer.id),
+            None => None,
+        })
+        .collect();
+    let door_levels: Vec<usize> = doors
+        .iter()
+        .zip(&door_hosts)
+        .map(|(door, host)| match host {
+            Some(wall_index) => wall_levels[*wall_index],
+            None => level_index(&mut levels, door.dir.line.pt_1.z),
+        })
+        .collect();
+    //Number the storeys from the bottom up
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    order.sort_by(|a, b| levels[*a].total_cmp(&levels[*b]));
+
+    let mut step = StepWriter {
+        entities: Vec::new(),
+    };
+    let unit = step.add(String::from("IFCSIUNIT(*,.LENGTHUNIT.,$,.METRE.)"));
+    let units = step.add(format!("IFCUNITASSIGNMENT((#{}))", unit));
+    let world_axis = step.axis(&Point3f::new(0.0, 0.0, 0.0), &Vector3f::unit_x());
+    let world = step.add(format!("IFCLOCALPLACEMENT($,#{})", world_axis));
+    let context = step.add(format!(
+        "IFCGEOMETRICREPRESENTATIONCONTEXT($,'Model',3,1.E-05,#{},$)",
+        world_axis
+    ));
+    let project = step.add(format!(
+        "IFCPROJECT('{}',$,{},$,$,$,$,(#{}),#{})",
+        new_guid(),
+        text(name),
+        context,
+        units
+    ));
+    let site = step.add(format!(
+        "IFCSITE('{}',$,'Site',$,$,#{},$,$,.ELEMENT.,$,$,$,$,$)",
+        new_guid(),
+        world
+    ));
+    let building_placement = step.placement(
+        Some(world),
+        &Point3f::new(0.0, 0.0, 0.0),
+        &Vector3f::unit_x(),
+    );
+    let building = step.add(format!(
+        "IFCBUILDING('{}',$,'Building',$,$,#{},$,$,.ELEMENT.,$,$,$)",
+        new_guid(),
+        building_placement
+    ));
+    step.add(format!(
+        "IFCRELAGGREGATES('{}',$,$,$,#{},(#{}))",
+        new_guid(),
+        project,
+        site
+    ));
+    step.add(format!(
+        "IFCRELAGGREGATES('{}',$,$,$,#{},(#{}))",
+        new_guid(),
+        site,
+        building
+    ));
+
+    let mut storeys = vec![0; levels.len()];
+    let mut storey_placements = vec![0; levels.len()];
+    for (number, index) in order.iter().enumerate() {
+        let elevation = levels[*index];
+        storey_placements[*index] = step.placement(
+            Some(building_placement),
+            &Point3f::new(0.0, 0.0, elevation),
+            &Vector3f::unit_x(),
+        );
+        storeys[*index] = step.add(format!(
+            "IFCBUILDINGSTOREY('{}',$,{},$,$,#{},$,$,.ELEMENT.,{})",
+            new_guid(),
+            text(&format!("Level {}", number + 1)),
+            storey_placements[*index],
+            real(elevation)
+        ));
+    }
+    if !storeys.is_empty() {
+        let ordered: Vec<usize> = order.iter().map(|index| storeys[*index]).collect();
+        step.add(format!(
+            "IFCRELAGGREGATES('{}',$,$,$,#{},{})",
+            new_guid(),
+            building,
+            list(&ordered)
+        ));
+    }
+
+    let mut contained = vec![Vec::new(); levels.len()];
+    let mut wall_ids = Vec::new();
+    for (wall, level) in walls.iter().zip(&wall_levels) {
+        let first = wall.first_pt.info;
+        let dir = wall.second_pt.info - first;
+        let origin = Point3f::new(first.x, first.y, first.z - levels[*level]);
+        let placement = step.placement(Some(storey_placements[*level]), &origin, &dir.normalize());
+        let shape = step.extruded_box(context, dir.magnitude(), wall.width * 2.0, wall.height);
+        let wall_id = step.add(format!(
+            "IFCWALL('{}',$,'Wall',$,$,#{},#{},$,.STANDARD.)",
+            to_ifc_guid(wall.get_id()),
+            placement,
+            shape
+        ));
+        contained[*level].push(wall_id);
+        wall_ids.push(wall_id);
+    }
+
+    for ((door, level), host) in doors.iter().zip(&door_levels).zip(&door_hosts) {
+        let first = door.dir.line.pt_1;
+        let dir = (door.dir.line.pt_2 - first).normalize();
+        let origin = Point3f::new(first.x, first.y, first.z - levels[*level]);
+        let placement = step.placement(Some(storey_placements[*level]), &origin, &dir);
+        let shape = step.extruded_box(context, door.dir.length, door.width * 2.0, door.height);
+        let door_id = step.add(format!(
+            "IFCDOOR('{}',$,'Door',$,$,#{},#{},$,{},{},.DOOR.,$,$)",
+            to_ifc_guid(door.get_id()),
+            placement,
+            shape,
+            real(door.height),
+            real(door.dir.length)
+        ));
+        contained[*level].push(door_id);
+        if let Some(wall_index) = host {
+            let wall = walls[*wall_index];
+            let opening_placement = step.placement(Some(storey_placements[*level]), &origin, &dir);
+            let opening_shape = step.extruded_box(
+                context,
+                door.dir.length,
+                wall.width * 2.0 + OPENING_MARGIN * 2.0,
+                door.height,
+            );
+            let opening = step.add(format!(
+                "IFCOPENINGELEMENT('{}',$,'Opening',$,$,#{},#{},$,.OPENING.)",
+                new_guid(),
+                opening_placement,
+                opening_shape
+            ));
+            step.add(format!(
+                "IFCRELVOIDSELEMENT('{}',$,$,$,#{},#{})",
+                new_guid(),
+                wall_ids[*wall_index],
+                opening
+            ));
+            step.add(format!(
+                "IFCRELFILLSELEMENT('{}',$,$,$,#{},#{})",
+                new_guid(),
+                opening,
+                door_id
+            ));
+        }
+    }
+
+    for (storey, elements) in storeys.iter().zip(&contained) {
+        if !elements.is_empty() {
+            step.add(format!(
+                "IFCRELCONTAINEDINSPATIALSTRUCTURE('{}',$,$,$,{},#{})",
+                new_guid(),
+                list(elements),
+                storey
+            ));
+        }
+    }
+
+    let mut ifc = String::new();
+    writeln!(ifc, "ISO-10303-21;").unwrap();
+    writeln!(ifc, "HEADER;").unwrap();
+    writeln!(
+        ifc,
+        "FILE_DESCRIPTION(('ViewDefinition [ReferenceView]'),'2;1');"
+    )
+    .unwrap();
+    writeln!(
+        ifc,
+        "FILE_NAME({},'',(''),(''),'','','');",
+        text(&format!("{}.ifc", name))
+    )
+    .unwrap();
+    writeln!(ifc, "FILE_SCHEMA(('IFC4'));").unwrap();
+    writeln!(ifc, "ENDSEC;").unwrap();
+    writeln!(ifc, "DATA;").unwrap();
+    for (i, entity) in step.entities.iter().enumerate() {
+        writeln!(ifc, "#{}={};", i + 1, entity).unwrap();
+    }
+    writeln!(ifc, "ENDSEC;").unwrap();
+    writeln!(ifc, "END-ISO-10303-21;").unwrap();
+    ifc
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StepValue {
+    Ref(usize),
+    Number(f64),
+    Text(String),
+    Enum(String),
+    List(Vec<StepValue>),
+    Typed(String, Vec<StepValue>),
+    Unset,
+}
+
+impl StepValue {
+    fn as_ref_id(&self) -> Option<usize> {
+        match self {
+            StepValue::Ref(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            StepValue::Number(val) => Some(*val),
+            StepValue::Typed(_, args) if args.len() == 1 => args[0].as_f64(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            StepValue::Text(val) | StepValue::Enum(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> &[StepValue] {
+        match self {
+            StepValue::List(vals) => vals,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StepEntity {
+    name: String,
+    args: Vec<StepValue>,
+}
+
+impl StepEntity {
+    fn arg(&self, index: usize) -> &StepValue {
+        self.args.get(index).unwrap_or(&StepValue::Unset)
+    }
+}
+
+fn step_error(pos: usize, msg: &str) -> ObjError {
+    ObjError::Other(format!("Bad IFC file at byte {}: {}", pos, msg))
+}
+
+//Undoes the STEP string encoding, which only allows printable ASCII in the file
+//Only plain hex digits, since from_str_radix would also take a sign
+fn hex_value(text: &str) -> Option<u32> {
+    if !text.is_empty() && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        u32::from_str_radix(text, 16).ok()
+    } else {
+        None
+    }
+}
+
+//The UTF-16 units of a \X2\ escape, or None if it isn't whole groups of four hex digits
+fn decode_units(hex: &str) -> Option<Vec<u16>> {
+    if hex.len() % 4 != 0 {
+        return None;
+    }
+    (0..hex.len() / 4)
+        .map(|i| {
+            hex.get(i * 4..i * 4 + 4)
+                .and_then(hex_value)
+                .map(|unit| unit as u16)
+        })
+        .collect()
+}
+
+//Escapes that don't decode are kept as they're written
+fn decode_text(raw: &str) -> String {
+    let mut out = String::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find('\\') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("\\\\") {
+            out.push('\\');
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with("\\X2\\") {
+            if let Some(end) = rest.find("\\X0\\") {
+                if let Some(units) = rest.get(4..end).and_then(decode_units) {
+                    out.push_str(&String::from_utf16_lossy(&units));
+                    rest = &rest[end + 4..];
+                    continue;
+                }
+            }
+        } else if rest.starts_with("\\X\\") {
+            if let Some(byte) = rest.get(3..5).and_then(hex_value) {
+                out.push(byte as u8 as char);
+                rest = &rest[5..];
+                continue;
+            }
+        }
+        out.push('\\');
+        rest = &rest[1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+struct StepParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StepParser<'a> {
+    fn peek(&mut self) -> Option<u8> {
+        loop {
+            while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.data[self.pos..].starts_with(b"/*") {
+                self.pos = match self.find(b"*/") {
+                    Some(end) => end + 2,
+                    None => self.data.len(),
+                };
+            } else {
+                return self.data.get(self.pos).copied();
+            }
+        }
+    }
+
+    fn find(&self, pattern: &[u8]) -> Option<usize> {
+        self.data[self.pos..]
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+            .map(|index| index + self.pos)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ObjError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(step_error(self.pos, &format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn take_while<F: Fn(u8) -> bool>(&mut self, pred: F) -> String {
+        let start = self.pos;
+        while self.pos < self.data.len() && pred(self.data[self.pos]) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.data[start..self.pos]).into_owned()
+    }
+
+    fn keyword(&mut self) -> String {
+        self.peek();
+        self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+            .to_uppercase()
+    }
+
+    fn text(&mut self) -> Result<String, ObjError> {
+        self.expect(b'\'')?;
+        let mut raw = Vec::new();
+        loop {
+            match self.data.get(self.pos) {
+                Some(b'\'') => {
+                    self.pos += 1;
+                    if self.data.get(self.pos) == Some(&b'\'') {
+                        raw.push(b'\'');
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Some(c) => {
+                    raw.push(*c);
+                    self.pos += 1;
+                }
+                None => return Err(step_error(self.pos, "unterminated string")),
+            }
+        }
+        Ok(decode_text(&String::from_utf8_lossy(&raw)))
+    }
+
+    fn value(&mut self) -> Result<StepValue, ObjError> {
+        match self.peek() {
+            Some(b'#') => {
+                self.pos += 1;
+                let id = self.take_while(|c| c.is_ascii_digit());
+                id.parse()
+                    .map(StepValue::Ref)
+                    .map_err(|_| step_error(self.pos, "bad entity reference"))
+            }
+            Some(b'$') | Some(b'*') => {
+                self.pos += 1;
+                Ok(StepValue::Unset)
+            }
+            Some(b'\'') => Ok(StepValue::Text(self.text()?)),
+            Some(b'"') => {
+                self.pos += 1;
+                let binary = self.take_while(|c| c != b'"');
+                self.expect(b'"')?;
+                Ok(StepValue::Text(binary))
+            }
+            Some(b'.') => {
+                self.pos += 1;
+                let name = self.take_while(|c| c != b'.');
+                self.expect(b'.')?;
+                Ok(StepValue::Enum(name))
+            }
+            Some(b'(') => Ok(StepValue::List(self.list()?)),
+            Some(c) if c.is_ascii_digit() || c == b'-' || c == b'+' => {
+                let num = self.take_while(|c| {
+                    c.is_ascii_digit()
+                        || c == b'.'
+                        || c == b'-'
+                        || c == b'+'
+                        || c == b'E'
+                        || c == b'e'
+                });
+                num.parse()
+                    .map(StepValue::Number)
+                    .map_err(|_| step_error(self.pos, &format!("bad number {}", num)))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = self.keyword();
+                Ok(StepValue::Typed(name, self.list()?))
+            }
+            _ => Err(step_error(self.pos, "unexpected character")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<StepValue>, ObjError> {
+        self.expect(b'(')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(b')') {
+            self.pos += 1;
+            return Ok(values);
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b')') => {
+                    self.pos += 1;
+                    return Ok(values);
+                }
+                _ => return Err(step_error(self.pos, "expected ',' or ')'")),
+            }
+        }
+    }
+
+    fn entities(&mut self) -> Result<HashMap<usize, StepEntity>, ObjError> {
+        let mut entities = HashMap::new();
+        self.pos = match self.find(b"DATA;") {
+            Some(start) => start + 5,
+            None => return Err(step_error(0, "no DATA section")),
+        };
+        while self.peek() == Some(b'#') {
+            let id = match self.value()? {
+                StepValue::Ref(id) => id,
+                _ => return Err(step_error(self.pos, "expected an entity id")),
+            };
+            self.expect(b'=')?;
+            if self.peek() == Some(b'(') {
+                //Complex entities are a list of partial entities, none of which we need
+                self.pos += 1;
+                while self.peek() != Some(b')') {
+                    self.value()?;
+                }
+                self.pos += 1;
+            } else {
+                let name = self.keyword();
+                let args = self.list()?;
+                entities.insert(id, StepEntity { name, args });
+            }
+            self.expect(b';')?;
+        }
+        Ok(entities)
+    }
+}
+
+///Something in an IFC file that couldn't be brought in as an object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfcImportIssue {
+    pub step_id: usize,
+    pub entity: String,
+    pub global_id: String,
+    pub reason: String,
+}
+
+pub struct IfcImport {
+    pub objects: Vec<DataBox>,
+    pub issues: Vec<IfcImportIssue>,
+}
+
+//Building elements that have no matching object type yet, so they get reported rather than quietly dropped
+const UNMAPPED_ELEMENTS: &[&str] = &[
+    "IFCSLAB",
+    "IFCWINDOW",
+    "IFCCOLUMN",
+    "IFCBEAM",
+    "IFCROOF",
+    "IFCSTAIR",
+    "IFCSTAIRFLIGHT",
+    "IFCRAMP",
+    "IFCRAMPFLIGHT",
+    "IFCCOVERING",
+    "IFCCURTAINWALL",
+    "IFCMEMBER",
+    "IFCPLATE",
+    "IFCRAILING",
+    "IFCFOOTING",
+    "IFCPILE",
+    "IFCCHIMNEY",
+    "IFCSHADINGDEVICE",
+    "IFCBUILDINGELEMENTPROXY",
+    "IFCFURNISHINGELEMENT",
+    "IFCFURNITURE",
+];
+//Doors that don't say how thick they are get this half thickness when they have no host
+const DEFAULT_DOOR_WIDTH: WorldCoord = 0.05;
+
+enum Profile {
+    Rect { position: TransMat, x_dim: f64 },
+    Poly(Vec<Point3f>),
+}
+
+struct Extrusion {
+    profile: Profile,
+    y_dim: Option<f64>,
+    position: TransMat,
+    depth: f64,
+}
+
+struct IfcModel {
+    entities: HashMap<usize, StepEntity>,
+    scale: f64,
+}
+
+impl IfcModel {
+    fn get(&self, value: &StepValue) -> Option<&StepEntity> {
+        value.as_ref_id().and_then(|id| self.entities.get(&id))
+    }
+
+    fn get_named(&self, value: &StepValue, name: &str) -> Option<&StepEntity> {
+        self.get(value).filter(|entity| entity.name == name)
+    }
+
+    fn length_scale(&self) -> f64 {
+        for entity in self.entities.values() {
+            if entity.name != "IFCUNITASSIGNMENT" {
+                continue;
+            }
+            for unit_ref in entity.arg(0).as_list() {
+                let unit = match self.get(unit_ref) {
+                    Some(unit) => unit,
+                    None => continue,
+                };
+                if unit.arg(1).as_str() != Some("LENGTHUNIT") {
+                    continue;
+                }
+                match unit.name.as_str() {
+                    "IFCSIUNIT" => {
+                        return match unit.arg(2).as_str() {
+                            Some("MILLI") => 0.001,
+                            Some("CENTI") => 0.01,
+                            Some("DECI") => 0.1,
+                            Some("KILO") => 1000.0,
+                            _ => 1.0,
+                        }
+                    }
+                    "IFCCONVERSIONBASEDUNIT" => {
+                        let name = unit.arg(2).as_str().unwrap_or("").to_uppercase();
+                        if name.contains("FOOT") {
+                            return 0.3048;
+                        } else if name.contains("INCH") {
+                            return 0.0254;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        1.0
+    }
+
+    fn length(&self, value: &StepValue) -> Option<f64> {
+        value.as_f64().map(|val| val * self.scale)
+    }
+
+    fn point(&self, value: &StepValue) -> Option<Point3f> {
+        let coords: Vec<f64> = self
+            .get_named(value, "IFCCARTESIANPOINT")?
+            .arg(0)
+            .as_list()
+            .iter()
+            .filter_map(|coord| self.length(coord))
+            .collect();
+        match coords.len() {
+            2 => Some(Point3f::new(coords[0], coords[1], 0.0)),
+            3 => Some(Point3f::new(coords[0], coords[1], coords[2])),
+            _ => None,
+        }
+    }
+
+    fn direction(&self, value: &StepValue) -> Option<Vector3f> {
+        let coords: Vec<f64> = self
+            .get_named(value, "IFCDIRECTION")?
+            .arg(0)
+            .as_list()
+            .iter()
+            .filter_map(|coord| coord.as_f64())
+            .collect();
+        let dir = match coords.len() {
+            2 => Vector3f::new(coords[0], coords[1], 0.0),
+            3 => Vector3f::new(coords[0], coords[1], coords[2]),
+            _ => return None,
+        };
+        if dir.magnitude2() > 0.0 {
+            Some(dir.normalize())
+        } else {
+            None
+        }
+    }
+
+    fn axis_placement(&self, value: &StepValue) -> TransMat {
+        let entity = match self.get(value) {
+            Some(entity) => entity,
+            None => return identity_mat(),
+        };
+        let origin = self
+            .point(entity.arg(0))
+            .unwrap_or_else(|| Point3f::new(0.0, 0.0, 0.0));
+        let (z_axis, ref_dir) = match entity.name.as_str() {
+            "IFCAXIS2PLACEMENT3D" => (
+                self.direction(entity.arg(1))
+                    .unwrap_or_else(Vector3f::unit_z),
+                self.direction(entity.arg(2))
+                    .unwrap_or_else(Vector3f::unit_x),
+            ),
+            "IFCAXIS2PLACEMENT2D" => (
+                Vector3f::unit_z(),
+                self.direction(entity.arg(1))
+                    .unwrap_or_else(Vector3f::unit_x),
+            ),
+            _ => return identity_mat(),
+        };
+        let mut x_axis = ref_dir - z_axis * ref_dir.dot(z_axis);
+        if x_axis.magnitude2() < 1e-12 {
+            x_axis = z_axis.cross(Vector3f::unit_y());
+        }
+        let x_axis = x_axis.normalize();
+        let y_axis = z_axis.cross(x_axis);
+        TransMat::from_cols(
+            x_axis.extend(0.0),
+            y_axis.extend(0.0),
+            z_axis.extend(0.0),
+            origin.to_homogeneous(),
+        )
+    }
+
+    fn local_placement(&self, value: &StepValue, depth: usize) -> TransMat {
+        match self.get_named(value, "IFCLOCALPLACEMENT") {
+            //Stop following broken files that place things relative to themselves
+            Some(placement) if depth < 64 => {
+                self.local_placement(placement.arg(0), depth + 1)
+                    * self.axis_placement(placement.arg(1))
+            }
+            _ => identity_mat(),
+        }
+    }
+
+    fn representation_items(&self, product: &StepEntity, identifier: &str) -> Vec<&StepEntity> {
+        let mut items = Vec::new();
+        let shape = match self.get(product.arg(6)) {
+            Some(shape) => shape,
+            None => return items,
+        };
+        for rep_ref in shape.arg(2).as_list() {
+            if let Some(rep) = self.get(rep_ref) {
+                if rep.arg(1).as_str() == Some(identifier) {
+                    items.extend(
+                        rep.arg(3)
+                            .as_list()
+                            .iter()
+                            .filter_map(|item| self.get(item)),
+                    );
+                }
+            }
+        }
+        items
+    }
+
+    fn polyline_pts(&self, polyline: &StepEntity) -> Vec<Point3f> {
+        polyline
+            .arg(0)
+            .as_list()
+            .iter()
+            .filter_map(|pt| self.point(pt))
+            .collect()
+    }
+
+    fn axis_line(&self, product: &StepEntity) -> Option<(Point3f, Point3f)> {
+        for item in self.representation_items(product, "Axis") {
+            if item.name == "IFCPOLYLINE" {
+                let pts = self.polyline_pts(item);
+                if pts.len() >= 2 {
+                    return Some((pts[0], pts[pts.len() - 1]));
+                }
+            }
+        }
+        None
+    }
+
+    fn extrusion(&self, product: &StepEntity) -> Option<Extrusion> {
+        for item in self.representation_items(product, "Body") {
+            if item.name != "IFCEXTRUDEDAREASOLID" {
+                continue;
+            }
+            let position = self.axis_placement(item.arg(1));
+            let dir = self.direction(item.arg(2)).unwrap_or_else(Vector3f::unit_z);
+            if position.transform_vector(dir).z < 0.999 {
+                continue;
+            }
+            let depth = self.length(item.arg(3))?;
+            let area = self.get(item.arg(0))?;
+            let (profile, y_dim) = match area.name.as_str() {
+                "IFCRECTANGLEPROFILEDEF" => (
+                    Profile::Rect {
+                        position: self.axis_placement(area.arg(2)),
+                        x_dim: self.length(area.arg(3))?,
+                    },
+                    self.length(area.arg(4)),
+                ),
+                "IFCARBITRARYCLOSEDPROFILEDEF" => {
+                    let outer = self.get_named(area.arg(2), "IFCPOLYLINE")?;
+                    (Profile::Poly(self.polyline_pts(outer)), None)
+                }
+                _ => continue,
+            };
+            return Some(Extrusion {
+                profile,
+                y_dim,
+                position,
+                depth,
+            });
+        }
+        None
+    }
+
+    //The center line, half thickness and height of a vertically extruded wall or door
+    fn extruded_line(
+        &self,
+        product: &StepEntity,
+        placement: &TransMat,
+    ) -> Result<(Point3f, Point3f, Option<f64>, f64), String> {
+        let extrusion = self
+            .extrusion(product)
+            .ok_or_else(|| String::from("No vertically extruded body"))?;
+        let position = extrusion.position;
+        let to_world = placement * position;
+        match extrusion.profile {
+            Profile::Rect {
+                position: profile_position,
+                x_dim,
+            } => {
+                let frame = to_world * profile_position;
+                let first = frame.transform_point(Point3f::new(-x_dim / 2.0, 0.0, 0.0));
+                let second = frame.transform_point(Point3f::new(x_dim / 2.0, 0.0, 0.0));
+                Ok((
+                    first,
+                    second,
+                    extrusion.y_dim.map(|y_dim| y_dim / 2.0),
+                    extrusion.depth,
+                ))
+            }
+            Profile::Poly(pts) => {
+                let (axis_1, axis_2) = self
+                    .axis_line(product)
+                    .ok_or_else(|| String::from("Body isn't a rectangle and there's no axis"))?;
+                let pts: Vec<Point3f> =
+                    pts.iter().map(|pt| position.transform_point(*pt)).collect();
+                let dir = Vector3f::new(axis_2.x - axis_1.x, axis_2.y - axis_1.y, 0.0);
+                if dir.magnitude2() == 0.0 {
+                    return Err(String::from("Axis has no length"));
+                }
+                let perp = Vector3f::unit_z().cross(dir.normalize());
+                let offsets: Vec<f64> = pts.iter().map(|pt| (pt - axis_1).dot(perp)).collect();
+                let min = minimum_of_list(&offsets).map_err(|e| e.to_string())?;
+                let max = maximum_of_list(&offsets).map_err(|e| e.to_string())?;
+                let base = pts.iter().map(|pt| pt.z).fold(f64::MAX, f64::min);
+                let shift = perp * (min + max) / 2.0;
+                let first = Point3f::new(axis_1.x, axis_1.y, base) + shift;
+                let second = Point3f::new(axis_2.x, axis_2.y, base) + shift;
+                Ok((
+                    placement.transform_point(first),
+                    placement.transform_point(second),
+                    Some((max - min) / 2.0),
+                    extrusion.depth,
+                ))
+            }
+        }
+    }
+
+    fn wall(&self, wall: &StepEntity) -> Result<Wall, String> {
+        let placement = self.local_placement(wall.arg(5), 0);
+        let (first, second, width, height) = self.extruded_line(wall, &placement)?;
+        let width = width.ok_or_else(|| String::from("Couldn't find the wall thickness"))?;
+        Ok(Wall::new(first, second, width, height))
+    }
+
+    fn door(&self, door: &StepEntity) -> Result<(Point3f, Point3f, Option<f64>, f64), String> {
+        let placement = self.local_placement(door.arg(5), 0);
+        if let Ok(line) = self.extruded_line(door, &placement) {
+            return Ok(line);
+        }
+        //Fall back on the overall size, which runs along the door's x axis
+        match (self.length(door.arg(8)), self.length(door.arg(9))) {
+            (Some(height), Some(width)) if width > 0.0 => {
+                let first = placement.transform_point(Point3f::new(0.0, 0.0, 0.0));
+                let second = placement.transform_point(Point3f::new(width, 0.0, 0.0));
+                Ok((first, second, None, height))
+            }
+            _ => Err(String::from(
+                "Door has neither an overall size nor a rectangular body",
+            )),
+        }
+    }
+}
+
+fn issue(id: usize, entity: &StepEntity, reason: String) -> IfcImportIssue {
+    IfcImportIssue {
+        step_id: id,
+        entity: entity.name.clone(),
+        global_id: entity.arg(0).as_str().unwrap_or("").to_string(),
+        reason,
+    }
+}
+
+///Reads the walls and doors out of an IFC STEP file, hosting each door in the wall its opening voids.
+///Anything that couldn't be brought in is listed in the issues rather than failing the whole import.
+pub fn read_ifc(data: &str) -> Result<IfcImport, ObjError> {
+    let entities = StepParser {
+        data: data.as_bytes(),
+        pos: 0,
+    }
+    .entities()?;
+    let mut model = IfcModel {
+        entities,
+        scale: 1.0,
+    };
+    model.scale = model.length_scale();
+    let mut ids: Vec<usize> = model.entities.keys().copied().collect();
+    ids.sort_unstable();
+
+    //Doors fill openings, openings void walls
+    let mut opening_hosts = HashMap::new();
+    let mut door_openings = HashMap::new();
+    for id in &ids {
+        let entity = &model.entities[id];
+        match entity.name.as_str() {
+            "IFCRELVOIDSELEMENT" => {
+                if let (Some(wall), Some(opening)) =
+                    (entity.arg(4).as_ref_id(), entity.arg(5).as_ref_id())
+                {
+                    opening_hosts.insert(opening, wall);
+                }
+            }
+            "IFCRELFILLSELEMENT" => {
+                if let (Some(opening), Some(door)) =
+                    (entity.arg(4).as_ref_id(), entity.arg(5).as_ref_id())
+                {
+                    door_openings.insert(door, opening);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut objects: Vec<DataBox> = Vec::new();
+    let mut issues = Vec::new();
+    let mut walls = HashMap::new();
+    for id in &ids {
+        let entity = &model.entities[id];
+        match entity.name.as_str() {
+            "IFCWALL" | "IFCWALLSTANDARDCASE" | "IFCWALLELEMENTEDCASE" => {
+                match model.wall(entity) {
+                    Ok(wall) => {
+                        walls.insert(*id, objects.len());
+                        objects.push(Box::new(wall));
+                    }
+                    Err(reason) => issues.push(issue(*id, entity, reason)),
+                }
+            }
+            name if UNMAPPED_ELEMENTS.contains(&name) => {
+                issues.push(issue(*id, entity, String::from("No matching object type")))
+            }
+            _ => (),
+        }
+    }
+
+    for id in &ids {
+        let entity = &model.entities[id];
+        if entity.name != "IFCDOOR" {
+            continue;
+        }
+        let (mut first, mut second, width, height) = match model.door(entity) {
+            Ok(door) => door,
+            Err(reason) => {
+                issues.push(issue(*id, entity, reason));
+                continue;
+            }
+        };
+        let host = door_openings
+            .get(id)
+            .and_then(|opening| opening_hosts.get(opening))
+            .and_then(|wall| walls.get(wall))
+            .copied();
+        let host_wall = host.and_then(|index| objects[index].downcast_ref::<Wall>());
+        if let Some(wall) = host_wall {
+            //Doors are laid along their wall from the first point, so make sure they run the same way
+            if (second - first).dot(wall.second_pt.info - wall.first_pt.info) < 0.0 {
+                std::mem::swap(&mut first, &mut second);
+            }
+        }
+        let width = width
+            .or_else(|| host_wall.map(|wall| wall.width))
+            .unwrap_or(DEFAULT_DOOR_WIDTH);
+        let mut door: DataBox = Box::new(Door::new(first, second, width, height));
+        match host {
+            Some(index) => {
+                if let Err(e) = join_refs(
+                    &mut objects[index],
+                    &mut door,
+                    RefType::ProfilePlane,
+                    RefType::ProfileLine,
+                    &first,
+                ) {
+                    issues.push(issue(*id, entity, format!("Couldn't host door: {}", e)));
+                }
+            }
+            None => issues.push(issue(
+                *id,
+                entity,
+                String::from("Door isn't in an opening of an imported wall, so it has no host"),
+            )),
+        }
+        objects.push(door);
+    }
+    Ok(IfcImport { objects, issues })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ifc_guid() {
+        assert_eq!(to_ifc_guid(&ObjID::nil()), "0000000000000000000000");
+        assert_eq!(
+            to_ifc_guid(&ObjID::from_u128(u128::MAX)),
+            "3$$$$$$$$$$$$$$$$$$$$$"
+        );
+        let guid = to_ifc_guid(&ObjID::new_v4());
+        assert_eq!(guid.len(), 22);
+    }
+
+    #[test]
+    fn test_objects_to_ifc() {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(4.0, 0.0, 0.0),
+            0.1,
+            3.0,
+        );
+        let upper = Wall::new(
+            Point3f::new(0.0, 0.0, 3.0),
+            Point3f::new(0.0, 4.0, 3.0),
+            0.1,
+            3.0,
+        );
+        let mut door = Door::new(
+            Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(2.0, 0.0, 0.0),
+            0.05,
+            2.0,
+        );
+        let wall_line = wall.get_result(RefType::ProfileLine, 0).unwrap();
+        door.set_ref(
+            RefType::ProfileLine,
+            0,
+            wall_line,
+            RefID::new(*wall.get_id(), RefType::ProfileLine, 0),
+            &None,
+        );
+        let sheet = Sheet::new(String::from("Sheet"), Point2f::new(0.42, 0.297));
+        let wall_guid = to_ifc_guid(wall.get_id());
+        let door_guid = to_ifc_guid(door.get_id());
+        let objs: Vec<DataBox> = vec![
+            Box::new(wall),
+            Box::new(upper),
+            Box::new(door),
+            Box::new(sheet),
+        ];
+        let ifc = objects_to_ifc(&objs, "Test's file");
+        assert!(ifc.starts_with("ISO-10303-21;"));
+        assert!(ifc.contains("FILE_SCHEMA(('IFC4'));"));
+        assert!(ifc.contains("IFCPROJECT('"));
+        assert!(ifc.contains(",'Test''s file',"));
+        assert!(ifc.contains(&format!("IFCWALL('{}'", wall_guid)));
+        assert!(ifc.contains(&format!("IFCDOOR('{}'", door_guid)));
+        assert!(ifc.contains("'Level 1',$,$,#"));
+        assert!(ifc.contains("'Level 2',$,$,#"));
+        assert!(ifc.contains(".ELEMENT.,3.);"));
+        assert_eq!(ifc.matches("IFCWALL(").count(), 2);
+        assert_eq!(ifc.matches("IFCOPENINGELEMENT(").count(), 1);
+        assert_eq!(ifc.matches("IFCRELVOIDSELEMENT(").count(), 1);
+        assert_eq!(ifc.matches("IFCRELFILLSELEMENT(").count(), 1);
+        assert!(!ifc.contains("Sheet"));
+        assert!(ifc.contains("IFCRECTANGLEPROFILEDEF(.AREA.,$,#"));
+        assert!(ifc.contains(",4.,0.2)"));
+    }
+
+    #[test]
+    fn test_non_finite_objects_to_ifc() {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(4.0, 0.0, 0.0),
+            0.1,
+            3.0,
+        );
+        let lost = Wall::new(
+            Point3f::new(0.0, 0.0, f64::NAN),
+            Point3f::new(0.0, 4.0, f64::NAN),
+            0.1,
+            3.0,
+        );
+        let door = Door::new(
+            Point3f::new(1.0, f64::NAN, 0.0),
+            Point3f::new(2.0, 0.0, 0.0),
+            0.05,
+            2.0,
+        );
+        let wall_guid = to_ifc_guid(wall.get_id());
+        let lost_guid = to_ifc_guid(lost.get_id());
+        let door_guid = to_ifc_guid(door.get_id());
+        let objs: Vec<DataBox> = vec![Box::new(lost), Box::new(wall), Box::new(door)];
+        let ifc = objects_to_ifc(&objs, "Lost");
+        assert!(ifc.contains(&format!("IFCWALL('{}'", wall_guid)));
+        assert!(!ifc.contains(&format!("IFCWALL('{}'", lost_guid)));
+        assert!(!ifc.contains(&format!("IFCDOOR('{}'", door_guid)));
+        assert!(!ifc.contains("NaN"));
+    }
+
+    #[test]
+    fn test_read_ifc_round_trip() {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 3.0),
+            Point3f::new(4.0, 0.0, 3.0),
+            0.1,
+            3.0,
+        );
+        let mut door = Door::new(
+            Point3f::new(1.0, 0.0, 3.0),
+            Point3f::new(2.0, 0.0, 3.0),
+            0.05,
+            2.0,
+        );
+        let wall_line = wall.get_result(RefType::ProfileLine, 0).unwrap();
+        door.set_ref(
+            RefType::ProfileLine,
+            0,
+            wall_line,
+            RefID::new(*wall.get_id(), RefType::ProfileLine, 0),
+            &Some(RefResult::Point(Point3f::new(1.0, 0.0, 3.0))),
+        );
+        let objs: Vec<DataBox> = vec![Box::new(wall), Box::new(door)];
+        let import = read_ifc(&objects_to_ifc(&objs, "Round trip")).unwrap();
+        assert_eq!(import.issues, Vec::new());
+        assert_eq!(import.objects.len(), 2);
+        let wall = import.objects[0].downcast_ref::<Wall>().unwrap();
+        assert!((wall.first_pt.info - Point3f::new(0.0, 0.0, 3.0)).magnitude() < 1e-9);
+        assert!((wall.second_pt.info - Point3f::new(4.0, 0.0, 3.0)).magnitude() < 1e-9);
+        assert!((wall.width - 0.1).abs() < 1e-9);
+        assert!((wall.height - 3.0).abs() < 1e-9);
+        assert_eq!(wall.get_num_results_for_type(RefType::ProfilePlane), 1);
+        let door = import.objects[1].downcast_ref::<Door>().unwrap();
+        assert_eq!(door.dir.refer.unwrap().id, *wall.get_id());
+        assert!((door.dir.line.pt_1 - Point3f::new(1.0, 0.0, 3.0)).magnitude() < 1e-9);
+        assert!((door.dir.line.pt_2 - Point3f::new(2.0, 0.0, 3.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_read_ifc() {
+        let data = r#"ISO-10303-21;
+HEADER;
+FILE_SCHEMA(('IFC2X3'));
+ENDSEC;
+DATA;
+#1=IFCSIUNIT(*,.LENGTHUNIT.,.MILLI.,.METRE.);
+#2=IFCUNITASSIGNMENT((#1));
+#3=IFCCARTESIANPOINT((0.,0.,0.));
+#4=IFCAXIS2PLACEMENT3D(#3,$,$);
+/* The wall runs up the y axis from (1000, 0) */
+#5=IFCCARTESIANPOINT((1000.,0.,0.));
+#6=IFCDIRECTION((0.,1.));
+#7=IFCAXIS2PLACEMENT3D(#5,$,#6);
+#8=IFCLOCALPLACEMENT($,#4);
+#9=IFCLOCALPLACEMENT(#8,#7);
+#10=IFCPOLYLINE((#11,#12));
+#11=IFCCARTESIANPOINT((0.,0.));
+#12=IFCCARTESIANPOINT((5000.,0.));
+#13=IFCSHAPEREPRESENTATION($,'Axis','Curve2D',(#10));
+#14=IFCPOLYLINE((#15,#16,#17,#18,#15));
+#15=IFCCARTESIANPOINT((0.,0.));
+#16=IFCCARTESIANPOINT((5000.,0.));
+#17=IFCCARTESIANPOINT((5000.,-200.));
+#18=IFCCARTESIANPOINT((0.,-200.));
+#19=IFCARBITRARYCLOSEDPROFILEDEF(.AREA.,$,#14);
+#20=IFCEXTRUDEDAREASOLID(#19,#4,#21,2700.);
+#21=IFCDIRECTION((0.,0.,1.));
+#22=IFCSHAPEREPRESENTATION($,'Body','SweptSolid',(#20));
+#23=IFCPRODUCTDEFINITIONSHAPE($,$,(#13,#22));
+#24=IFCWALLSTANDARDCASE('2O2Fr$t4X7Zf8NOew3FLOH',$,'Wall ''A''',$,$,#9,#23,$);
+#25=IFCSLAB('3vB2YO$MX4xv5uCqZZG05x',$,'Floor',$,$,#8,$,$,.FLOOR.);
+#26=IFCCARTESIANPOINT((1000.,0.,0.));
+#27=IFCAXIS2PLACEMENT3D(#26,$,$);
+#28=IFCLOCALPLACEMENT(#9,#27);
+#29=IFCOPENINGELEMENT('1hqIFTRjfV6AWq_bMtnZwI',$,$,$,$,#28,$,$);
+#30=IFCRELVOIDSELEMENT('0B3fOEqoH6zQAwpOVjDcSC',$,$,$,#24,#29);
+#31=IFCDOOR('0LV8Pn$sD1wQnvLr_GGQ2g',$,'Door',$,$,#28,$,$,2100.,900.);
+#32=IFCRELFILLSELEMENT('1xXWXbGtb3rQfuAS0qbpJ_',$,$,$,#29,#31);
+#33=IFCDOOR('2xXWXbGtb3rQfuAS0qbpJ_',$,'Loose door',$,$,#8,$,$,$,$);
+#34=(IFCNAMEDUNIT(*,.LENGTHUNIT.)IFCSIUNIT());
+ENDSEC;
+END-ISO-10303-21;
+"#;
+        let import = read_ifc(data).unwrap();
+        assert_eq!(import.objects.len(), 2);
+        let wall = import.objects[0].downcast_ref::<Wall>().unwrap();
+        //The profile sits on one side of the axis, so the center line is shifted off it
+        assert!((wall.first_pt.info - Point3f::new(1.1, 0.0, 0.0)).magnitude() < 1e-9);
+        assert!((wall.second_pt.info - Point3f::new(1.1, 5.0, 0.0)).magnitude() < 1e-9);
+        assert!((wall.width - 0.1).abs() < 1e-9);
+        assert!((wall.height - 2.7).abs() < 1e-9);
+        let door = import.objects[1].downcast_ref::<Door>().unwrap();
+        assert_eq!(door.dir.refer.unwrap().id, *wall.get_id());
+        assert!((door.dir.line.pt_1 - Point3f::new(1.1, 1.0, 0.0)).magnitude() < 1e-9);
+        assert!((door.dir.line.pt_2 - Point3f::new(1.1, 1.9, 0.0)).magnitude() < 1e-9);
+        assert!((door.height - 2.1).abs() < 1e-9);
+        assert!((door.width - 0.1).abs() < 1e-9);
+        assert_eq!(import.issues.len(), 2);
+        assert_eq!(import.issues[0].step_id, 25);
+        assert_eq!(import.issues[0].entity, "IFCSLAB");
+        assert_eq!(import.issues[0].global_id, "3vB2YO$MX4xv5uCqZZG05x");
+        assert_eq!(import.issues[1].step_id, 33);
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text("Caf\\X2\\00E9\\X0\\"), "Café");
+        assert_eq!(decode_text("a\\\\b"), "a\\b");
+        assert_eq!(decode_text("\\X\\E9t\\X\\"), "\u{e9}t\\X\\");
+        //Bad escapes, including ones cut off partway through a character, are left as text
+        assert_eq!(decode_text("\\X\\é"), "\\X\\é");
+        assert_eq!(decode_text("\\X2\\00é\\X0\\"), "\\X2\\00é\\X0\\");
+        assert_eq!(decode_text("\\X2\\00E9"), "\\X2\\00E9");
+        assert_eq!(decode_text("\\X\\+1"), "\\X\\+1");
+        assert_eq!(text("Café's"), "'Caf\\X2\\00E9\\X0\\''s'");
+    }
+}
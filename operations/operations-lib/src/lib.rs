@@ -2,10 +2,28 @@ use tracing::*;
 pub use obj_defs::*;
 pub use indexmap;
 
+mod diff;
+mod drawing;
+mod dxf;
+mod gltf;
+mod ifc;
 mod joins;
+mod mesh_export;
 mod ops;
+mod pdf;
+mod snapshot;
+mod svg;
 mod updates;
 
+pub use diff::*;
+pub use drawing::*;
+pub use dxf::*;
+pub use gltf::*;
+pub use ifc::*;
 pub use joins::*;
+pub use mesh_export::*;
 pub use ops::*;
+pub use pdf::*;
+pub use snapshot::*;
+pub use svg::*;
 pub use updates::*;
@@ -0,0 +1,307 @@
+use crate::*;
+use cgmath::{InnerSpace, Matrix, Matrix3, SquareMatrix, Transform};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+//Vertices closer together than this are treated as the same vertex when merging
+const MERGE_TOLERANCE: f64 = 1e-6;
+const STL_HEADER_SIZE: usize = 80;
+
+//Normals go through the inverse transpose so they stay perpendicular to their faces under non-uniform scales
+fn normal_matrix(transform: &TransMat) -> Matrix3<f64> {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    linear.invert().unwrap_or(linear).transpose()
+}
+
+fn add_meshes(
+    meshes: &mut Vec<(ObjID, MeshData)>,
+    id: &ObjID,
+    output: &UpdateOutput,
+    symbols: &HashMap<ObjID, Vec<(ObjID, UpdateOutput)>>,
+    transform: &TransMat,
+    depth: usize,
+) {
+    match output {
+        UpdateOutput::Mesh { data } => {
+            let mut data = data.clone();
+            for pt in data.positions.chunks_exact_mut(3) {
+                let moved = transform.transform_point(Point3f::new(pt[0], pt[1], pt[2]));
+                pt.copy_from_slice(&[moved.x, moved.y, moved.z]);
+            }
+            let normal_transform = normal_matrix(transform);
+            for normal in data.normals.chunks_exact_mut(3) {
+                let turned =
+                    (normal_transform * Vector3f::new(normal[0], normal[1], normal[2])).normalize();
+                normal.copy_from_slice(&[turned.x, turned.y, turned.z]);
+            }
+            meshes.push((*id, data));
+        }
+        UpdateOutput::Instance { data } => {
+            let contents = data.source.as_ref().and_then(|source| symbols.get(source));
+            match contents {
+                Some(contents) if depth < MAX_SYMBOL_DEPTH => {
+                    let transform = transform * to_graphic_transform(&data.transform);
+                    for (child_id, child) in contents {
+                        add_meshes(meshes, child_id, child, symbols, &transform, depth + 1);
+                    }
+                }
+                _ => warn!("Contents of symbol instance {} not found, skipping", id),
+            }
+        }
+        _ => (),
+    }
+}
+
+///Gathers every mesh, with the contents of symbol instances copied into place.
+pub fn flatten_outputs(
+    outputs: &[(ObjID, UpdateOutput)],
+    symbols: &HashMap<ObjID, Vec<(ObjID, UpdateOutput)>>,
+) -> Vec<(ObjID, MeshData)> {
+    let mut meshes = Vec::new();
+    for (id, output) in outputs {
+        add_meshes(&mut meshes, id, output, symbols, &identity_mat(), 0);
+    }
+    meshes
+}
+
+///Collapses vertices that sit on top of each other, then drops any triangles that collapsed with them.
+///Merged vertices can't keep separate normals or UVs, so those are dropped, but material indices are kept.
+///Triangles with corners past the end of the positions are dropped too.
+pub fn merge_vertices(data: &MeshData) -> MeshData {
+    let mut positions = Vec::new();
+    let mut merged: HashMap<(i64, i64, i64), u64> = HashMap::new();
+    let mut remap = Vec::with_capacity(data.positions.len() / 3);
+    for pt in data.positions.chunks_exact(3) {
+        let key = (
+            (pt[0] / MERGE_TOLERANCE).round() as i64,
+            (pt[1] / MERGE_TOLERANCE).round() as i64,
+            (pt[2] / MERGE_TOLERANCE).round() as i64,
+        );
+        let index = *merged.entry(key).or_insert_with(|| {
+            positions.extend_from_slice(pt);
+            (positions.len() / 3 - 1) as u64
+        });
+        remap.push(index);
+    }
+    let mut indices = Vec::with_capacity(data.indices.len());
+    let mut material_indices = Vec::with_capacity(data.material_indices.len());
+    for (i, tri) in data.indices.chunks_exact(3).enumerate() {
+        let tri: Option<Vec<u64>> = tri
+            .iter()
+            .map(|index| remap.get(*index as usize).copied())
+            .collect();
+        let tri = match tri {
+            Some(tri) => tri,
+            None => continue,
+        };
+        if tri[0] != tri[1] && tri[1] != tri[2] && tri[0] != tri[2] {
+            indices.extend(tri);
+            if let Some(material) = data.material_indices.get(i) {
+                material_indices.push(*material);
+            }
+        }
+    }
+    MeshData {
+        positions,
+        indices,
+        material_indices,
+        metadata: data.metadata.clone(),
+        ..Default::default()
+    }
+}
+
+fn get_point(data: &MeshData, index: u64) -> Option<Point3f> {
+    let start = (index as usize).checked_mul(3)?;
+    let pt = data.positions.get(start..start.checked_add(3)?)?;
+    Some(Point3f::new(pt[0], pt[1], pt[2]))
+}
+
+///Writes the meshes out as a Wavefront OBJ file, with one named object per mesh.
+///Coordinates stay y up, as most OBJ readers expect, and are multiplied by scale.
+///Triangles with corners past the end of their mesh's positions are left out.
+pub fn meshes_to_obj(meshes: &[(ObjID, MeshData)], scale: f64) -> String {
+    let mut obj = String::new();
+    let mut first_vertex = 1;
+    for (id, data) in meshes {
+        let count = data.positions.len() / 3;
+        writeln!(obj, "o {}", id).unwrap();
+        for pt in data.positions.chunks_exact(3) {
+            writeln!(
+                obj,
+                "v {} {} {}",
+                pt[0] * scale,
+                pt[1] * scale,
+                pt[2] * scale
+            )
+            .unwrap();
+        }
+        for tri in data.indices.chunks_exact(3) {
+            if tri.iter().any(|index| *index as usize >= count) {
+                continue;
+            }
+            writeln!(
+                obj,
+                "f {} {} {}",
+                tri[0] as usize + first_vertex,
+                tri[1] as usize + first_vertex,
+                tri[2] as usize + first_vertex
+            )
+            .unwrap();
+        }
+        first_vertex += count;
+    }
+    obj
+}
+
+///Writes the meshes out as a binary STL file.
+///STL has no units and is usually read z up, so coordinates go back to world space and are multiplied by scale.
+///Triangles with corners past the end of their mesh's positions are left out.
+pub fn meshes_to_stl(meshes: &[(ObjID, MeshData)], scale: f64) -> Vec<u8> {
+    let mut triangles = Vec::new();
+    for (_, data) in meshes {
+        for tri in data.indices.chunks_exact(3) {
+            let corners: Option<Vec<Point3f>> = tri
+                .iter()
+                .map(|index| {
+                    let pt = get_point(data, *index)?;
+                    Some(Point3f::new(pt.x * scale, -pt.z * scale, pt.y * scale))
+                })
+                .collect();
+            triangles.extend(corners);
+        }
+    }
+    let mut stl = vec![0; STL_HEADER_SIZE];
+    let header = b"kafka-cad binary STL";
+    stl[..header.len()].copy_from_slice(header);
+    stl.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for corners in triangles {
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let normal = if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        };
+        for val in &[normal.x, normal.y, normal.z] {
+            stl.extend_from_slice(&(*val as f32).to_le_bytes());
+        }
+        for pt in corners {
+            for val in &[pt.x, pt.y, pt.z] {
+                stl.extend_from_slice(&(*val as f32).to_le_bytes());
+            }
+        }
+        //Attribute byte count, which nothing uses
+        stl.extend_from_slice(&0u16.to_le_bytes());
+    }
+    stl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Two triangles making a square, with the shared corners duplicated like the kernel outputs them
+    fn square() -> MeshData {
+        MeshData {
+            positions: vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0,
+                1.0, 0.0,
+            ],
+            indices: vec![0, 1, 2, 3, 4, 5],
+            material_indices: vec![0, 1],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_vertices() {
+        let mut data = square();
+        //Add a triangle that collapses to a line
+        data.positions.extend(&[2.0, 0.0, 0.0, 2.0, 0.0000001, 0.0]);
+        data.indices.extend(&[1, 6, 7]);
+        data.material_indices.push(2);
+        let merged = merge_vertices(&data);
+        assert_eq!(merged.positions.len(), 15);
+        assert_eq!(merged.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(merged.material_indices, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_indices_past_positions() {
+        let mut data = square();
+        data.indices.extend(&[4, 5, 6]);
+        data.material_indices.push(2);
+        let merged = merge_vertices(&data);
+        assert_eq!(merged.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(merged.material_indices, vec![0, 1]);
+        let meshes = vec![(ObjID::new_v4(), data)];
+        let obj = meshes_to_obj(&meshes, 1.0);
+        assert_eq!(obj.matches("\nf ").count(), 2);
+        let stl = meshes_to_stl(&meshes, 1.0);
+        assert_eq!(stl.len(), STL_HEADER_SIZE + 4 + 2 * 50);
+    }
+
+    #[test]
+    fn test_flatten_outputs() {
+        let def_id = ObjID::new_v4();
+        let instance = UpdateOutput::Instance {
+            data: InstanceData {
+                transform: TransMat::from_translation(Vector3f::new(0.0, 5.0, 0.0)),
+                bbox: Cube::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 1.0, 1.0)),
+                source: Some(def_id),
+                metadata: None,
+            },
+        };
+        let mut symbols = HashMap::new();
+        symbols.insert(
+            def_id,
+            vec![(ObjID::new_v4(), UpdateOutput::Mesh { data: square() })],
+        );
+        let meshes = flatten_outputs(&[(ObjID::new_v4(), instance)], &symbols);
+        assert_eq!(meshes.len(), 1);
+        //World y is graphics -z
+        assert_eq!(&meshes[0].1.positions[..3], &[0.0, 0.0, -5.0]);
+    }
+
+    #[test]
+    fn test_normal_matrix() {
+        //Stretching a 45 degree face along x tips its normal towards y
+        let stretch = TransMat::from_nonuniform_scale(2.0, 1.0, 1.0);
+        let normal = normal_matrix(&stretch) * Vector3f::new(1.0, 1.0, 0.0);
+        assert_eq!(normal, Vector3f::new(0.5, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_meshes_to_obj() {
+        let first = ObjID::new_v4();
+        let meshes = vec![(first, square()), (ObjID::new_v4(), square())];
+        let obj = meshes_to_obj(&meshes, 1000.0);
+        assert!(obj.starts_with(&format!("o {}\nv 0 0 0\nv 1000 0 0\n", first)));
+        assert_eq!(obj.matches("\nv ").count(), 12);
+        assert!(obj.contains("f 1 2 3\n"));
+        assert!(obj.ends_with("f 10 11 12\n"));
+    }
+
+    #[test]
+    fn test_meshes_to_stl() {
+        let stl = meshes_to_stl(&[(ObjID::new_v4(), square())], 2.0);
+        assert_eq!(stl.len(), STL_HEADER_SIZE + 4 + 2 * 50);
+        let mut count = [0; 4];
+        count.copy_from_slice(&stl[80..84]);
+        assert_eq!(u32::from_le_bytes(count), 2);
+        let read_f32 = |offset: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&stl[offset..offset + 4]);
+            f32::from_le_bytes(bytes)
+        };
+        //The square faces graphics +z, which is world -y
+        assert_eq!(read_f32(84), 0.0);
+        assert_eq!(read_f32(88), -1.0);
+        assert_eq!(read_f32(92), 0.0);
+        //Second corner of the first triangle is scaled
+        assert_eq!(read_f32(108), 2.0);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use cgmath::InnerSpace;
 
 pub fn move_objects(objs: &mut Vec<DataBox>, delta: &Vector3f) {
     for obj in objs {
@@ -62,13 +63,130 @@ pub fn remove_objs_from_visibility_group(
 }
 
 pub async fn get_obj_update_info(
-    geom_conn: &mut GeomConn,
+    geom_conn: &dyn GeomKernel,
     obj: &DataBox,
+    lod: Lod,
 ) -> Result<(UpdateOutput, Option<DrawingRepresentations>), ObjError> {
-    let output = obj.update(geom_conn).await?;
+    let output = obj.update(geom_conn, lod).await?;
     let views_opt = match obj.as_drawing_views() {
         Some(views) => Some(views.get_views(ViewFlags::all())),
         None => None,
     };
     Ok((output, views_opt))
 }
+
+///Exports and the like want every object at full detail
+pub async fn get_update_outputs(
+    geom_conn: &dyn GeomKernel,
+    objs: &Vec<DataBox>,
+) -> Result<Vec<(ObjID, UpdateOutput)>, ObjError> {
+    //Updating them all at once lets the kernel batch their geometry
+    let updates = objs.iter().map(|obj| async move {
+        let output = obj.update(geom_conn, Lod::Full).await?;
+        Ok((*obj.get_id(), output))
+    });
+    futures::future::try_join_all(updates).await
+}
+
+///Finds the symbol file, and the offset into it, that each symbol definition in objs is defined by.
+pub fn get_symbol_files(objs: &Vec<DataBox>) -> Vec<(ObjID, FileID, ChangeID)> {
+    objs.iter()
+        .filter_map(|obj| obj.downcast_ref::<SymbolDef>())
+        .map(|def| (*def.get_id(), def.get_sym_file(), def.get_change()))
+        .collect()
+}
+
+///Creates a wall along each segment, then joins any walls whose endpoints are within tolerance of each other.
+pub fn create_joined_walls(
+    segments: &Vec<(Point3f, Point3f)>,
+    width: WorldCoord,
+    height: WorldCoord,
+    tolerance: WorldCoord,
+) -> Vec<DataBox> {
+    let mut walls: Vec<DataBox> = segments
+        .iter()
+        .map(|(first, second)| Box::new(Wall::new(*first, *second, width, height)) as DataBox)
+        .collect();
+    //Group wall endpoints that coincide.  Each entry is the location and the indices of the walls that meet there.
+    let mut clusters: Vec<(Point3f, Vec<usize>)> = Vec::new();
+    for (index, (first, second)) in segments.iter().enumerate() {
+        for pt in &[first, second] {
+            match clusters
+                .iter_mut()
+                .find(|(loc, _)| (*loc - **pt).magnitude() <= tolerance)
+            {
+                Some((_, members)) => members.push(index),
+                None => clusters.push((**pt, vec![index])),
+            }
+        }
+    }
+    for (loc, members) in clusters {
+        if members.len() < 2 {
+            continue;
+        }
+        let first_index = members[0];
+        for (i, other_index) in members.iter().skip(1).enumerate() {
+            if *other_index == first_index {
+                continue;
+            }
+            //Split the borrow so we can hold both walls at once
+            let (low, high) = walls.split_at_mut(std::cmp::max(first_index, *other_index));
+            let (first, other) = if first_index < *other_index {
+                (&mut low[first_index], &mut high[0])
+            } else {
+                (&mut high[0], &mut low[*other_index])
+            };
+            //The first two walls at a point join to each other, anything else at that point joins one way
+            let joined = if i == 0 {
+                join_refs(
+                    first,
+                    other,
+                    RefType::ProfilePoint,
+                    RefType::ProfilePoint,
+                    &loc,
+                )
+            } else {
+                snap_to_ref(other, first, RefType::ProfilePoint, &loc)
+            };
+            if let Err(e) = joined {
+                warn!("Couldn't join walls at {:?}: {}", loc, e);
+            }
+        }
+    }
+    walls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_joined_walls() {
+        let segments = vec![
+            (Point3f::new(0.0, 0.0, 0.0), Point3f::new(10.0, 0.0, 0.0)),
+            (
+                Point3f::new(10.001, 0.0, 0.0),
+                Point3f::new(10.0, 10.0, 0.0),
+            ),
+            (Point3f::new(10.0, 0.0, 0.0), Point3f::new(10.0, -10.0, 0.0)),
+            (Point3f::new(20.0, 20.0, 0.0), Point3f::new(30.0, 20.0, 0.0)),
+        ];
+        let walls = create_joined_walls(&segments, 0.5, 3.0, 0.01);
+        assert_eq!(walls.len(), 4);
+        let refers = |wall: &DataBox| -> Vec<ObjID> {
+            wall.get_refs()
+                .into_iter()
+                .flatten()
+                .filter(|r| r.owner.ref_type == RefType::ProfilePoint)
+                .map(|r| r.other.id)
+                .collect()
+        };
+        assert_eq!(refers(&walls[0]), vec![*walls[1].get_id()]);
+        assert_eq!(refers(&walls[1]), vec![*walls[0].get_id()]);
+        assert_eq!(refers(&walls[2]), vec![*walls[0].get_id()]);
+        assert!(refers(&walls[3]).is_empty());
+        //The second wall snapped onto the first wall's endpoint
+        let second = walls[1].downcast_ref::<Wall>().unwrap();
+        assert_eq!(second.first_pt.info, Point3f::new(10.0, 0.0, 0.0));
+    }
+}
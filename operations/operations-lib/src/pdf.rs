@@ -0,0 +1,240 @@
+use crate::*;
+use std::fmt::Write;
+
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+
+fn unit_color(rgba: &RGBA) -> String {
+    format!(
+        "{:.4} {:.4} {:.4}",
+        rgba.r as f64 / 255.0,
+        rgba.g as f64 / 255.0,
+        rgba.b as f64 / 255.0
+    )
+}
+
+///Keeps track of the transparency states the content stream needs, since PDF can't set alpha inline.
+struct AlphaStates {
+    states: Vec<(f32, f32)>,
+}
+
+impl AlphaStates {
+    fn get_name(&mut self, stroke: f32, fill: f32) -> String {
+        let index = match self.states.iter().position(|s| *s == (stroke, fill)) {
+            Some(index) => index,
+            None => {
+                self.states.push((stroke, fill));
+                self.states.len() - 1
+            }
+        };
+        format!("/GS{}", index)
+    }
+}
+
+fn write_path(content: &mut String, element: &Element2D) {
+    match element {
+        Element2D::Line(line) => {
+            writeln!(
+                content,
+                "{} {} m {} {} l",
+                line.first.x, line.first.y, line.second.x, line.second.y
+            )
+            .unwrap();
+        }
+        Element2D::Arc(arc) => {
+            for (i, (p_0, p_1, p_2, p_3)) in arc_to_beziers(arc).iter().enumerate() {
+                if i == 0 {
+                    writeln!(content, "{} {} m", p_0.x, p_0.y).unwrap();
+                }
+                writeln!(
+                    content,
+                    "{} {} {} {} {} {} c",
+                    p_1.x, p_1.y, p_2.x, p_2.y, p_3.x, p_3.y
+                )
+                .unwrap();
+            }
+        }
+        Element2D::Rect(rect) => {
+            writeln!(
+                content,
+                "{} {} {} {} re",
+                rect.bottom_left.x,
+                rect.bottom_left.y,
+                rect.top_right.x - rect.bottom_left.x,
+                rect.top_right.y - rect.bottom_left.y
+            )
+            .unwrap();
+        }
+        Element2D::Poly(poly) => {
+            for (i, pt) in poly.pts.iter().enumerate() {
+                let op = if i == 0 { "m" } else { "l" };
+                writeln!(content, "{} {} {}", pt.x, pt.y, op).unwrap();
+            }
+            if !poly.pts.is_empty() {
+                writeln!(content, "h").unwrap();
+            }
+        }
+    }
+}
+
+fn write_element(content: &mut String, alphas: &mut AlphaStates, element: &DrawElement2D) {
+    writeln!(content, "q").unwrap();
+    writeln!(content, "{} RG", unit_color(&element.line_color)).unwrap();
+    writeln!(
+        content,
+        "{:.4} w",
+        element.line_thickness as f64 * MM_PER_POINT
+    )
+    .unwrap();
+    match &element.line_type {
+        LineType::Solid => writeln!(content, "[] 0 d").unwrap(),
+        LineType::Dashed { name } => {
+            let dashes: Vec<String> = dash_pattern(name).iter().map(|d| d.to_string()).collect();
+            writeln!(content, "[{}] 0 d", dashes.join(" ")).unwrap();
+        }
+    }
+    let closed = match element.element {
+        Element2D::Rect(..) | Element2D::Poly(..) => true,
+        Element2D::Line(..) | Element2D::Arc(..) => false,
+    };
+    match (&element.fill_type, closed) {
+        (FillType::Solid { color }, true) => {
+            writeln!(
+                content,
+                "{} gs",
+                alphas.get_name(element.line_color.a, color.a)
+            )
+            .unwrap();
+            writeln!(content, "{} rg", unit_color(color)).unwrap();
+            write_path(content, &element.element);
+            writeln!(content, "B").unwrap();
+        }
+        (FillType::Hatch { name }, true) => {
+            writeln!(content, "{} gs", alphas.get_name(element.line_color.a, 1.0)).unwrap();
+            //Clip to the element, then draw the hatch lines across its extents
+            writeln!(content, "q").unwrap();
+            write_path(content, &element.element);
+            writeln!(content, "W n").unwrap();
+            writeln!(content, "[] 0 d").unwrap();
+            let (min, max) = element_extents(&element.element);
+            for angle in hatch_angles(name) {
+                for (start, end) in hatch_segments(&min, &max, angle, HATCH_SPACING) {
+                    writeln!(content, "{} {} m {} {} l", start.x, start.y, end.x, end.y).unwrap();
+                }
+            }
+            writeln!(content, "S").unwrap();
+            writeln!(content, "Q").unwrap();
+            write_path(content, &element.element);
+            writeln!(content, "S").unwrap();
+        }
+        (_, false) => {
+            writeln!(content, "{} gs", alphas.get_name(element.line_color.a, 1.0)).unwrap();
+            write_path(content, &element.element);
+            writeln!(content, "S").unwrap();
+        }
+    }
+    writeln!(content, "Q").unwrap();
+}
+
+///Writes the sheet out as a single page PDF sized to the sheet's print size.
+pub fn sheet_to_pdf(layout: &SheetLayout) -> Vec<u8> {
+    let mut alphas = AlphaStates { states: Vec::new() };
+    let mut content = String::new();
+    //Everything below is in millimeters
+    writeln!(
+        content,
+        "{:.6} 0 0 {:.6} 0 0 cm",
+        POINTS_PER_MM, POINTS_PER_MM
+    )
+    .unwrap();
+    writeln!(content, "1 J 1 j").unwrap();
+    for placed in &layout.elements {
+        write_element(&mut content, &mut alphas, &placed.element);
+    }
+
+    let mut gs_dict = String::new();
+    let first_gs_obj = 5;
+    for i in 0..alphas.states.len() {
+        write!(gs_dict, "/GS{} {} 0 R ", i, first_gs_obj + i).unwrap();
+    }
+    let mut objects = vec![
+        String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+        String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>"),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.4} {:.4}] /Contents 4 0 R /Resources << /ExtGState << {}>> >> >>",
+            layout.width * POINTS_PER_MM,
+            layout.height * POINTS_PER_MM,
+            gs_dict
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ),
+    ];
+    for (stroke, fill) in &alphas.states {
+        objects.push(format!(
+            "<< /Type /ExtGState /CA {} /ca {} >>",
+            stroke, fill
+        ));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        write!(pdf, "{} 0 obj\n{}\nendobj\n", i + 1, obj).unwrap();
+    }
+    let xref_offset = pdf.len();
+    write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+    for offset in offsets {
+        writeln!(pdf, "{:010} 00000 n ", offset).unwrap();
+    }
+    write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    )
+    .unwrap();
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheet_to_pdf() {
+        let mut line = DrawElement2D::new_default(Element2D::Line(Line2D::new(
+            Point2f::new(0.0, 0.0),
+            Point2f::new(10.0, 10.0),
+        )));
+        line.line_color = RGBA::new(255, 0, 0, 0.5);
+        let layout = SheetLayout {
+            name: String::from("Sheet"),
+            width: 420.0,
+            height: 297.0,
+            elements: vec![PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Wall"),
+                element: line,
+            }],
+        };
+        let pdf = String::from_utf8(sheet_to_pdf(&layout)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("/MediaBox [0 0 1190.5512 841.8898]"));
+        assert!(pdf.contains("1.0000 0.0000 0.0000 RG"));
+        assert!(pdf.contains("/CA 0.5 /ca 1"));
+        //The xref table has to point at the real object offsets
+        let xref_start: usize = pdf
+            .lines()
+            .skip_while(|l| *l != "startxref")
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(pdf[xref_start..].starts_with("xref"));
+        let first_obj = pdf.find("1 0 obj").unwrap();
+        assert!(pdf.contains(&format!("{:010} 00000 n", first_obj)));
+    }
+}
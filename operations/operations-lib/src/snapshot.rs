@@ -0,0 +1,197 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+//Bumped whenever the layout of a snapshot changes, so old readers can refuse newer files
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+///One object in a snapshot.  data is the object's own serialization, tagged with its type.
+///references are written out so the dependency graph can be read and diffed,
+///but on import they come back from the objects themselves.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotObject {
+    pub id: ObjID,
+    pub references: Vec<Reference>,
+    pub data: Value,
+}
+
+///Every live object in one file at one offset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub version: u32,
+    pub file: String,
+    pub offset: i64,
+    pub objects: Vec<SnapshotObject>,
+}
+
+///Writes the objects out as a pretty printed JSON snapshot, sorted by id so snapshots of the same file diff cleanly.
+pub fn objects_to_snapshot(
+    objs: &Vec<DataBox>,
+    file: &str,
+    offset: i64,
+) -> Result<String, ObjError> {
+    let mut objects = Vec::with_capacity(objs.len());
+    for obj in objs {
+        objects.push(SnapshotObject {
+            id: *obj.get_id(),
+            references: obj.get_refs().into_iter().flatten().collect(),
+            data: serde_json::to_value(obj)?,
+        });
+    }
+    objects.sort_by_key(|obj| obj.id);
+    let snapshot = FileSnapshot {
+        version: SNAPSHOT_VERSION,
+        file: file.to_string(),
+        offset,
+        objects,
+    };
+    Ok(serde_json::to_string_pretty(&snapshot)?)
+}
+
+//Ids only ever show up as whole strings, so swapping every string that matches catches the object's own id and all of its references
+fn replace_ids(value: &mut Value, new_ids: &HashMap<String, String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(new_id) = new_ids.get(text.as_str()) {
+                *text = new_id.clone();
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                replace_ids(value, new_ids);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                replace_ids(value, new_ids);
+            }
+        }
+        _ => (),
+    }
+}
+
+///Reads the objects back out of a snapshot.
+///If fresh_ids is set, every object gets a new id and references between objects in the snapshot are updated to match.
+///References to objects outside the snapshot are left alone.
+pub fn read_snapshot(data: &str, fresh_ids: bool) -> Result<Vec<DataBox>, ObjError> {
+    let snapshot: FileSnapshot = serde_json::from_str(data)?;
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(ObjError::Other(format!(
+            "Snapshot version {} is newer than the supported version {}",
+            snapshot.version, SNAPSHOT_VERSION
+        )));
+    }
+    let mut new_ids = HashMap::new();
+    if fresh_ids {
+        for obj in &snapshot.objects {
+            new_ids.insert(obj.id.to_string(), ObjID::new_v4().to_string());
+        }
+    }
+    let mut objects = Vec::with_capacity(snapshot.objects.len());
+    for mut obj in snapshot.objects {
+        replace_ids(&mut obj.data, &new_ids);
+        let data: DataBox = serde_json::from_value(obj.data)?;
+        let expected = match new_ids.get(&obj.id.to_string()) {
+            Some(new_id) => ObjID::parse_str(new_id)?,
+            None => obj.id,
+        };
+        if *data.get_id() != expected {
+            return Err(ObjError::Other(format!(
+                "Snapshot entry {} holds object {}",
+                obj.id,
+                data.get_id()
+            )));
+        }
+        objects.push(data);
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosted_door() -> (Wall, Door) {
+        let wall = Wall::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(4.0, 0.0, 0.0),
+            0.1,
+            3.0,
+        );
+        let mut door = Door::new(
+            Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(2.0, 0.0, 0.0),
+            0.05,
+            2.0,
+        );
+        let wall_line = wall.get_result(RefType::ProfileLine, 0).unwrap();
+        door.set_ref(
+            RefType::ProfileLine,
+            0,
+            wall_line,
+            RefID::new(*wall.get_id(), RefType::ProfileLine, 0),
+            &None,
+        );
+        (wall, door)
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let (wall, door) = hosted_door();
+        let wall_id = *wall.get_id();
+        let door_id = *door.get_id();
+        let objs: Vec<DataBox> = vec![Box::new(door), Box::new(wall)];
+        let snapshot = objects_to_snapshot(&objs, "file", 12).unwrap();
+        let parsed: FileSnapshot = serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(parsed.version, SNAPSHOT_VERSION);
+        assert_eq!(parsed.offset, 12);
+        assert_eq!(parsed.objects.len(), 2);
+        assert!(parsed.objects[0].id < parsed.objects[1].id);
+        let door_entry = parsed.objects.iter().find(|obj| obj.id == door_id).unwrap();
+        assert_eq!(door_entry.data["type"], "Door");
+        assert!(door_entry
+            .references
+            .iter()
+            .any(|reference| reference.other.id == wall_id));
+
+        let objects = read_snapshot(&snapshot, false).unwrap();
+        assert_eq!(objects.len(), 2);
+        let door = objects.iter().find(|obj| *obj.get_id() == door_id).unwrap();
+        assert!(door.downcast_ref::<Door>().is_some());
+        assert_eq!(door.get_refs()[0].as_ref().unwrap().other.id, wall_id);
+    }
+
+    #[test]
+    fn test_read_snapshot_fresh_ids() {
+        let (wall, door) = hosted_door();
+        let wall_id = *wall.get_id();
+        let door_id = *door.get_id();
+        let objs: Vec<DataBox> = vec![Box::new(wall), Box::new(door)];
+        let snapshot = objects_to_snapshot(&objs, "file", 12).unwrap();
+        let objects = read_snapshot(&snapshot, true).unwrap();
+        assert_eq!(objects.len(), 2);
+        let new_wall = objects
+            .iter()
+            .find(|obj| obj.downcast_ref::<Wall>().is_some())
+            .unwrap();
+        let new_door = objects
+            .iter()
+            .find(|obj| obj.downcast_ref::<Door>().is_some())
+            .unwrap();
+        assert_ne!(*new_wall.get_id(), wall_id);
+        assert_ne!(*new_door.get_id(), door_id);
+        let door_ref = new_door.get_refs()[0].clone().unwrap();
+        assert_eq!(door_ref.owner.id, *new_door.get_id());
+        assert_eq!(door_ref.other.id, *new_wall.get_id());
+    }
+
+    #[test]
+    fn test_read_snapshot_newer_version() {
+        let snapshot = format!(
+            "{{\"version\": {}, \"file\": \"file\", \"offset\": 0, \"objects\": []}}",
+            SNAPSHOT_VERSION + 1
+        );
+        assert!(read_snapshot(&snapshot, false).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::*;
+use std::fmt::Write;
+
+fn color(rgba: &RGBA) -> String {
+    format!("rgb({},{},{})", rgba.r, rgba.g, rgba.b)
+}
+
+fn stroke_attrs(element: &DrawElement2D) -> String {
+    let mut attrs = format!(
+        "stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{:.4}\"",
+        color(&element.line_color),
+        element.line_color.a,
+        element.line_thickness as f64 * MM_PER_POINT
+    );
+    if let LineType::Dashed { name } = &element.line_type {
+        let dashes: Vec<String> = dash_pattern(name).iter().map(|d| d.to_string()).collect();
+        write!(attrs, " stroke-dasharray=\"{}\"", dashes.join(" ")).unwrap();
+    }
+    attrs
+}
+
+fn write_hatch_pattern(defs: &mut String, id: &str, element: &DrawElement2D, name: &str) {
+    for (i, angle) in hatch_angles(name).iter().enumerate() {
+        writeln!(
+            defs,
+            "<pattern id=\"{}-{}\" patternUnits=\"userSpaceOnUse\" width=\"{}\" height=\"{}\" patternTransform=\"rotate({})\">\
+<line x1=\"0\" y1=\"0\" x2=\"{}\" y2=\"0\" stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{:.4}\"/></pattern>",
+            id,
+            i,
+            HATCH_SPACING,
+            HATCH_SPACING,
+            angle,
+            HATCH_SPACING,
+            color(&element.line_color),
+            element.line_color.a,
+            element.line_thickness as f64 * MM_PER_POINT
+        )
+        .unwrap();
+    }
+}
+
+fn shape(element: &Element2D) -> Option<String> {
+    match element {
+        Element2D::Line(line) => Some(format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"",
+            line.first.x, line.first.y, line.second.x, line.second.y
+        )),
+        Element2D::Arc(arc) => {
+            let (start, sweep) = arc_sweep(arc);
+            let start_pt = arc_point(arc, start);
+            let mut path = format!("<path d=\"M {} {}", start_pt.x, start_pt.y);
+            //A single SVG arc can't describe a full circle, so always split it in half
+            for half in 1..3 {
+                let end_pt = arc_point(arc, start + sweep * half as f64 / 2.0);
+                write!(
+                    path,
+                    " A {} {} 0 0 1 {} {}",
+                    arc.radius, arc.radius, end_pt.x, end_pt.y
+                )
+                .unwrap();
+            }
+            path.push('"');
+            Some(path)
+        }
+        Element2D::Rect(rect) => {
+            let x = rect.bottom_left.x.min(rect.top_right.x);
+            let y = rect.bottom_left.y.min(rect.top_right.y);
+            let width = (rect.top_right.x - rect.bottom_left.x).abs();
+            let height = (rect.top_right.y - rect.bottom_left.y).abs();
+            Some(format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+                x, y, width, height
+            ))
+        }
+        Element2D::Poly(poly) => {
+            if poly.pts.is_empty() {
+                return None;
+            }
+            let pts: Vec<String> = poly
+                .pts
+                .iter()
+                .map(|pt| format!("{},{}", pt.x, pt.y))
+                .collect();
+            Some(format!("<polygon points=\"{}\"", pts.join(" ")))
+        }
+    }
+}
+
+fn is_closed(element: &Element2D) -> bool {
+    match element {
+        Element2D::Rect(..) | Element2D::Poly(..) => true,
+        Element2D::Line(..) | Element2D::Arc(..) => false,
+    }
+}
+
+///Writes the sheet out as an SVG document sized to the sheet's print size.
+pub fn sheet_to_svg(layout: &SheetLayout) -> String {
+    let mut defs = String::new();
+    let mut body = String::new();
+    for (index, placed) in layout.elements.iter().enumerate() {
+        let element = &placed.element;
+        let shape_str = match shape(&element.element) {
+            Some(shape_str) => shape_str,
+            None => continue,
+        };
+        let stroke = stroke_attrs(element);
+        if is_closed(&element.element) {
+            match &element.fill_type {
+                FillType::Solid { color: fill } => {
+                    writeln!(
+                        body,
+                        "{} fill=\"{}\" fill-opacity=\"{}\" {}/>",
+                        shape_str,
+                        color(fill),
+                        fill.a,
+                        stroke
+                    )
+                    .unwrap();
+                }
+                FillType::Hatch { name } => {
+                    let id = format!("hatch-{}", index);
+                    write_hatch_pattern(&mut defs, &id, element, name);
+                    for i in 0..hatch_angles(name).len() {
+                        writeln!(
+                            body,
+                            "{} fill=\"url(#{}-{})\" stroke=\"none\"/>",
+                            shape_str, id, i
+                        )
+                        .unwrap();
+                    }
+                    writeln!(body, "{} fill=\"none\" {}/>", shape_str, stroke).unwrap();
+                }
+            }
+        } else {
+            writeln!(body, "{} fill=\"none\" {}/>", shape_str, stroke).unwrap();
+        }
+    }
+    let mut svg = String::new();
+    writeln!(svg, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" viewBox=\"0 0 {w} {h}\">",
+        w = layout.width,
+        h = layout.height
+    )
+    .unwrap();
+    writeln!(svg, "<title>{}</title>", escape(&layout.name)).unwrap();
+    writeln!(svg, "<defs>\n{}</defs>", defs).unwrap();
+    //Sheet coordinates have y going up, SVG has it going down
+    writeln!(
+        svg,
+        "<g transform=\"matrix(1 0 0 -1 0 {})\">\n{}</g>",
+        layout.height, body
+    )
+    .unwrap();
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheet_to_svg() {
+        let mut hatched = DrawElement2D::new_default(Element2D::Rect(Rect2D::new(
+            Point2f::new(10.0, 10.0),
+            Point2f::new(0.0, 0.0),
+        )));
+        hatched.fill_type = FillType::Hatch {
+            name: String::from("Cross"),
+        };
+        hatched.line_type = LineType::Dashed {
+            name: String::from("Hidden"),
+        };
+        let layout = SheetLayout {
+            name: String::from("Plans & Sections"),
+            width: 400.0,
+            height: 300.0,
+            elements: vec![PlacedElement {
+                obj_id: ObjID::new_v4(),
+                obj_type: String::from("Wall"),
+                element: hatched,
+            }],
+        };
+        let svg = sheet_to_svg(&layout);
+        assert!(svg.contains("width=\"400mm\" height=\"300mm\""));
+        assert!(svg.contains("<title>Plans &amp; Sections</title>"));
+        assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"10\" height=\"10\""));
+        assert!(svg.contains("url(#hatch-0-1)"));
+        assert!(svg.contains("stroke-dasharray=\"3 1.5\""));
+    }
+}
@@ -0,0 +1,528 @@
+use operations::*;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use trace_lib::*;
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod representation {
+    tonic::include_proto!("representation");
+}
+
+mod object_state {
+    tonic::include_proto!("object_state");
+}
+use object_state::*;
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+use geom::*;
+
+mod ops_proto {
+    tonic::include_proto!("operations");
+}
+
+mod obj_convert;
+
+use obj_convert::*;
+use ops_proto::*;
+
+type Outputs = Vec<(ObjID, UpdateOutput)>;
+
+//Tessellates the objects, along with the contents of every symbol they instance
+#[instrument(skip(geom_conn, objects, symbol_files))]
+async fn get_outputs_with_symbols(
+    geom_conn: &dyn GeomKernel,
+    objects: &Vec<ObjectMsg>,
+    symbol_files: &Vec<SymbolFileMsg>,
+) -> Result<(Outputs, HashMap<ObjID, Outputs>), Status> {
+    let objects = from_obj_msgs(objects)?;
+    let outputs = operations::get_update_outputs(geom_conn, &objects)
+        .instrument(info_span!("get_update_outputs"))
+        .await
+        .map_err(to_status)?;
+    let mut symbol_defs = operations::get_symbol_files(&objects);
+    //Symbols can refer to different versions of the same file
+    let mut file_outputs = HashMap::new();
+    for symbol_file in symbol_files {
+        let file = to_obj_id(&symbol_file.file)?;
+        let symbol_objs = from_obj_msgs(&symbol_file.objects)?;
+        symbol_defs.extend(operations::get_symbol_files(&symbol_objs));
+        let symbol_outputs = operations::get_update_outputs(geom_conn, &symbol_objs)
+            .instrument(info_span!("get_update_outputs"))
+            .await
+            .map_err(to_status)?;
+        file_outputs.insert((file, symbol_file.offset), symbol_outputs);
+    }
+    let mut symbols = HashMap::new();
+    for (def_id, file, change) in symbol_defs {
+        match file_outputs.get(&(file, change as i64)) {
+            Some(symbol_outputs) => {
+                symbols.insert(def_id, symbol_outputs.clone());
+            }
+            None => warn!("Symbol file {} for {} wasn't passed in", file, def_id),
+        }
+    }
+    Ok((outputs, symbols))
+}
+
+#[derive(Debug)]
+struct OperationsService {
+    geom_url: String,
+    cache: Arc<TessellationCache>,
+}
+
+impl OperationsService {
+    //Anything the kernel tessellates is kept in the cache for next time
+    async fn kernel(&self) -> Result<CachedKernel, Status> {
+        let (kernel, identity) = connect_kernel(self.geom_url.clone())
+            .instrument(info_span!("connect_kernel"))
+            .await
+            .map_err(to_status)?;
+        Ok(CachedKernel::new(kernel, identity, self.cache.clone()))
+    }
+}
+
+#[tonic::async_trait]
+impl operations_server::Operations for OperationsService {
+    #[instrument]
+    async fn create_walls(
+        &self,
+        request: Request<CreateWallsInput>,
+    ) -> Result<Response<CreateWallsOutput>, Status> {
+        let walls_msg = request.get_ref();
+        propagate_trace(request.metadata());
+        let mut results = Vec::new();
+        for wall_msg in &walls_msg.walls {
+            let wall = Box::new(to_wall(
+                &wall_msg.first_pt,
+                &wall_msg.second_pt,
+                wall_msg.width,
+                wall_msg.height,
+            )?) as DataBox;
+            results.push(to_object_msg(&wall).map_err(to_status)?);
+        }
+        Ok(Response::new(CreateWallsOutput { walls: results }))
+    }
+
+    #[instrument]
+    async fn move_objects(
+        &self,
+        request: Request<MoveObjectsInput>,
+    ) -> Result<Response<MoveObjectsOutput>, Status> {
+        let msg = request.get_ref();
+        propagate_trace(request.metadata());
+        let mut objects = from_obj_msgs(&msg.objects)?;
+        let delta = to_vector_3f(&msg.delta)?;
+        operations::move_objects(&mut objects, &delta);
+        let obj_msgs = to_object_msgs(&objects)?;
+        Ok(Response::new(MoveObjectsOutput { objects: obj_msgs }))
+    }
+
+    #[instrument]
+    async fn join_object_to_other(
+        &self,
+        request: Request<JoinObjectToOtherInput>,
+    ) -> Result<Response<JoinObjectToOtherOutput>, Status> {
+        let msg = request.get_ref();
+        propagate_trace(request.metadata());
+        let mut obj = from_obj_msg_opt(&msg.to_join)?;
+        let other_obj = from_obj_msg_opt(&msg.join_to)?;
+        let ref_type = from_ref_type_msg(msg.looking_for)?;
+        let guess = to_point_3f(&msg.guess)?;
+        operations::snap_to_ref(&mut obj, &other_obj, ref_type, &guess).map_err(to_status)?;
+        let obj_msg = to_object_msg(&obj).map_err(to_status)?;
+        Ok(Response::new(JoinObjectToOtherOutput {
+            joined: Some(obj_msg),
+        }))
+    }
+
+    #[instrument]
+    async fn join_objects(
+        &self,
+        request: Request<JoinObjectsInput>,
+    ) -> Result<Response<JoinObjectsOutput>, Status> {
+        let msg = request.get_ref();
+        propagate_trace(request.metadata());
+        let mut first_obj = from_obj_msg_opt(&msg.first_obj)?;
+        let mut second_obj = from_obj_msg_opt(&msg.second_obj)?;
+        let first_wants = from_ref_type_msg(msg.first_wants)?;
+        let second_wants = from_ref_type_msg(msg.second_wants)?;
+        let guess = to_point_3f(&msg.guess)?;
+        operations::join_refs(
+            &mut first_obj,
+            &mut second_obj,
+            first_wants,
+            second_wants,
+            &guess,
+        )
+        .map_err(to_status)?;
+        let first_msg = to_object_msg(&first_obj).map_err(to_status)?;
+        let second_msg = to_object_msg(&second_obj).map_err(to_status)?;
+        Ok(Response::new(JoinObjectsOutput {
+            first_obj: Some(first_msg),
+            second_obj: Some(second_msg),
+        }))
+    }
+
+    #[instrument]
+    async fn update_objects(
+        &self,
+        request: Request<UpdateObjectsInput>,
+    ) -> Result<Response<UpdateObjectsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let update_msg = request.get_ref();
+        let refers = from_ref_msgs(&update_msg.obj_refs)?;
+        let mut objs = get_map_from_change_msgs(&update_msg.objects)?;
+        operations::update_all(&mut objs, refers);
+        let changes = to_change_msgs(&update_msg.objects, &objs).map_err(to_status)?;
+        Ok(Response::new(UpdateObjectsOutput { objects: changes }))
+    }
+
+    #[instrument]
+    async fn client_representation(
+        &self,
+        request: Request<ClientRepresentationInput>,
+    ) -> Result<Response<ClientRepresentationOutput>, Status> {
+        propagate_trace(request.metadata());
+        let repr_msg = request.get_ref();
+        let geom_conn = self.kernel().await?;
+        let geom_conn = &geom_conn;
+        let lods = to_lods(&repr_msg.lods)?;
+        let lods = &lods;
+        let changes = from_change_msgs(&repr_msg.objects)?;
+        //Every change is tessellated at once so the kernel can batch them
+        let updates = changes.iter().flat_map(|change| {
+            lods.iter().map(move |lod| async move {
+                let (output, views_opt, bounds) = match change {
+                    Change::Add { obj } | Change::Modify { obj } => {
+                        let (output, views_opt) = get_obj_update_info(geom_conn, obj, *lod)
+                            .instrument(info_span!("get_obj_update_info"))
+                            .await
+                            .map_err(to_status)?;
+                        let bounds = obj
+                            .as_position()
+                            .map(|pos| pos.get_axis_aligned_bounding_box());
+                        (output, views_opt, bounds)
+                    }
+                    Change::Delete { .. } => (UpdateOutput::Delete, None, None),
+                };
+                Ok::<_, Status>(encode_update_output(output, views_opt, bounds))
+            })
+        });
+        let outputs = futures::future::try_join_all(updates).await?;
+        Ok(Response::new(ClientRepresentationOutput { outputs }))
+    }
+
+    #[instrument]
+    async fn create_sheet(
+        &self,
+        request: Request<CreateSheetInput>,
+    ) -> Result<Response<CreateSheetOutput>, Status> {
+        propagate_trace(request.metadata());
+        let sheet_msg = request.into_inner();
+        let sheet = Box::new(to_sheet(sheet_msg)?) as DataBox;
+        let sheet_msg = to_object_msg(&sheet).map_err(to_status)?;
+        Ok(Response::new(CreateSheetOutput {
+            sheet: Some(sheet_msg),
+        }))
+    }
+
+    #[instrument]
+    async fn create_viewport(
+        &self,
+        request: Request<CreateViewportInput>,
+    ) -> Result<Response<CreateViewportOutput>, Status> {
+        propagate_trace(request.metadata());
+        let viewport_msg = request.into_inner();
+        let viewport = Box::new(to_viewport(viewport_msg)?) as DataBox;
+        let viewport_msg = to_object_msg(&viewport).map_err(to_status)?;
+        Ok(Response::new(CreateViewportOutput {
+            viewport: Some(viewport_msg),
+        }))
+    }
+
+    #[instrument]
+    async fn export_sheet(
+        &self,
+        request: Request<ExportSheetInput>,
+    ) -> Result<Response<ExportSheetOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let sheet_id = to_obj_id(&msg.sheet_id)?;
+        let objects = from_obj_msgs(&msg.objects)?;
+        let layout = operations::layout_sheet(&sheet_id, &objects).map_err(to_status)?;
+        let data = match DrawingFormat::from_i32(msg.format) {
+            Some(DrawingFormat::Svg) => operations::sheet_to_svg(&layout).into_bytes(),
+            Some(DrawingFormat::Pdf) => operations::sheet_to_pdf(&layout),
+            Some(DrawingFormat::Dxf) => {
+                operations::elements_to_dxf(&layout.elements, DxfUnits::Millimeters).into_bytes()
+            }
+            None => return Err(Status::invalid_argument("Unknown drawing format")),
+        };
+        Ok(Response::new(ExportSheetOutput { data }))
+    }
+
+    #[instrument]
+    async fn export_view(
+        &self,
+        request: Request<ExportViewInput>,
+    ) -> Result<Response<ExportViewOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let view = from_axis_view_msg(msg.view)?;
+        let objects = from_obj_msgs(&msg.objects)?;
+        let elements = operations::collect_view(&objects, &view);
+        let data = match DrawingFormat::from_i32(msg.format) {
+            Some(DrawingFormat::Dxf) => {
+                operations::elements_to_dxf(&elements, DxfUnits::Meters).into_bytes()
+            }
+            Some(..) => {
+                return Err(Status::invalid_argument(
+                    "Axis views can only be exported to DXF",
+                ))
+            }
+            None => return Err(Status::invalid_argument("Unknown drawing format")),
+        };
+        Ok(Response::new(ExportViewOutput { data }))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_dxf_walls(
+        &self,
+        request: Request<ImportDxfWallsInput>,
+    ) -> Result<Response<ImportDxfWallsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let data = String::from_utf8_lossy(&msg.data);
+        let segments = operations::read_dxf_segments(&data, &msg.layers).map_err(to_status)?;
+        info!("Read {} segments from DXF", segments.len());
+        let walls =
+            operations::create_joined_walls(&segments, msg.width, msg.height, msg.tolerance);
+        let walls = to_object_msgs(&walls)?;
+        Ok(Response::new(ImportDxfWallsOutput { walls }))
+    }
+
+    #[instrument]
+    async fn export_ifc(
+        &self,
+        request: Request<ExportIfcInput>,
+    ) -> Result<Response<ExportIfcOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let objects = from_obj_msgs(&msg.objects)?;
+        let data = operations::objects_to_ifc(&objects, &msg.name).into_bytes();
+        Ok(Response::new(ExportIfcOutput { data }))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_ifc(
+        &self,
+        request: Request<ImportIfcInput>,
+    ) -> Result<Response<ImportIfcOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let data = String::from_utf8_lossy(&msg.data);
+        let import = operations::read_ifc(&data).map_err(to_status)?;
+        info!(
+            "Read {} objects from IFC, {} issues",
+            import.objects.len(),
+            import.issues.len()
+        );
+        let objects = to_object_msgs(&import.objects)?;
+        let issues = import
+            .issues
+            .into_iter()
+            .map(|issue| IfcImportIssueMsg {
+                step_id: issue.step_id as u64,
+                entity: issue.entity,
+                global_id: issue.global_id,
+                reason: issue.reason,
+            })
+            .collect();
+        Ok(Response::new(ImportIfcOutput { objects, issues }))
+    }
+
+    #[instrument(skip(request))]
+    async fn get_symbol_files(
+        &self,
+        request: Request<GetSymbolFilesInput>,
+    ) -> Result<Response<GetSymbolFilesOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let objects = from_obj_msgs(&msg.objects)?;
+        let files = operations::get_symbol_files(&objects)
+            .into_iter()
+            .map(|(def_id, file, change)| SymbolFileRefMsg {
+                file: file.to_string(),
+                offset: change as i64,
+                definition: def_id.to_string(),
+            })
+            .collect();
+        Ok(Response::new(GetSymbolFilesOutput { files }))
+    }
+
+    #[instrument(skip(request))]
+    async fn export_gltf(
+        &self,
+        request: Request<ExportGltfInput>,
+    ) -> Result<Response<ExportGltfOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        //The representations were already tessellated, so the kernel isn't needed
+        let outputs = decode_reps(&msg.reps)?;
+        let mut symbols = HashMap::new();
+        for symbol in &msg.symbols {
+            let symbol_outputs = decode_reps(&symbol.reps)?;
+            for def_id in &symbol.definitions {
+                symbols.insert(to_obj_id(def_id)?, symbol_outputs.clone());
+            }
+        }
+        let data = operations::outputs_to_glb(&outputs, &symbols).map_err(to_status)?;
+        Ok(Response::new(ExportGltfOutput { data }))
+    }
+
+    #[instrument(skip(request))]
+    async fn export_mesh(
+        &self,
+        request: Request<ExportMeshInput>,
+    ) -> Result<Response<ExportMeshOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let geom_conn = self.kernel().await?;
+        let (outputs, symbols) =
+            get_outputs_with_symbols(&geom_conn, &msg.objects, &msg.symbol_files).await?;
+        let mut meshes = operations::flatten_outputs(&outputs, &symbols);
+        if msg.merge_vertices {
+            for (_, data) in &mut meshes {
+                *data = operations::merge_vertices(data);
+            }
+        }
+        let scale = if msg.scale == 0.0 { 1.0 } else { msg.scale };
+        let data = match MeshFormat::from_i32(msg.format) {
+            Some(MeshFormat::Obj) => operations::meshes_to_obj(&meshes, scale).into_bytes(),
+            Some(MeshFormat::Stl) => operations::meshes_to_stl(&meshes, scale),
+            None => return Err(Status::invalid_argument("Unknown mesh format")),
+        };
+        Ok(Response::new(ExportMeshOutput { data }))
+    }
+
+    #[instrument(skip(request))]
+    async fn export_snapshot(
+        &self,
+        request: Request<ExportSnapshotInput>,
+    ) -> Result<Response<ExportSnapshotOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let objects = from_obj_msgs(&msg.objects)?;
+        let data = operations::objects_to_snapshot(&objects, &msg.file, msg.offset)
+            .map_err(to_status)?
+            .into_bytes();
+        Ok(Response::new(ExportSnapshotOutput { data }))
+    }
+
+    #[instrument(skip(request))]
+    async fn import_snapshot(
+        &self,
+        request: Request<ImportSnapshotInput>,
+    ) -> Result<Response<ImportSnapshotOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let data = std::str::from_utf8(&msg.data)
+            .map_err(|e| Status::invalid_argument(format!("Snapshot isn't UTF-8: {}", e)))?;
+        let objects = operations::read_snapshot(data, msg.fresh_ids).map_err(to_status)?;
+        info!("Read {} objects from snapshot", objects.len());
+        let objects = to_object_msgs(&objects)?;
+        Ok(Response::new(ImportSnapshotOutput { objects }))
+    }
+
+    #[instrument(skip(request))]
+    async fn diff_objects(
+        &self,
+        request: Request<DiffObjectsInput>,
+    ) -> Result<Response<DiffObjectsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let to_json = |value: Option<serde_json::Value>| match value {
+            Some(value) => value.to_string(),
+            None => String::new(),
+        };
+        let mut diffs = Vec::new();
+        for pair in &msg.pairs {
+            let (before, after) = match (&pair.before, &pair.after) {
+                (Some(before), Some(after)) => (
+                    from_object_msg(before).map_err(to_status)?,
+                    from_object_msg(after).map_err(to_status)?,
+                ),
+                _ => return Err(Status::invalid_argument("Both objects are needed to diff")),
+            };
+            let properties = operations::diff_objects(&before, &after)
+                .map_err(to_status)?
+                .into_iter()
+                .map(|diff| PropertyDiffMsg {
+                    path: diff.path,
+                    before: to_json(diff.before),
+                    after: to_json(diff.after),
+                })
+                .collect();
+            diffs.push(ObjectDiffMsg { properties });
+        }
+        Ok(Response::new(DiffObjectsOutput { diffs }))
+    }
+
+    #[instrument(skip(request))]
+    async fn blame_object(
+        &self,
+        request: Request<BlameObjectInput>,
+    ) -> Result<Response<BlameObjectOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let versions = from_obj_msgs(&msg.versions)?;
+        let properties = operations::blame_versions(&versions)
+            .map_err(to_status)?
+            .into_iter()
+            .map(|blame| PropertyBlameMsg {
+                path: blame.path,
+                value: blame.value.to_string(),
+                version: blame.version as u64,
+            })
+            .collect();
+        Ok(Response::new(BlameObjectOutput { properties }))
+    }
+
+    #[instrument]
+    async fn get_cache_stats(
+        &self,
+        request: Request<GetCacheStatsInput>,
+    ) -> Result<Response<GetCacheStatsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let stats = self.cache.stats();
+        Ok(Response::new(GetCacheStatsOutput {
+            memory_hits: stats.memory_hits,
+            store_hits: stats.store_hits,
+            misses: stats.misses,
+        }))
+    }
+}
+
+///How many tessellations are kept in memory unless configured otherwise
+pub const DEFAULT_CACHE_SIZE: usize = 10000;
+
+///Serves the operations, using the geometry kernel at geom_url, or tessellating in process for local://
+pub async fn run(
+    run_url: SocketAddr,
+    geom_url: String,
+    cache: TessellationCache,
+) -> Result<(), tonic::transport::Error> {
+    let svc = operations_server::OperationsServer::new(OperationsService {
+        geom_url,
+        cache: Arc::new(cache),
+    });
+
+    info!("Running on {:?}", run_url);
+    Server::builder().add_service(svc).serve(run_url).await
+}
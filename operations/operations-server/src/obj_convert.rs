@@ -32,6 +32,18 @@ pub fn to_vector_3f(msg: &Option<Vector3Msg>) -> Result<Vector3f, tonic::Status>
     }
 }
 
+pub fn to_lod(lod: i32) -> Result<Lod, tonic::Status> {
+    Lod::from_i32(lod).ok_or_else(|| tonic::Status::invalid_argument("Unknown level of detail"))
+}
+
+///No levels asked for means just the full one
+pub fn to_lods(lods: &[i32]) -> Result<Vec<Lod>, tonic::Status> {
+    if lods.is_empty() {
+        return Ok(vec![Lod::Full]);
+    }
+    lods.iter().map(|lod| to_lod(*lod)).collect()
+}
+
 pub fn to_door(
     first_pt: &Option<Point3Msg>,
     second_pt: &Option<Point3Msg>,
@@ -86,6 +98,18 @@ pub fn to_viewport(viewport: CreateViewportInput) -> Result<Viewport, tonic::Sta
     ))
 }
 
+pub fn from_axis_view_msg(view: i32) -> Result<ViewType, tonic::Status> {
+    match AxisView::from_i32(view) {
+        Some(AxisView::Top) => Ok(ViewType::Top),
+        Some(AxisView::Front) => Ok(ViewType::Front),
+        Some(AxisView::Left) => Ok(ViewType::Left),
+        Some(AxisView::Right) => Ok(ViewType::Right),
+        Some(AxisView::Back) => Ok(ViewType::Back),
+        Some(AxisView::Bottom) => Ok(ViewType::Bottom),
+        None => Err(tonic::Status::invalid_argument("Unknown axis view")),
+    }
+}
+
 pub fn from_ref_type_msg(ref_type: i32) -> Result<RefType, tonic::Status> {
     match ref_id_msg::RefType::from_i32(ref_type) {
         Some(ref_id_msg::RefType::Drawable) => Ok(RefType::Drawable),
@@ -212,7 +236,9 @@ pub fn get_map_from_change_msgs(
                 let id = to_obj_id(&msg.id)?;
                 results.insert(id, None);
             }
-            None => (),
+            Some(change_msg::ChangeType::Branch(..))
+            | Some(change_msg::ChangeType::Tag(..))
+            | None => (),
         }
     }
     Ok(results)
@@ -229,6 +255,12 @@ pub fn from_change_msg(msg: &ChangeMsg) -> Result<Change, tonic::Status> {
         Some(change_msg::ChangeType::Delete(msg)) => Ok(Change::Delete {
             id: to_obj_id(&msg.id)?,
         }),
+        Some(change_msg::ChangeType::Branch(..)) => Err(tonic::Status::invalid_argument(
+            "A branch isn't a change to an object",
+        )),
+        Some(change_msg::ChangeType::Tag(..)) => Err(tonic::Status::invalid_argument(
+            "A tag isn't a change to an object",
+        )),
         None => Err(tonic::Status::invalid_argument("No change type specified")),
     }
 }
@@ -282,6 +314,10 @@ pub fn to_change_msgs(
                         };
                         results.push(change);
                     }
+                    Some(change_msg::ChangeType::Branch(..))
+                    | Some(change_msg::ChangeType::Tag(..)) => {
+                        results.push(old_change.clone());
+                    }
                     None => {
                         results.push(ChangeMsg {
                             user: old_change.user.clone(),
@@ -308,6 +344,9 @@ fn encode_mesh(mesh: MeshData) -> MeshDataMsg {
         positions: mesh.positions,
         indices: mesh.indices,
         meta_json: from_json(mesh.metadata),
+        normals: mesh.normals,
+        uvs: mesh.uvs,
+        material_indices: mesh.material_indices,
     }
 }
 
@@ -444,6 +483,7 @@ pub fn encode_views(views_opt: Option<DrawingRepresentations>) -> Option<Drawing
 pub fn encode_update_output(
     output: UpdateOutput,
     views: Option<DrawingRepresentations>,
+    bounds: Option<Cube>,
 ) -> UpdateOutputMsg {
     let encoded_output = match output {
         UpdateOutput::Empty => Some(update_output_msg::Output::Empty(String::default())),
@@ -460,8 +500,97 @@ pub fn encode_update_output(
         }
     };
     let encoded_views = encode_views(views);
+    let encoded_bounds = bounds.map(|cube| BoundsMsg {
+        bottom_left: encode_point3(cube.bottom_left),
+        top_right: encode_point3(cube.top_right),
+    });
     UpdateOutputMsg {
         output: encoded_output,
         views: encoded_views,
+        bounds: encoded_bounds,
+    }
+}
+
+fn to_json(json: &str) -> Option<serde_json::Value> {
+    if json.is_empty() {
+        None
+    } else {
+        serde_json::from_str(json).ok()
+    }
+}
+
+fn decode_mesh(mesh: &MeshDataMsg) -> MeshData {
+    MeshData {
+        positions: mesh.positions.clone(),
+        indices: mesh.indices.clone(),
+        normals: mesh.normals.clone(),
+        uvs: mesh.uvs.clone(),
+        material_indices: mesh.material_indices.clone(),
+        metadata: to_json(&mesh.meta_json),
+    }
+}
+
+fn decode_transmat(values: &[f64]) -> Result<TransMat, tonic::Status> {
+    if values.len() != 16 {
+        return Err(tonic::Status::invalid_argument("Transforms need 16 values"));
     }
+    let column =
+        |i: usize| cgmath::Vector4::new(values[i], values[i + 1], values[i + 2], values[i + 3]);
+    Ok(TransMat::from_cols(
+        column(0),
+        column(4),
+        column(8),
+        column(12),
+    ))
+}
+
+fn decode_instance(instance: &InstanceDataMsg) -> Result<InstanceData, tonic::Status> {
+    let source = match instance.source.as_str() {
+        "" => None,
+        id => Some(to_obj_id(id)?),
+    };
+    Ok(InstanceData {
+        transform: decode_transmat(&instance.transform)?,
+        bbox: Cube {
+            bottom_left: to_point_3f(&instance.bottom_left)?,
+            top_right: to_point_3f(&instance.top_right)?,
+        },
+        source,
+        metadata: to_json(&instance.meta_json),
+    })
+}
+
+pub fn decode_update_output(output: &UpdateOutputMsg) -> Result<UpdateOutput, tonic::Status> {
+    match &output.output {
+        Some(update_output_msg::Output::Empty(..)) | None => Ok(UpdateOutput::Empty),
+        Some(update_output_msg::Output::Delete(..)) => Ok(UpdateOutput::Delete),
+        Some(update_output_msg::Output::Mesh(mesh)) => Ok(UpdateOutput::Mesh {
+            data: decode_mesh(mesh),
+        }),
+        Some(update_output_msg::Output::FileRef(file)) => Ok(UpdateOutput::FileRef {
+            file: to_obj_id(file)?,
+        }),
+        Some(update_output_msg::Output::Instance(instance)) => Ok(UpdateOutput::Instance {
+            data: decode_instance(instance)?,
+        }),
+        Some(update_output_msg::Output::OtherJson(json)) => Ok(UpdateOutput::Other {
+            data: to_json(json).unwrap_or_default(),
+        }),
+        Some(update_output_msg::Output::CompactMesh(..)) => Err(tonic::Status::invalid_argument(
+            "Compact meshes are only sent to clients",
+        )),
+    }
+}
+
+///The objects' outputs, from their representations
+pub fn decode_reps(reps: &[UpdateChangeMsg]) -> Result<Vec<(ObjID, UpdateOutput)>, tonic::Status> {
+    let mut results = Vec::new();
+    for rep in reps {
+        let output = match &rep.update {
+            Some(update) => decode_update_output(update)?,
+            None => UpdateOutput::Empty,
+        };
+        results.push((to_obj_id(&rep.obj_id)?, output));
+    }
+    Ok(results)
 }
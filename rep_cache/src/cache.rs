@@ -1,22 +1,24 @@
-//! The object cache for a file maps objIDs to a list of the last X number of changes to that object.
-//! X is configurable.
+//! The object cache for a file maps objIDs to a list of the last REP_HISTORY changes to that object.
+//! Reads at offsets older than that aren't found, unless the offset is tagged.
 
 use super::*;
+use cache_store::history::{self, Version};
+use cache_store::{Store, StoreCommands};
 use prost::Message;
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum RepCacheError {
     #[error("Object {0} not found")]
     ObjNotFound(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
     #[error("Prost encode error: {0}")]
     ProstEncodeError(#[from] prost::EncodeError),
     #[error("Prost decode error: {0}")]
     ProstDecodeError(#[from] prost::DecodeError),
     #[error("Redis error: {0:?}")]
-    DatabaseError(#[from] redis::RedisError),
+    DatabaseError(#[from] cache_store::StoreError),
 }
 
 impl Into<tonic::Status> for RepCacheError {
@@ -27,6 +29,7 @@ impl Into<tonic::Status> for RepCacheError {
             | RepCacheError::ProstEncodeError(..)
             | RepCacheError::ProstDecodeError(..) => tonic::Code::Internal,
             RepCacheError::ObjNotFound(..) => tonic::Code::NotFound,
+            RepCacheError::InvalidArgument(..) => tonic::Code::InvalidArgument,
         };
         tonic::Status::new(code, msg)
     }
@@ -37,46 +40,309 @@ pub fn to_status<T: Into<RepCacheError>>(err: T) -> tonic::Status {
     obj_error.into()
 }
 
-fn obj_rep_cache(file: &str, key: &str) -> String {
+///How many representations of each object are kept for reading older offsets and branches
+pub const REP_HISTORY: isize = 100;
+
+//Each level of detail is kept in its own list
+fn obj_rep_cache(file: &str, key: &str, lod: i32) -> String {
+    if lod == representation::LevelOfDetail::Full as i32 {
+        format!("{}:{}:reps", file, key)
+    } else {
+        format!("{}:{}:reps:lod{}", file, key, lod)
+    }
+}
+
+//Before there was history, only the latest full representation was set here
+fn legacy_obj_rep(file: &str, key: &str) -> String {
     format!("{}:{}", file, key)
 }
 
+fn legacy_key(file: &str, key: &str, lod: i32) -> Option<String> {
+    if lod == representation::LevelOfDetail::Full as i32 {
+        Some(legacy_obj_rep(file, key))
+    } else {
+        None
+    }
+}
+
+fn branch_parent(file: &str) -> String {
+    format!("{}:branch", file)
+}
+
+fn tags(file: &str) -> String {
+    format!("{}:tags", file)
+}
+
+fn rep_offset(rep_bin: &[u8]) -> Result<i64, RepCacheError> {
+    Ok(representation::UpdateChangeMsg::decode(rep_bin)?.offset)
+}
+
+async fn update_tag(
+    conn: &dyn Store,
+    file: &str,
+    tag: &representation::TagRepMsg,
+) -> Result<(), RepCacheError> {
+    if tag.removed {
+        conn.hdel(tags(file), &tag.name).await?;
+    } else {
+        conn.hset(tags(file), &tag.name, tag.offset).await?;
+    }
+    Ok(())
+}
+
+///The offsets tagged in the file, along with those tagged in the files it branched from up to where it branched
+pub(crate) async fn get_tag_offsets(
+    conn: &dyn Store,
+    file: &str,
+) -> Result<Vec<i64>, RepCacheError> {
+    let mut offsets: Vec<i64> = conn.hvals(tags(file)).await?;
+    let mut file = String::from(file);
+    let mut before_or_equal = i64::MAX;
+    while let Some(branch) = get_branch_parent(conn, &file).await? {
+        before_or_equal = before_or_equal.min(branch.offset);
+        let inherited: Vec<i64> = conn.hvals(tags(&branch.parent)).await?;
+        offsets.extend(
+            inherited
+                .into_iter()
+                .filter(|offset| *offset <= before_or_equal),
+        );
+        file = branch.parent;
+    }
+    Ok(offsets)
+}
+
 async fn store_object_rep(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     key: &str,
+    lod: i32,
     obj: &[u8],
+    tag_offsets: &[i64],
 ) -> Result<(), RepCacheError> {
-    let obj_rep_cache = obj_rep_cache(file, key);
-    trace!("Setting obj {} rep in file{}", key, file);
-    conn.set(&obj_rep_cache, obj).await?;
-    Ok(())
+    let obj_rep_cache = obj_rep_cache(file, key, lod);
+    trace!("Pushing obj {} rep in file{}", key, file);
+    //Older reps are kept for branches that started before them, and for tags
+    history::push(
+        conn,
+        &obj_rep_cache,
+        legacy_key(file, key, lod).as_deref(),
+        obj.to_vec(),
+        REP_HISTORY,
+        tag_offsets,
+        rep_offset,
+    )
+    .await
+}
+
+pub(crate) async fn get_branch_parent(
+    conn: &dyn Store,
+    file: &str,
+) -> Result<Option<representation::BranchRepMsg>, RepCacheError> {
+    let branch: Option<Vec<u8>> = conn.get(branch_parent(file)).await?;
+    match branch {
+        Some(bytes) => Ok(Some(representation::BranchRepMsg::decode(bytes.as_ref())?)),
+        None => Ok(None),
+    }
 }
 
+///Gets the latest representation of the object at the level of detail as of the offset.
+///If a branch hasn't changed the object, it's read from the parent as of the offset the branch started at.
 pub async fn get_object_rep(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     key: &str,
+    offset: i64,
+    lod: i32,
 ) -> Result<representation::UpdateChangeMsg, RepCacheError> {
-    trace!("getting object {} in file {}", key, file,);
-    let obj_rep_cache = obj_rep_cache(file, key);
-    let rep_opt: Option<Vec<u8>> = conn.get(obj_rep_cache).await?;
-    match rep_opt {
-        Some(rep_bin) => {
-            let rep = representation::UpdateChangeMsg::decode(rep_bin.as_ref())?;
-            Ok(rep)
+    trace!(
+        "getting object {} in file {} at offset {}",
+        key,
+        file,
+        offset
+    );
+    let mut file = String::from(file);
+    let mut offset = offset;
+    loop {
+        let obj_rep_cache = obj_rep_cache(&file, key, lod);
+        let legacy = legacy_key(&file, key, lod);
+        match history::find(conn, &obj_rep_cache, legacy.as_deref(), offset, rep_offset).await? {
+            Version::Found(rep_bin) => {
+                return Ok(representation::UpdateChangeMsg::decode(rep_bin.as_ref())?)
+            }
+            //The rep as of the offset is gone, so it has to be calculated again
+            Version::Trimmed => return Err(RepCacheError::ObjNotFound(String::from(key))),
+            Version::Missing => (),
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                file = branch.parent;
+                offset = branch.offset;
+            }
+            None => return Err(RepCacheError::ObjNotFound(String::from(key))),
         }
-        None => Err(RepCacheError::ObjNotFound(String::from(key))),
     }
 }
 
 pub async fn update_object_rep_cache(
-    conn: &mut MultiplexedConnection,
+    conn: &dyn Store,
     file: &str,
     input: &[u8],
 ) -> Result<(), RepCacheError> {
     let object = representation::UpdateChangeMsg::decode(input)?;
     info!("Updating object rep cache: {:?}", object);
-    store_object_rep(conn, file, &object.obj_id, input).await?;
+    if let Some(branch) = &object.branch {
+        let mut serialized = Vec::new();
+        branch.encode(&mut serialized)?;
+        conn.set(branch_parent(file), serialized).await?;
+        return Ok(());
+    }
+    if let Some(tag) = &object.tag {
+        update_tag(conn, file, tag).await?;
+        return Ok(());
+    }
+    let tag_offsets = get_tag_offsets(conn, file).await?;
+    store_object_rep(conn, file, &object.obj_id, object.lod, input, &tag_offsets).await?;
+    if object.lod == representation::LevelOfDetail::Full as i32 {
+        crate::region::store_place(conn, file, &object, &tag_offsets).await?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use representation::LevelOfDetail;
+
+    fn rep(offset: i64, lod: LevelOfDetail) -> Vec<u8> {
+        let rep = representation::UpdateChangeMsg {
+            file: String::from("file"),
+            obj_id: String::from("obj"),
+            offset,
+            lod: lod as i32,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        rep.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn test_levels_of_detail() {
+        let conn = cache_store::MemoryStore::new();
+        update_object_rep_cache(&conn, "file", &rep(1, LevelOfDetail::Full))
+            .await
+            .unwrap();
+        update_object_rep_cache(&conn, "file", &rep(1, LevelOfDetail::BoundingBox))
+            .await
+            .unwrap();
+        update_object_rep_cache(&conn, "file", &rep(2, LevelOfDetail::Full))
+            .await
+            .unwrap();
+
+        let full = get_object_rep(&conn, "file", "obj", i64::MAX, LevelOfDetail::Full as i32)
+            .await
+            .unwrap();
+        assert_eq!(full.offset, 2);
+        let bbox = get_object_rep(
+            &conn,
+            "file",
+            "obj",
+            i64::MAX,
+            LevelOfDetail::BoundingBox as i32,
+        )
+        .await
+        .unwrap();
+        assert_eq!(bbox.offset, 1);
+        assert_eq!(bbox.lod, LevelOfDetail::BoundingBox as i32);
+        let simplified = get_object_rep(
+            &conn,
+            "file",
+            "obj",
+            i64::MAX,
+            LevelOfDetail::Simplified as i32,
+        )
+        .await;
+        assert!(simplified.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let conn = cache_store::MemoryStore::new();
+        conn.set(legacy_obj_rep("file", "obj"), rep(1, LevelOfDetail::Full))
+            .await
+            .unwrap();
+        let legacy = get_object_rep(&conn, "file", "obj", i64::MAX, LevelOfDetail::Full as i32)
+            .await
+            .unwrap();
+        assert_eq!(legacy.offset, 1);
+
+        for offset in 2..(REP_HISTORY as i64 + 3) {
+            update_object_rep_cache(&conn, "file", &rep(offset, LevelOfDetail::Full))
+                .await
+                .unwrap();
+        }
+        let length: isize = conn
+            .llen(obj_rep_cache("file", "obj", LevelOfDetail::Full as i32))
+            .await
+            .unwrap();
+        assert_eq!(length, REP_HISTORY);
+        let latest = get_object_rep(&conn, "file", "obj", i64::MAX, LevelOfDetail::Full as i32)
+            .await
+            .unwrap();
+        assert_eq!(latest.offset, REP_HISTORY as i64 + 2);
+        //The oldest reps were trimmed, so the legacy one isn't what they were either
+        let trimmed = get_object_rep(&conn, "file", "obj", 2, LevelOfDetail::Full as i32).await;
+        assert!(matches!(trimmed, Err(RepCacheError::ObjNotFound(..))));
+        let trimmed = get_object_rep(&conn, "file", "obj", 1, LevelOfDetail::Full as i32).await;
+        assert!(matches!(trimmed, Err(RepCacheError::ObjNotFound(..))));
+    }
+
+    #[tokio::test]
+    async fn test_tagged_history() {
+        let conn = cache_store::MemoryStore::new();
+        let tag = |offset: i64| {
+            let rep = representation::UpdateChangeMsg {
+                tag: Some(representation::TagRepMsg {
+                    name: format!("Tag {}", offset),
+                    offset,
+                    removed: false,
+                }),
+                ..Default::default()
+            };
+            let mut bytes = Vec::new();
+            rep.encode(&mut bytes).unwrap();
+            bytes
+        };
+        update_object_rep_cache(&conn, "parent", &tag(1))
+            .await
+            .unwrap();
+        let branch = representation::UpdateChangeMsg {
+            branch: Some(representation::BranchRepMsg {
+                parent: String::from("parent"),
+                offset: 1,
+            }),
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        branch.encode(&mut bytes).unwrap();
+        update_object_rep_cache(&conn, "file", &bytes)
+            .await
+            .unwrap();
+        update_object_rep_cache(&conn, "file", &tag(3))
+            .await
+            .unwrap();
+        assert_eq!(get_tag_offsets(&conn, "file").await.unwrap().len(), 2);
+
+        for offset in 2..(REP_HISTORY as i64 + 10) {
+            update_object_rep_cache(&conn, "file", &rep(offset, LevelOfDetail::Full))
+                .await
+                .unwrap();
+        }
+        let tagged = get_object_rep(&conn, "file", "obj", 3, LevelOfDetail::Full as i32)
+            .await
+            .unwrap();
+        assert_eq!(tagged.offset, 3);
+        let untagged = get_object_rep(&conn, "file", "obj", 4, LevelOfDetail::Full as i32).await;
+        assert!(matches!(untagged, Err(RepCacheError::ObjNotFound(..))));
+    }
+}
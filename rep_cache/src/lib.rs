@@ -0,0 +1,95 @@
+use cache_store::Store;
+use commit_log::CommitLog;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use trace_lib::*;
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod cache;
+mod consume;
+mod region;
+use cache::*;
+use consume::*;
+use region::*;
+
+mod representation {
+    tonic::include_proto!("representation");
+}
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+
+mod rep_cache {
+    tonic::include_proto!("rep_cache");
+}
+use rep_cache::*;
+
+#[instrument]
+async fn get_redis_conn(url: &str) -> Result<Arc<dyn Store>, tonic::Status> {
+    cache_store::connect(url)
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("{:?}", e)))
+}
+
+#[derive(Debug)]
+struct RepCacheService {
+    redis_url: String,
+}
+
+#[tonic::async_trait]
+impl rep_cache_server::RepCache for RepCacheService {
+    #[instrument]
+    async fn get_object_representations(
+        &self,
+        request: Request<GetObjectRepresentationsInput>,
+    ) -> Result<Response<GetObjectRepresentationsOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let mut reps = Vec::new();
+        for id in &msg.obj_ids {
+            let rep = cache::get_object_rep(&redis_conn, &msg.file, &id, msg.offset, msg.lod)
+                .instrument(info_span!("get_object_rep"))
+                .await
+                .map_err(to_status)?;
+            reps.push(rep);
+        }
+        Ok(Response::new(GetObjectRepresentationsOutput { reps }))
+    }
+
+    #[instrument]
+    async fn get_objects_in_region(
+        &self,
+        request: Request<GetObjectsInRegionInput>,
+    ) -> Result<Response<GetObjectsInRegionOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let region = ViewRegion::from_msg(msg).map_err(to_status)?;
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let obj_ids = region::objects_in_region(&redis_conn, &msg.file, msg.offset, &region)
+            .instrument(info_span!("objects_in_region"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(GetObjectsInRegionOutput { obj_ids }))
+    }
+}
+
+///Serves the representation cache while keeping it up to date from the representations topic
+pub async fn run(
+    run_url: SocketAddr,
+    redis_url: String,
+    log: Arc<dyn CommitLog>,
+    group: String,
+    topic: String,
+) -> Result<(), tonic::transport::Error> {
+    tokio::spawn(update_cache(redis_url.clone(), log, group, topic));
+
+    let svc = rep_cache_server::RepCacheServer::new(RepCacheService { redis_url });
+
+    info!("Running on {:?}", run_url);
+    Server::builder().add_service(svc).serve(run_url).await
+}
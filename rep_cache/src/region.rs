@@ -0,0 +1,420 @@
+//! Where objects are, so clients can open just the part of a file they're looking at.
+//! Each object keeps a history of places alongside its representations.  It's also filed under every cell of a grid its
+//! bounds have touched, so a region only reads the objects filed under the cells it covers.
+
+use super::*;
+use cache_store::history::{self, Version};
+use cache_store::{Store, StoreCommands};
+use prost::Message;
+use std::collections::BTreeSet;
+
+///The width and depth of each cell of the grid objects are filed under
+pub const CELL_SIZE: f64 = 10.0;
+///Objects covering more cells than this are filed once, in a set every region reads
+pub const MAX_CELLS: i64 = 64;
+
+fn obj_places(file: &str, key: &str) -> String {
+    format!("{}:{}:places", file, key)
+}
+
+fn cell(file: &str, name: &str) -> String {
+    format!("{}:cell:{}", file, name)
+}
+
+//The names of every cell anything has been filed under
+fn cells(file: &str) -> String {
+    format!("{}:cells", file)
+}
+
+fn unplaced(file: &str) -> String {
+    format!("{}:unplaced", file)
+}
+
+fn large(file: &str) -> String {
+    format!("{}:large", file)
+}
+
+fn cell_name(i: i64, j: i64) -> String {
+    format!("{}:{}", i, j)
+}
+
+///An axis aligned box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Bounds {
+    ///Transformed boxes can have their corners swapped, so sort them out per axis
+    pub fn new(first: [f64; 3], second: [f64; 3]) -> Bounds {
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        for axis in 0..3 {
+            min[axis] = first[axis].min(second[axis]);
+            max[axis] = first[axis].max(second[axis]);
+        }
+        Bounds { min, max }
+    }
+
+    fn from_msg(msg: &representation::BoundsMsg) -> Option<Bounds> {
+        match (&msg.bottom_left, &msg.top_right) {
+            (Some(first), Some(second)) => Some(Bounds::new(to_array(first), to_array(second))),
+            _ => None,
+        }
+    }
+
+    fn is_finite(&self) -> bool {
+        self.min
+            .iter()
+            .chain(self.max.iter())
+            .all(|c| c.is_finite())
+    }
+
+    fn middle(&self) -> [f64; 3] {
+        let middle = |axis: usize| self.min[axis] + (self.max[axis] - self.min[axis]) / 2.0;
+        [middle(0), middle(1), middle(2)]
+    }
+
+    ///Whether two boxes overlap.  Boxes that only touch count.
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    ///How far the point is from the nearest part of the box, which is 0 if it's inside
+    pub fn distance_to(&self, pt: [f64; 3]) -> f64 {
+        (0..3)
+            .map(|axis| {
+                let nearest = pt[axis].max(self.min[axis]).min(self.max[axis]);
+                (nearest - pt[axis]).powi(2)
+            })
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    //The first and last cell along x, then along y
+    fn cell_range(&self) -> ((i64, i64), (i64, i64)) {
+        let cell = |coord: f64| (coord / CELL_SIZE).floor() as i64;
+        (
+            (cell(self.min[0]), cell(self.max[0])),
+            (cell(self.min[1]), cell(self.max[1])),
+        )
+    }
+}
+
+fn to_array(pt: &geom::Point3Msg) -> [f64; 3] {
+    [pt.x, pt.y, pt.z]
+}
+
+///The part of the file a client is looking at.  Objects nearer the eye come first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewRegion {
+    pub bounds: Bounds,
+    pub eye: [f64; 3],
+}
+
+impl ViewRegion {
+    ///The region asked for, looked at from its middle if the eye isn't given
+    pub fn from_msg(msg: &GetObjectsInRegionInput) -> Result<ViewRegion, RepCacheError> {
+        let (first, second) = match (&msg.bottom_left, &msg.top_right) {
+            (Some(first), Some(second)) => (to_array(first), to_array(second)),
+            _ => {
+                return Err(RepCacheError::InvalidArgument(String::from(
+                    "View region needs both corners",
+                )))
+            }
+        };
+        let bounds = Bounds::new(first, second);
+        let eye = match &msg.eye {
+            Some(eye) => to_array(eye),
+            None => bounds.middle(),
+        };
+        if !bounds.is_finite() || !eye.iter().all(|c| c.is_finite()) {
+            return Err(RepCacheError::InvalidArgument(String::from(
+                "View region must be finite",
+            )));
+        }
+        Ok(ViewRegion { bounds, eye })
+    }
+}
+
+///Files the object under its place as of the change.  Only full representations are passed in, since every level of detail is in the same place.
+pub async fn store_place(
+    conn: &dyn Store,
+    file: &str,
+    rep: &representation::UpdateChangeMsg,
+    tag_offsets: &[i64],
+) -> Result<(), RepCacheError> {
+    let update = rep.update.as_ref();
+    let deleted = matches!(
+        update.and_then(|update| update.output.as_ref()),
+        Some(representation::update_output_msg::Output::Delete(..))
+    );
+    let place = PlaceMsg {
+        offset: rep.offset,
+        bounds: update.and_then(|update| update.bounds.clone()),
+        deleted,
+    };
+    let mut serialized = Vec::new();
+    place.encode(&mut serialized)?;
+    let obj_places = obj_places(file, &rep.obj_id);
+    //Kept as long as the representations, including for tags
+    history::push(
+        conn,
+        &obj_places,
+        None,
+        serialized,
+        REP_HISTORY,
+        tag_offsets,
+        place_offset,
+    )
+    .await?;
+    if deleted {
+        return Ok(());
+    }
+    //Objects stay filed where they've been, and are checked against their place as of the offset when read
+    match place.bounds.as_ref().and_then(Bounds::from_msg) {
+        Some(bounds) => {
+            let ((first_i, last_i), (first_j, last_j)) = bounds.cell_range();
+            let count = (last_i.saturating_sub(first_i) + 1)
+                .saturating_mul(last_j.saturating_sub(first_j) + 1);
+            if !bounds.is_finite() || count > MAX_CELLS {
+                conn.sadd(large(file), &rep.obj_id).await?;
+                return Ok(());
+            }
+            for i in first_i..=last_i {
+                for j in first_j..=last_j {
+                    let name = cell_name(i, j);
+                    conn.sadd(cell(file, &name), &rep.obj_id).await?;
+                    conn.sadd(cells(file), name).await?;
+                }
+            }
+        }
+        None => conn.sadd(unplaced(file), &rep.obj_id).await?,
+    }
+    Ok(())
+}
+
+fn place_offset(place_bin: &[u8]) -> Result<i64, RepCacheError> {
+    Ok(PlaceMsg::decode(place_bin)?.offset)
+}
+
+//Where the object was as of the offset, reading from the parent for anything a branch hasn't changed
+async fn get_place(
+    conn: &dyn Store,
+    file: &str,
+    key: &str,
+    offset: i64,
+) -> Result<Option<PlaceMsg>, RepCacheError> {
+    let mut file = String::from(file);
+    let mut offset = offset;
+    loop {
+        let obj_places = obj_places(&file, key);
+        match history::find(conn, &obj_places, None, offset, place_offset).await? {
+            Version::Found(place_bin) => return Ok(Some(PlaceMsg::decode(place_bin.as_ref())?)),
+            //Where it was is gone, so it's sent like objects that aren't anywhere in particular
+            Version::Trimmed => {
+                return Ok(Some(PlaceMsg {
+                    offset,
+                    bounds: None,
+                    deleted: false,
+                }))
+            }
+            Version::Missing => (),
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => {
+                file = branch.parent;
+                offset = branch.offset;
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+//Every object that might be in the region, in the file or the files it branched from
+async fn candidates(
+    conn: &dyn Store,
+    file: &str,
+    region: &ViewRegion,
+) -> Result<BTreeSet<String>, RepCacheError> {
+    let ((first_i, last_i), (first_j, last_j)) = region.bounds.cell_range();
+    let mut candidates = BTreeSet::new();
+    let mut file = String::from(file);
+    loop {
+        let unplaced: Vec<String> = conn.smembers(unplaced(&file)).await?;
+        let large: Vec<String> = conn.smembers(large(&file)).await?;
+        candidates.extend(unplaced);
+        candidates.extend(large);
+        //Only cells with something in them are read, however big the region is
+        let names: Vec<String> = conn.smembers(cells(&file)).await?;
+        for name in names {
+            let mut coords = name.splitn(2, ':').map(str::parse::<i64>);
+            if let (Some(Ok(i)), Some(Ok(j))) = (coords.next(), coords.next()) {
+                if first_i <= i && i <= last_i && first_j <= j && j <= last_j {
+                    let objs: Vec<String> = conn.smembers(cell(&file, &name)).await?;
+                    candidates.extend(objs);
+                }
+            }
+        }
+        match get_branch_parent(conn, &file).await? {
+            Some(branch) => file = branch.parent,
+            None => return Ok(candidates),
+        }
+    }
+}
+
+///The objects, as of the offset, whose bounds intersect the region, nearest the eye first.
+///Objects without a position, like sheets and symbol definitions, aren't anywhere in particular, so they always come first.
+pub async fn objects_in_region(
+    conn: &dyn Store,
+    file: &str,
+    offset: i64,
+    region: &ViewRegion,
+) -> Result<Vec<String>, RepCacheError> {
+    let mut unplaced = Vec::new();
+    let mut placed = Vec::new();
+    for key in candidates(conn, file, region).await? {
+        let place = match get_place(conn, file, &key, offset).await? {
+            Some(place) if !place.deleted => place,
+            _ => continue,
+        };
+        match place.bounds.as_ref().and_then(Bounds::from_msg) {
+            Some(bounds) => {
+                if bounds.intersects(&region.bounds) {
+                    placed.push((bounds.distance_to(region.eye), key));
+                }
+            }
+            None => unplaced.push(key),
+        }
+    }
+    //A stable sort keeps objects the same distance away in ID order
+    placed.sort_by(|(first, _), (second, _)| {
+        first
+            .partial_cmp(second)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    unplaced.extend(placed.into_iter().map(|(_, key)| key));
+    Ok(unplaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use representation::update_output_msg::Output;
+
+    fn point(x: f64, y: f64, z: f64) -> Option<geom::Point3Msg> {
+        Some(geom::Point3Msg { x, y, z })
+    }
+
+    fn rep(obj_id: &str, offset: i64, output: Output, bounds: Option<(f64, f64)>) -> Vec<u8> {
+        let bounds = bounds.map(|(start, end)| representation::BoundsMsg {
+            bottom_left: point(start, -0.5, 0.0),
+            top_right: point(end, 0.5, 3.0),
+        });
+        let rep = representation::UpdateChangeMsg {
+            file: String::from("file"),
+            obj_id: String::from(obj_id),
+            offset,
+            update: Some(representation::UpdateOutputMsg {
+                output: Some(output),
+                views: None,
+                bounds,
+            }),
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        rep.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn wall(obj_id: &str, offset: i64, start: f64, end: f64) -> Vec<u8> {
+        rep(
+            obj_id,
+            offset,
+            Output::Empty(String::new()),
+            Some((start, end)),
+        )
+    }
+
+    fn region(first: [f64; 3], second: [f64; 3], eye: Option<[f64; 3]>) -> ViewRegion {
+        ViewRegion::from_msg(&GetObjectsInRegionInput {
+            bottom_left: point(first[0], first[1], first[2]),
+            top_right: point(second[0], second[1], second[2]),
+            eye: eye.and_then(|eye| point(eye[0], eye[1], eye[2])),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bounds() {
+        let bounds = Bounds::new([10.0, 5.0, 0.0], [0.0, -5.0, 5.0]);
+        assert_eq!(bounds.min, [0.0, -5.0, 0.0]);
+        assert_eq!(bounds.max, [10.0, 5.0, 5.0]);
+        assert!(bounds.intersects(&Bounds::new([10.0, 5.0, 5.0], [20.0, 20.0, 20.0])));
+        assert!(!bounds.intersects(&Bounds::new([11.0, 5.0, 5.0], [20.0, 20.0, 20.0])));
+        assert_eq!(bounds.distance_to([5.0, 0.0, 1.0]), 0.0);
+        assert_eq!(bounds.distance_to([13.0, 9.0, 1.0]), 5.0);
+        assert_eq!(bounds.middle(), [5.0, 0.0, 2.5]);
+    }
+
+    #[test]
+    fn test_view_region() {
+        let flipped = region([115.0, 5.0, 5.0], [105.0, -5.0, 0.0], None);
+        assert_eq!(flipped.eye, [110.0, 0.0, 2.5]);
+        let infinite = ViewRegion::from_msg(&GetObjectsInRegionInput {
+            bottom_left: point(0.0, 0.0, 0.0),
+            top_right: point(f64::INFINITY, 0.0, 0.0),
+            ..Default::default()
+        });
+        assert!(infinite.is_err());
+        let nan_eye = ViewRegion::from_msg(&GetObjectsInRegionInput {
+            bottom_left: point(0.0, 0.0, 0.0),
+            top_right: point(1.0, 1.0, 1.0),
+            eye: point(f64::NAN, 0.0, 0.0),
+            ..Default::default()
+        });
+        assert!(nan_eye.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_objects_in_region() {
+        let conn = cache_store::MemoryStore::new();
+        let reps = vec![
+            wall("far", 1, 40.0, 50.0),
+            wall("near", 2, 10.0, 20.0),
+            wall("outside", 3, 100.0, 110.0),
+            wall("long", 4, -1000.0, 1000.0),
+            rep("sheet", 5, Output::OtherJson(String::from("{}")), None),
+            wall("moved", 6, 200.0, 210.0),
+            wall("moved", 7, 30.0, 35.0),
+            wall("deleted", 8, 5.0, 6.0),
+            rep("deleted", 9, Output::Delete(String::new()), None),
+        ];
+        for rep in reps {
+            crate::cache::update_object_rep_cache(&conn, "file", &rep)
+                .await
+                .unwrap();
+        }
+        let long: Vec<String> = conn.smembers(large("file")).await.unwrap();
+        assert_eq!(long, vec![String::from("long")]);
+
+        let view = region([0.0, -5.0, 0.0], [60.0, 5.0, 5.0], Some([0.0, 0.0, 1.0]));
+        assert_eq!(
+            objects_in_region(&conn, "file", i64::MAX, &view)
+                .await
+                .unwrap(),
+            vec!["sheet", "long", "near", "moved", "far"]
+        );
+        //Before the move and the delete, and before the sheet was added
+        assert_eq!(
+            objects_in_region(&conn, "file", 4, &view).await.unwrap(),
+            vec!["long", "near", "far"]
+        );
+        assert_eq!(
+            objects_in_region(&conn, "file", 8, &view).await.unwrap(),
+            vec!["sheet", "long", "deleted", "near", "moved", "far"]
+        );
+    }
+}
@@ -1,60 +1,43 @@
 use crate::*;
-use futures::StreamExt;
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::stream_consumer::StreamConsumer;
-use rdkafka::consumer::{CommitMode, Consumer};
-use rdkafka::message::Message;
+use commit_log::{CommitLog, Record, StartAt};
+use std::sync::Arc;
 
 #[instrument]
-async fn handle_message<M: Message + std::fmt::Debug>(
-    broker: &str,
+async fn handle_message(
+    log: &dyn CommitLog,
     repr_topic: &str,
     ops_url: String,
-    m: &M,
+    record: &Record,
 ) -> Result<(), RepresentationError> {
-    let partition = m.partition();
-    let offset = m.offset();
-    let bytes = m
-        .payload()
-        .ok_or(RepresentationError::PayloadError { partition, offset })?;
-    let file_bytes = m
-        .key()
-        .ok_or(RepresentationError::FileError { partition, offset })?;
-    let file = std::str::from_utf8(file_bytes)?;
-    calc_representation(broker, repr_topic, file, ops_url, bytes).await?;
+    calc_representation(
+        log,
+        repr_topic,
+        &record.key,
+        record.offset,
+        ops_url,
+        &record.payload,
+    )
+    .await?;
     Ok(())
 }
 
 async fn handle_stream(
-    brokers: &str,
+    log: &dyn CommitLog,
     group_id: &str,
     obj_topic: &str,
     repr_topic: &str,
     ops_url: String,
 ) -> Result<(), RepresentationError> {
-    let consumer: StreamConsumer<rdkafka::consumer::DefaultConsumerContext> = ClientConfig::new()
-        .set("group.id", group_id)
-        .set("bootstrap.servers", brokers)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()?;
-
-    consumer.subscribe(&[obj_topic])?;
+    let mut consumer = log.consume(obj_topic, group_id, StartAt::Committed).await?;
     println!("Subscribed to {:?}", obj_topic);
 
-    // consumer.start() returns a stream. The stream can be used ot chain together expensive steps,
-    // such as complex computations on a thread pool or asynchronous IO.
-    let mut message_stream = consumer.start();
-
-    while let Some(message) = message_stream.next().await {
-        match message {
-            Ok(m) => {
-                if let Err(e) = handle_message(brokers, repr_topic, ops_url.clone(), &m).await {
+    while let Some(record) = consumer.next().await {
+        match record {
+            Ok(record) => {
+                if let Err(e) = handle_message(log, repr_topic, ops_url.clone(), &record).await {
                     error!("{}", e);
                 }
-                if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
+                if let Err(e) = consumer.commit(&record).await {
                     error!("{}", e);
                 }
             }
@@ -67,15 +50,14 @@ async fn handle_stream(
 }
 
 pub async fn start_consume_stream(
-    brokers: String,
+    log: Arc<dyn CommitLog>,
     group_id: String,
     obj_topic: String,
     repr_topic: String,
     ops_url: String,
 ) {
-    std::thread::sleep(std::time::Duration::from_secs(30));
     println!("Start consuming stream on topic {:?}", obj_topic);
-    if let Err(e) = handle_stream(&brokers, &group_id, &obj_topic, &repr_topic, ops_url).await {
+    if let Err(e) = handle_stream(log.as_ref(), &group_id, &obj_topic, &repr_topic, ops_url).await {
         println!("{}", e);
     }
 }
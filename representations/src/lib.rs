@@ -0,0 +1,151 @@
+use commit_log::CommitLog;
+use prost::Message;
+use std::sync::Arc;
+use thiserror::Error;
+use trace_lib::*;
+use tracing::*;
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+
+mod object_state {
+    tonic::include_proto!("object_state");
+}
+use object_state::*;
+
+mod representation {
+    tonic::include_proto!("representation");
+}
+use representation::*;
+
+mod operations {
+    tonic::include_proto!("operations");
+}
+use operations::*;
+
+mod consume;
+mod produce;
+
+#[derive(Debug, Error)]
+pub enum RepresentationError {
+    #[error("Commit log error: {0}")]
+    LogError(#[from] commit_log::LogError),
+    #[error("Transport error: {0}")]
+    TransportError(#[from] tonic::transport::Error),
+    #[error("Service error: {0}")]
+    ServiceError(#[from] tonic::Status),
+    #[error("Prost encode error: {0}")]
+    ProstEncodeError(#[from] prost::EncodeError),
+    #[error("Prost decode error: {0}")]
+    ProstDecodeError(#[from] prost::DecodeError),
+    #[error("No change type set")]
+    NoChangeType,
+}
+
+//Coarsest first, so clients watching the stream get something to show as soon as possible
+const LODS: [LevelOfDetail; 3] = [
+    LevelOfDetail::BoundingBox,
+    LevelOfDetail::Simplified,
+    LevelOfDetail::Full,
+];
+
+#[instrument]
+async fn call_service(
+    ops_url: String,
+    object: ChangeMsg,
+) -> Result<Vec<(LevelOfDetail, UpdateOutputMsg)>, RepresentationError> {
+    let mut client = operations::operations_client::OperationsClient::connect(ops_url).await?;
+    let resp = client
+        .client_representation(TracedRequest::new(ClientRepresentationInput {
+            objects: vec![object],
+            lods: LODS.iter().map(|lod| *lod as i32).collect(),
+        }))
+        .await;
+    let representation = trace_response(resp)?;
+    Ok(LODS.iter().copied().zip(representation.outputs).collect())
+}
+
+#[instrument]
+pub async fn calc_representation(
+    log: &dyn commit_log::CommitLog,
+    topic: &str,
+    file: &str,
+    offset: i64,
+    ops_url: String,
+    msg: &[u8],
+) -> Result<(), RepresentationError> {
+    let change = object_state::ChangeMsg::decode(msg)?;
+    debug!("Got change: {:?}", change);
+    let obj_id = match &change.change_type {
+        Some(change_msg::ChangeType::Add(object))
+        | Some(change_msg::ChangeType::Modify(object)) => object.id.clone(),
+        Some(change_msg::ChangeType::Delete(msg)) => msg.id.clone(),
+        Some(change_msg::ChangeType::Branch(branch)) => {
+            //There's nothing to calculate, but the representation caches need to know where the branch came from
+            let update_change = UpdateChangeMsg {
+                file: String::from(file),
+                user: change.user.clone(),
+                obj_id: String::new(),
+                update: None,
+                offset,
+                branch: Some(BranchRepMsg {
+                    parent: branch.parent.clone(),
+                    offset: branch.offset,
+                }),
+                lod: LevelOfDetail::Full as i32,
+                tag: None,
+            };
+            produce::submit_representations(log, topic, file, update_change).await?;
+            return Ok(());
+        }
+        Some(change_msg::ChangeType::Tag(tag)) => {
+            //Likewise, the representation caches need to keep what the tag reads
+            let update_change = UpdateChangeMsg {
+                file: String::from(file),
+                user: change.user.clone(),
+                obj_id: String::new(),
+                update: None,
+                offset,
+                branch: None,
+                lod: LevelOfDetail::Full as i32,
+                tag: Some(TagRepMsg {
+                    name: tag.name.clone(),
+                    offset: tag.offset,
+                    removed: tag.removed,
+                }),
+            };
+            produce::submit_representations(log, topic, file, update_change).await?;
+            return Ok(());
+        }
+        None => return Err(RepresentationError::NoChangeType),
+    };
+    let user = change.user.clone();
+    let reprs = call_service(ops_url, change).await?;
+    info!("Got representations: {:?}", reprs);
+    for (lod, repr) in reprs {
+        let update_change = UpdateChangeMsg {
+            file: String::from(file),
+            user: user.clone(),
+            obj_id: obj_id.clone(),
+            update: Some(repr),
+            offset,
+            branch: None,
+            lod: lod as i32,
+            tag: None,
+        };
+        produce::submit_representations(log, topic, file, update_change).await?;
+    }
+    Ok(())
+}
+
+///Recalculates representations of changed objects until the commit log goes away
+pub async fn run(
+    log: Arc<dyn CommitLog>,
+    group: String,
+    obj_topic: String,
+    repr_topic: String,
+    ops_url: String,
+) {
+    consume::start_consume_stream(log, group, obj_topic, repr_topic, ops_url).await;
+}
@@ -1,51 +1,23 @@
 use super::*;
-use futures::FutureExt;
+use commit_log::{CommitLog, LogError};
 use prost::Message;
-use rdkafka::config::ClientConfig;
-//use rdkafka::message::Message;
-use rdkafka::producer::{FutureProducer, FutureRecord};
 
 #[instrument]
 pub async fn submit_changes(
-    brokers: &str,
+    log: &dyn CommitLog,
     topic_name: &str,
     file: &str,
-    payloads: Vec<ChangeMsg>,
-) -> Vec<i64> {
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", brokers)
-        .set("message.timeout.ms", "5000")
-        .create()
-        .expect("Producer creation error");
-
-    // This loop is non blocking: all messages will be sent one after the other, without waiting
-    // for the results.
-    let futures = payloads
+    changes: Vec<ChangeMsg>,
+) -> Result<Vec<i64>, LogError> {
+    let payloads = changes
         .into_iter()
         .map(|msg| {
             let mut payload = Vec::new();
             msg.encode(&mut payload).unwrap();
-            // The send operation on the topic returns a future, that will be completed once the
-            // result or failure from Kafka will be received.
-            producer
-                .send(
-                    FutureRecord::to(topic_name)
-                        .payload(&payload)
-                        .key(&String::from(file)),
-                    0,
-                )
-                .map(move |delivery_status| {
-                    debug!("Delivery status for message received from file {}", file);
-                    delivery_status
-                })
+            payload
         })
-        .collect::<Vec<_>>();
-
-    // This loop will wait until all delivery statuses have been received received.
-    let mut offsets = Vec::new();
-    for future in futures {
-        let (_, offset) = future.await.unwrap().unwrap();
-        offsets.push(offset);
-    }
-    offsets
+        .collect();
+    let offsets = log.append(topic_name, file, payloads).await?;
+    debug!("Delivered messages for file {}", file);
+    Ok(offsets)
 }
@@ -23,6 +23,12 @@ fn extract_info(changes: Vec<ChangeMsg>) -> (Vec<RefIdMsg>, IndexMap<String, Cha
                 change_msg::ChangeType::Delete(msg) => {
                     objects.insert(msg.id.clone(), change);
                 }
+                change_msg::ChangeType::Branch(..) => {
+                    warn!("Branches are created with CreateBranch, skipping");
+                }
+                change_msg::ChangeType::Tag(..) => {
+                    warn!("Tags are created with SubmitTag, skipping");
+                }
             }
         }
     }
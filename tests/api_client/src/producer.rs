@@ -5,6 +5,7 @@ pub async fn begin_undo_event(client: &mut ApiClient, file: &String, user: &Stri
     let undo_input = BeginUndoEventInput {
         file: file.clone(),
         user: user.clone(),
+        branch: String::new(),
     };
     client.begin_undo_event(Request::new(undo_input)).await?;
     Ok(())
@@ -21,6 +22,7 @@ pub async fn undo_latest(
             file: file.clone(),
             user: user.clone(),
             offset,
+            branch: String::new(),
         }),
     };
     let output = client.undo_latest(Request::new(input)).await?.into_inner();
@@ -38,6 +40,7 @@ pub async fn redo_latest(
             file: file.clone(),
             user: user.clone(),
             offset,
+            branch: String::new(),
         }),
     };
     let output = client.redo_latest(Request::new(input)).await?.into_inner();
@@ -16,6 +16,7 @@ async fn create_floor(
         file: file.clone(),
         user: user.clone(),
         offset: 0,
+        branch: String::new(),
     };
     let (sheet_id, offset) = create_sheet(
         client,
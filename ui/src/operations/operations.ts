@@ -75,7 +75,8 @@ export function subToFile(name: string, user: string) {
     var sub = {
         "Subscribe": {
             "filename": name,
-            "user": user
+            "user": user,
+            "capabilities": ["CompactMeshes"]
         }
     };
     var msg = JSON.stringify(sub);
@@ -132,6 +133,41 @@ export function redoLatest() {
 export function deleteObject(event: string, id: string) {
 }
 
+//Unpacks a CompactMeshMsg into the same getters a MeshDataMsg has.  We don't ask for compressed meshes, so the buffers are raw.
+function expandCompactMesh(compact: any) {
+    var min = compact.getMin();
+    var max = compact.getMax();
+    var low = [min.getX(), min.getY(), min.getZ()];
+    var size = [max.getX() - low[0], max.getY() - low[1], max.getZ() - low[2]];
+    var packed = compact.getPositions_asU8();
+    var view = new DataView(packed.buffer, packed.byteOffset, packed.byteLength);
+    var positions = [];
+    for (var i = 0; i < packed.byteLength / 2; i++) {
+        positions.push(low[i % 3] + view.getUint16(i * 2, true) / 65535 * size[i % 3]);
+    }
+    var packedIndices = compact.getIndices_asU8();
+    var indexView = new DataView(packedIndices.buffer, packedIndices.byteOffset, packedIndices.byteLength);
+    var wide = compact.getIndexWidth() == updates.IndexWidth.U32;
+    var indices = [];
+    for (var i = 0; i < packedIndices.byteLength; i += wide ? 4 : 2) {
+        indices.push(wide ? indexView.getUint32(i, true) : indexView.getUint16(i, true));
+    }
+    var normals = Array.from(new Int8Array(compact.getNormals_asU8()), n => n / 127);
+    var packedUvs = compact.getUvs_asU8();
+    var uvView = new DataView(packedUvs.buffer, packedUvs.byteOffset, packedUvs.byteLength);
+    var uvs = [];
+    for (var i = 0; i < packedUvs.byteLength; i += 4) {
+        uvs.push(uvView.getFloat32(i, true));
+    }
+    return {
+        getPositionsList: () => positions,
+        getIndicesList: () => indices,
+        getNormalsList: () => normals,
+        getUvsList: () => uvs,
+        getMetaJson: () => compact.getMetaJson(),
+    };
+}
+
 function handleUpdate(msg: UpdateChangeMsg) {
     var file = msg.getFile();
     var id = msg.getObjId();
@@ -157,6 +193,13 @@ function handleUpdate(msg: UpdateChangeMsg) {
             }
             renderer.renderMesh(id, mesh, parent);
             break;
+        case updates.UpdateOutputMsg.OutputCase.COMPACT_MESH:
+            var parent = null;
+            if (file != filename) {
+                parent = file_to_sym_def[file];
+            }
+            renderer.renderMesh(id, expandCompactMesh(update.getCompactMesh()), parent);
+            break;
         case updates.UpdateOutputMsg.OutputCase.INSTANCE:
             var instance = update.getInstance();
             renderer.createInstances(id, instance);
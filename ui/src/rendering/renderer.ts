@@ -164,6 +164,15 @@ export class Renderer {
         var vertexData = new BABYLON.VertexData();
         vertexData.positions = positions;
         vertexData.indices = indices;
+        //Representations from before normals and UVs were added come without them
+        var normals = triangles.getNormalsList();
+        if (normals.length > 0) {
+            vertexData.normals = normals;
+        }
+        var uvs = triangles.getUvsList();
+        if (uvs.length > 0) {
+            vertexData.uvs = uvs;
+        }
         vertexData.applyToMesh(mesh);
     }
 
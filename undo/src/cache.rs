@@ -1,10 +1,10 @@
 //! The object cache for a file maps objIDs to a list of the last X number of changes to that object.
 //! X is configurable.
 
+use cache_store::{Store, StoreCommands};
 use prost::Message;
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use uuid::Uuid;
 
 use crate::*;
@@ -17,7 +17,12 @@ fn redo_stack(file: &str, user: &str) -> String {
     format!("{}:{}:redo", file, user)
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+//Every change made to the file by any user, scored by offset, so past changes can be reverted
+fn change_log(file: &str) -> String {
+    format!("{}:changes", file)
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum UndoChangeType {
     Add,
     Modify,
@@ -33,7 +38,7 @@ pub struct UndoEntry {
 }
 
 async fn add_entry_to_event(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     event: &str,
     obj_id: String,
     offset: i64,
@@ -51,7 +56,7 @@ async fn add_entry_to_event(
 }
 
 async fn get_event_entries(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     event: &str,
 ) -> Result<Vec<UndoEntry>, UndoError> {
     let serialized: Vec<Vec<u8>> = redis_conn.lrange(event, 0, -1).await?;
@@ -63,7 +68,7 @@ async fn get_event_entries(
 }
 
 async fn push_event_to_stack(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     stack: &str,
     event: &str,
 ) -> Result<(), UndoError> {
@@ -72,7 +77,7 @@ async fn push_event_to_stack(
 }
 
 async fn delete_event_if_empty(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     stack: &str,
     event: &str,
 ) -> Result<(), UndoError> {
@@ -84,7 +89,7 @@ async fn delete_event_if_empty(
 }
 
 async fn pop_entry_from_event(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     event: &str,
     obj_id: &str,
@@ -111,7 +116,7 @@ async fn pop_entry_from_event(
 }
 
 async fn get_current_event_in_stack(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
     stack: &str,
@@ -127,7 +132,7 @@ async fn get_current_event_in_stack(
 }
 
 async fn update_event_in_stack(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
     stack: &str,
@@ -141,7 +146,7 @@ async fn update_event_in_stack(
 }
 
 async fn move_entry_between_stacks(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
     from_stack: &str,
@@ -166,8 +171,20 @@ async fn move_entry_between_stacks(
     Ok(())
 }
 
+async fn log_change(
+    redis_conn: &dyn Store,
+    file: &str,
+    entry: &UndoEntry,
+) -> Result<(), UndoError> {
+    let serialized = bincode::serialize(entry)?;
+    redis_conn
+        .zadd(change_log(file), serialized, entry.offset)
+        .await?;
+    Ok(())
+}
+
 async fn update_undo_cache_inner(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     offset: i64,
     msg: ChangeMsg,
@@ -177,12 +194,26 @@ async fn update_undo_cache_inner(
         Some(change_msg::ChangeType::Add(inner_msg)) => (inner_msg.id, UndoChangeType::Add),
         Some(change_msg::ChangeType::Modify(inner_msg)) => (inner_msg.id, UndoChangeType::Modify),
         Some(change_msg::ChangeType::Delete(inner_msg)) => (inner_msg.id, UndoChangeType::Delete),
+        //A branch starts with empty undo stacks, the parent's history isn't undoable from it
+        Some(change_msg::ChangeType::Branch(..)) => return Ok(()),
+        //Tagging doesn't change any objects
+        Some(change_msg::ChangeType::Tag(..)) => return Ok(()),
         None => (String::new(), UndoChangeType::NotSet),
     };
+    if change_type != UndoChangeType::NotSet {
+        let entry = UndoEntry {
+            offset,
+            obj_id: obj_id.clone(),
+            change_type: change_type.clone(),
+        };
+        log_change(redis_conn, file, &entry).await?;
+    }
     let undo_stack = undo_stack(file, &user);
     let redo_stack = redo_stack(file, &user);
     match msg.change_source {
-        Some(change_msg::ChangeSource::UserAction(..)) => {
+        //A revert is undone like any other change the user makes
+        Some(change_msg::ChangeSource::UserAction(..))
+        | Some(change_msg::ChangeSource::Revert(..)) => {
             update_event_in_stack(
                 redis_conn,
                 file,
@@ -228,7 +259,7 @@ async fn update_undo_cache_inner(
 }
 
 pub async fn update_undo_cache(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     offset: i64,
     msg_bytes: &[u8],
@@ -239,7 +270,7 @@ pub async fn update_undo_cache(
     Ok(())
 }
 
-async fn begin_event(redis_conn: &mut MultiplexedConnection, stack: &str) -> Result<(), UndoError> {
+async fn begin_event(redis_conn: &dyn Store, stack: &str) -> Result<(), UndoError> {
     let event = Uuid::new_v4().to_string();
     info!(
         "Creating new event with id {:?} for stack {:?}",
@@ -250,7 +281,7 @@ async fn begin_event(redis_conn: &mut MultiplexedConnection, stack: &str) -> Res
 }
 
 pub async fn begin_undo_event(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
 ) -> Result<(), UndoError> {
@@ -260,7 +291,7 @@ pub async fn begin_undo_event(
 }
 
 async fn begin_redo_event(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
 ) -> Result<(), UndoError> {
@@ -270,7 +301,7 @@ async fn begin_redo_event(
 }
 
 async fn get_current_event_and_list(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
     stack: &str,
@@ -283,7 +314,7 @@ async fn get_current_event_and_list(
 }
 
 pub async fn undo(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
 ) -> Result<(String, Vec<UndoEntry>), UndoError> {
@@ -295,7 +326,7 @@ pub async fn undo(
 }
 
 pub async fn redo(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &dyn Store,
     file: &str,
     user: &str,
 ) -> Result<(String, Vec<UndoEntry>), UndoError> {
@@ -306,32 +337,87 @@ pub async fn redo(
     Ok(results)
 }
 
+///Every change made to the file from the start offset up to and including the end one, oldest first
+pub async fn get_changes_in_range(
+    redis_conn: &dyn Store,
+    file: &str,
+    start: i64,
+    end: i64,
+) -> Result<Vec<UndoEntry>, UndoError> {
+    let serialized: Vec<Vec<u8>> = redis_conn
+        .zrangebyscore(change_log(file), start, end)
+        .await?;
+    let mut results = Vec::new();
+    for entry in serialized {
+        results.push(bincode::deserialize(&entry)?);
+    }
+    if results.is_empty() {
+        return Err(UndoError::NoChangesToRevert(
+            format!("{}..={}", start, end),
+            String::from(file),
+        ));
+    }
+    Ok(results)
+}
+
+///Every change still in an undo event, whoever it belongs to
+pub async fn get_event(
+    redis_conn: &dyn Store,
+    file: &str,
+    event: &str,
+) -> Result<Vec<UndoEntry>, UndoError> {
+    let results = get_event_entries(redis_conn, event).await?;
+    if results.is_empty() {
+        return Err(UndoError::NoChangesToRevert(
+            String::from(event),
+            String::from(file),
+        ));
+    }
+    Ok(results)
+}
+
+///The objects changed in the file after the offset
+pub async fn get_changed_after(
+    redis_conn: &dyn Store,
+    file: &str,
+    offset: i64,
+) -> Result<HashSet<String>, UndoError> {
+    let serialized: Vec<Vec<u8>> = redis_conn
+        .zrangebyscore(change_log(file), offset + 1, "+inf")
+        .await?;
+    let mut results = HashSet::new();
+    for entry in serialized {
+        let entry: UndoEntry = bincode::deserialize(&entry)?;
+        results.insert(entry.obj_id);
+    }
+    Ok(results)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use uuid::Uuid;
 
-    pub async fn test_get_conn() -> MultiplexedConnection {
+    pub async fn test_get_conn() -> Arc<dyn Store> {
+        //Runs against a real Redis when REDIS_URL is set
         let env_opt = std::env::var("REDIS_URL");
         let redis_url = if let Ok(url) = env_opt {
             url
         } else {
-            String::from("redis://127.0.0.1:6379")
+            String::from("memory://tests")
         };
-        let client = redis::Client::open(redis_url).unwrap();
-        let (conn, fut) = client.get_multiplexed_async_connection().await.unwrap();
-        tokio::spawn(fut);
-        conn
+        cache_store::connect(&redis_url).await.unwrap()
     }
 
     #[tokio_macros::test]
     async fn test_cache() {
-        let mut conn = test_get_conn().await;
+        let conn = test_get_conn().await;
         let file = Uuid::new_v4().to_string();
         let user = Uuid::new_v4().to_string();
         let obj_1 = Uuid::new_v4().to_string();
 
-        begin_undo_event(&mut conn, &file, &user).await.unwrap();
+        begin_undo_event(&conn, &file, &user).await.unwrap();
 
         let offset = 1;
         let msg = ChangeMsg {
@@ -343,11 +429,11 @@ mod tests {
             })),
             change_source: Some(change_msg::ChangeSource::UserAction(EmptyMsg {})),
         };
-        update_undo_cache_inner(&mut conn, &file, offset, msg)
+        update_undo_cache_inner(&conn, &file, offset, msg)
             .await
             .unwrap();
 
-        let (event, list) = undo(&mut conn, &file, &user).await.unwrap();
+        let (event, list) = undo(&conn, &file, &user).await.unwrap();
         assert_eq!(list.len(), 1);
         assert_eq!(list[0].obj_id, obj_1);
         assert_eq!(list[0].offset, offset);
@@ -361,14 +447,14 @@ mod tests {
             })),
             change_source: Some(change_msg::ChangeSource::Undo(event)),
         };
-        update_undo_cache_inner(&mut conn, &file, offset, undo_msg)
+        update_undo_cache_inner(&conn, &file, offset, undo_msg)
             .await
             .unwrap();
 
         //Undo again, there shouldn't be an undo event anymore so this should throw an error
-        assert!(undo(&mut conn, &file, &user).await.is_err());
+        assert!(undo(&conn, &file, &user).await.is_err());
 
-        let (event, list) = redo(&mut conn, &file, &user).await.unwrap();
+        let (event, list) = redo(&conn, &file, &user).await.unwrap();
         assert_eq!(list.len(), 1);
         assert_eq!(list[0].obj_id, obj_1);
         assert_eq!(list[0].offset, offset);
@@ -384,15 +470,15 @@ mod tests {
             })),
             change_source: Some(change_msg::ChangeSource::Redo(event)),
         };
-        update_undo_cache_inner(&mut conn, &file, offset, redo_msg)
+        update_undo_cache_inner(&conn, &file, offset, redo_msg)
             .await
             .unwrap();
 
         //Redo again, there shouldn't be a redo event anymore so this should throw an error
-        assert!(redo(&mut conn, &file, &user).await.is_err());
+        assert!(redo(&conn, &file, &user).await.is_err());
 
         //Now undo/redo again
-        let (event, list) = undo(&mut conn, &file, &user).await.unwrap();
+        let (event, list) = undo(&conn, &file, &user).await.unwrap();
         assert_eq!(list.len(), 1);
         assert_eq!(list[0].obj_id, obj_1);
         assert_eq!(list[0].offset, offset);
@@ -405,10 +491,10 @@ mod tests {
             })),
             change_source: Some(change_msg::ChangeSource::Undo(event)),
         };
-        update_undo_cache_inner(&mut conn, &file, offset, undo_msg)
+        update_undo_cache_inner(&conn, &file, offset, undo_msg)
             .await
             .unwrap();
-        let (event, list) = redo(&mut conn, &file, &user).await.unwrap();
+        let (event, list) = redo(&conn, &file, &user).await.unwrap();
         assert_eq!(list.len(), 1);
         assert_eq!(list[0].obj_id, obj_1);
         assert_eq!(list[0].offset, offset);
@@ -423,8 +509,77 @@ mod tests {
             })),
             change_source: Some(change_msg::ChangeSource::Redo(event)),
         };
-        update_undo_cache_inner(&mut conn, &file, offset, redo_msg)
+        update_undo_cache_inner(&conn, &file, offset, redo_msg)
             .await
             .unwrap();
     }
+
+    #[tokio_macros::test]
+    async fn test_change_log() {
+        let conn = test_get_conn().await;
+        let file = Uuid::new_v4().to_string();
+        let user = Uuid::new_v4().to_string();
+        let other_user = Uuid::new_v4().to_string();
+        let obj_1 = Uuid::new_v4().to_string();
+        let obj_2 = Uuid::new_v4().to_string();
+        begin_undo_event(&conn, &file, &user).await.unwrap();
+        begin_undo_event(&conn, &file, &other_user)
+            .await
+            .unwrap();
+        let changes = vec![
+            (
+                &user,
+                change_msg::ChangeType::Add(ObjectMsg {
+                    id: obj_1.clone(),
+                    dependencies: None,
+                    obj_data: Vec::new(),
+                }),
+            ),
+            (
+                &other_user,
+                change_msg::ChangeType::Add(ObjectMsg {
+                    id: obj_2.clone(),
+                    dependencies: None,
+                    obj_data: Vec::new(),
+                }),
+            ),
+            (
+                &user,
+                change_msg::ChangeType::Delete(DeleteMsg { id: obj_1.clone() }),
+            ),
+        ];
+        for (offset, (change_user, change_type)) in changes.into_iter().enumerate() {
+            let msg = ChangeMsg {
+                user: change_user.clone(),
+                change_type: Some(change_type),
+                change_source: Some(change_msg::ChangeSource::UserAction(EmptyMsg {})),
+            };
+            update_undo_cache_inner(&conn, &file, offset as i64 + 1, msg)
+                .await
+                .unwrap();
+        }
+
+        let entries = get_changes_in_range(&conn, &file, 1, 2).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].obj_id, obj_1);
+        assert_eq!(entries[0].change_type, UndoChangeType::Add);
+        assert_eq!(entries[1].obj_id, obj_2);
+        assert!(get_changes_in_range(&conn, &file, 4, 5).await.is_err());
+
+        let later = get_changed_after(&conn, &file, 2).await.unwrap();
+        assert_eq!(later.len(), 1);
+        assert!(later.contains(&obj_1));
+
+        let event = get_current_event_in_stack(
+            &conn,
+            &file,
+            &other_user,
+            &undo_stack(&file, &other_user),
+        )
+        .await
+        .unwrap();
+        let entries = get_event(&conn, &file, &event).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].obj_id, obj_2);
+    }
 }
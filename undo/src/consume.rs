@@ -0,0 +1,73 @@
+use cache_store::Store;
+use commit_log::{CommitLog, LogError, Record, StartAt};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::*;
+use tracing_futures::Instrument;
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("Commit log error: {0}")]
+    LogError(#[from] LogError),
+    #[error("Cache error: {0}")]
+    CacheError(#[from] crate::UndoError),
+}
+
+async fn handle_message(
+    redis_conn: &dyn Store,
+    record: &Record,
+) -> Result<(), UpdateError> {
+    crate::cache::update_undo_cache(redis_conn, &record.key, record.offset, &record.payload)
+        .instrument(info_span!("update_undo_cache"))
+        .await?;
+    Ok(())
+}
+
+async fn handle_stream(
+    redis_url: &str,
+    log: &dyn CommitLog,
+    group_id: &str,
+    topic: &str,
+) -> Result<(), UpdateError> {
+    let mut consumer = log.consume(topic, group_id, StartAt::Committed).await?;
+
+    while let Some(record) = consumer.next().await {
+        let fut = async {
+            match crate::get_redis_conn(redis_url).await {
+                Ok(redis_conn) => match record {
+                    Ok(record) => {
+                        if let Err(e) = handle_message(&redis_conn, &record)
+                            .instrument(info_span!("handle_message"))
+                            .await
+                        {
+                            error!("{}", e);
+                        }
+                        if let Err(e) = consumer.commit(&record).await {
+                            error!("{}", e);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                },
+                Err(e) => {
+                    error!("{}", e);
+                }
+            }
+        };
+        let instrumented = fut.instrument(info_span!("log message"));
+        instrumented.await;
+    }
+    Ok(())
+}
+
+pub async fn update_cache(
+    redis_url: String,
+    log: Arc<dyn CommitLog>,
+    group_id: String,
+    topic: String,
+) {
+    if let Err(e) = handle_stream(&redis_url, log.as_ref(), &group_id, &topic).await {
+        error!("{}", e);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use std::collections::HashSet;
 use trace_lib::TracedRequest;
 
 async fn get_all_previous_objects(
@@ -64,7 +65,9 @@ fn invert_changes_inner(
                     Some(change_msg::ChangeType::Delete(..)) => {
                         error!("Invalid modify coming after a delete");
                     }
-                    None => {
+                    Some(change_msg::ChangeType::Branch(..))
+                    | Some(change_msg::ChangeType::Tag(..))
+                    | None => {
                         error!("No data to undo back to");
                     }
                 },
@@ -86,7 +89,9 @@ fn invert_changes_inner(
                     Some(change_msg::ChangeType::Delete(..)) => {
                         error!("Object got deleted twice");
                     }
-                    None => {
+                    Some(change_msg::ChangeType::Branch(..))
+                    | Some(change_msg::ChangeType::Tag(..))
+                    | None => {
                         error!("No data to undo back to");
                     }
                 },
@@ -108,7 +113,9 @@ fn invert_changes_inner(
                     Some(change_msg::ChangeType::Delete(..)) => {
                         error!("Object not set after a delete");
                     }
-                    None => {
+                    Some(change_msg::ChangeType::Branch(..))
+                    | Some(change_msg::ChangeType::Tag(..))
+                    | None => {
                         error!("No data to undo back to");
                     }
                 },
@@ -139,6 +146,93 @@ pub async fn invert_changes(
     Ok(invert_changes_inner(user, source, entries, previous))
 }
 
+async fn get_current_objects(
+    obj_client: &mut ObjClient,
+    file: &str,
+    entries: &Vec<UndoEntry>,
+) -> Result<Vec<OptionChangeMsg>, Status> {
+    let mut obj_ids = Vec::new();
+    for entry in entries {
+        obj_ids.push(ObjectAtOffset {
+            offset: i64::MAX,
+            obj_id: entry.obj_id.clone(),
+        });
+    }
+    let input = GetObjectsInput {
+        file: String::from(file),
+        obj_ids,
+    };
+    let objs_msg = obj_client
+        .get_objects(TracedRequest::new(input))
+        .await?
+        .into_inner();
+    Ok(objs_msg.objects)
+}
+
+//Reverting puts each object back how it was before the first change to it in the set
+fn first_change_per_object(mut entries: Vec<UndoEntry>) -> Vec<UndoEntry> {
+    entries.sort_by_key(|entry| entry.offset);
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.obj_id.clone()))
+        .collect()
+}
+
+fn object_after(change: Option<ChangeMsg>) -> Option<ObjectMsg> {
+    match change.and_then(|change| change.change_type) {
+        Some(change_msg::ChangeType::Add(object))
+        | Some(change_msg::ChangeType::Modify(object)) => Some(object),
+        Some(change_msg::ChangeType::Delete(..))
+        | Some(change_msg::ChangeType::Branch(..))
+        | Some(change_msg::ChangeType::Tag(..))
+        | None => None,
+    }
+}
+
+//Unlike undo, this works from the objects' current state, since other changes may have been made since
+fn revert_changes_inner(
+    user: &str,
+    source: change_msg::ChangeSource,
+    entries: Vec<UndoEntry>,
+    previous: Vec<OptionChangeMsg>,
+    current: Vec<OptionChangeMsg>,
+) -> Vec<ChangeMsg> {
+    let mut reverted = Vec::new();
+    for ((entry, prev), cur) in entries.into_iter().zip(previous).zip(current) {
+        let target = object_after(prev.change);
+        let change_type = match (object_after(cur.change), target) {
+            (cur, target) if cur == target => continue,
+            (None, Some(object)) => change_msg::ChangeType::Add(object),
+            (Some(..), Some(object)) => change_msg::ChangeType::Modify(object),
+            (Some(..), None) => change_msg::ChangeType::Delete(DeleteMsg { id: entry.obj_id }),
+            (None, None) => continue,
+        };
+        reverted.push(ChangeMsg {
+            user: String::from(user),
+            change_type: Some(change_type),
+            change_source: Some(source.clone()),
+        });
+    }
+    reverted
+}
+
+pub async fn revert_changes(
+    obj_client: &mut ObjClient,
+    file: &str,
+    user: &str,
+    source: change_msg::ChangeSource,
+    entries: Vec<UndoEntry>,
+) -> Result<Vec<ChangeMsg>, Status> {
+    let entries = first_change_per_object(entries);
+    let previous = get_all_previous_objects(obj_client, file, &entries).await?;
+    let current = get_current_objects(obj_client, file, &entries).await?;
+    info!("Reverting from {:?} to {:?}", current, previous);
+    Ok(revert_changes_inner(
+        user, source, entries, previous, current,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,4 +318,74 @@ mod tests {
         assert_eq!(inverted[1], answers[1]);
         assert_eq!(inverted[2], answers[2]);
     }
+
+    #[test]
+    fn test_revert_changes_inner() {
+        let user = String::from("test user");
+        let source = change_msg::ChangeSource::Revert(String::from("1..=4"));
+        let object = |id: &str, data: &str| ObjectMsg {
+            id: String::from(id),
+            dependencies: None,
+            obj_data: String::from(data).into_bytes(),
+        };
+        let change = |change_type| OptionChangeMsg {
+            change: Some(ChangeMsg {
+                user: String::from("prev user"),
+                change_type: Some(change_type),
+                change_source: Some(change_msg::ChangeSource::UserAction(EmptyMsg {})),
+            }),
+        };
+        let entries = first_change_per_object(vec![
+            UndoEntry {
+                obj_id: String::from("obj_2"),
+                offset: 3,
+                change_type: UndoChangeType::Modify,
+            },
+            UndoEntry {
+                obj_id: String::from("obj_1"),
+                offset: 1,
+                change_type: UndoChangeType::Add,
+            },
+            UndoEntry {
+                obj_id: String::from("obj_2"),
+                offset: 2,
+                change_type: UndoChangeType::Modify,
+            },
+            UndoEntry {
+                obj_id: String::from("obj_3"),
+                offset: 4,
+                change_type: UndoChangeType::Modify,
+            },
+        ]);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].obj_id, "obj_2");
+        assert_eq!(entries[1].offset, 2);
+        let previous = vec![
+            OptionChangeMsg { change: None },
+            change(change_msg::ChangeType::Add(object("obj_2", "original"))),
+            change(change_msg::ChangeType::Add(object("obj_3", "original"))),
+        ];
+        let current = vec![
+            change(change_msg::ChangeType::Add(object("obj_1", "added"))),
+            change(change_msg::ChangeType::Delete(DeleteMsg {
+                id: String::from("obj_2"),
+            })),
+            //Already back how it was, so nothing to do
+            change(change_msg::ChangeType::Modify(object("obj_3", "original"))),
+        ];
+        let reverted = revert_changes_inner(&user, source.clone(), entries, previous, current);
+        assert_eq!(reverted.len(), 2);
+        assert_eq!(
+            reverted[0].change_type,
+            Some(change_msg::ChangeType::Delete(DeleteMsg {
+                id: String::from("obj_1")
+            }))
+        );
+        assert_eq!(
+            reverted[1].change_type,
+            Some(change_msg::ChangeType::Add(object("obj_2", "original")))
+        );
+        assert_eq!(reverted[1].change_source, Some(source));
+        assert_eq!(reverted[1].user, user);
+    }
 }
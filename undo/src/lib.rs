@@ -0,0 +1,248 @@
+use cache_store::Store;
+use commit_log::CommitLog;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use thiserror::Error;
+use tonic::transport::{Channel, Server};
+use tonic::{Request, Response, Status};
+use trace_lib::propagate_trace;
+use tracing::*;
+use tracing_futures::Instrument;
+
+mod cache;
+mod consume;
+mod invert;
+use cache::*;
+use consume::*;
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+
+mod object_state {
+    tonic::include_proto!("object_state");
+}
+use object_state::*;
+
+mod undo {
+    tonic::include_proto!("undo");
+}
+use undo::*;
+
+mod objects {
+    tonic::include_proto!("objects");
+}
+use objects::*;
+type ObjClient = objects_client::ObjectsClient<Channel>;
+
+#[derive(Debug, Error)]
+pub enum UndoError {
+    #[error("No events to undo for user {0} in file {1}")]
+    NoUndoEvent(String, String),
+    #[error("Obj {0} not found in undo event {1} in file {2}")]
+    NoObjInUndoEvent(String, String, String),
+    #[error("No changes to revert in {0} in file {1}")]
+    NoChangesToRevert(String, String),
+    #[error("Prost encode error: {0}")]
+    ProstEncodeError(#[from] prost::EncodeError),
+    #[error("Prost decode error: {0}")]
+    ProstDecodeError(#[from] prost::DecodeError),
+    #[error("Bincode error: {0}")]
+    BincodeError(#[from] bincode::Error),
+    #[error("Redis error: {0:?}")]
+    DatabaseError(#[from] cache_store::StoreError),
+}
+
+impl Into<tonic::Status> for UndoError {
+    fn into(self) -> tonic::Status {
+        let msg = format!("{}", self);
+        let code = match self {
+            UndoError::DatabaseError(..)
+            | UndoError::ProstEncodeError(..)
+            | UndoError::BincodeError(..)
+            | UndoError::ProstDecodeError(..) => tonic::Code::Internal,
+            UndoError::NoUndoEvent(..)
+            | UndoError::NoObjInUndoEvent(..)
+            | UndoError::NoChangesToRevert(..) => tonic::Code::NotFound,
+        };
+        tonic::Status::new(code, msg)
+    }
+}
+
+pub fn to_status<T: Into<UndoError>>(err: T) -> tonic::Status {
+    let obj_error: UndoError = err.into();
+    obj_error.into()
+}
+
+pub fn unavailable<T: std::fmt::Debug>(err: T) -> Status {
+    Status::unavailable(format!("Unable to connect to service: {:?}", err))
+}
+
+#[instrument]
+async fn get_redis_conn(url: &str) -> Result<Arc<dyn Store>, tonic::Status> {
+    cache_store::connect(url)
+        .await
+        .map_err(|e| tonic::Status::unavailable(format!("{:?}", e)))
+}
+
+#[derive(Debug)]
+struct UndoService {
+    redis_url: String,
+    obj_url: String,
+}
+
+#[tonic::async_trait]
+impl undo_server::Undo for UndoService {
+    #[instrument]
+    async fn begin_undo_event(
+        &self,
+        request: Request<BeginUndoEventInput>,
+    ) -> Result<Response<BeginUndoEventOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        cache::begin_undo_event(&redis_conn, &msg.file, &msg.user)
+            .instrument(info_span!("cache::begin_undo_event"))
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(BeginUndoEventOutput {}))
+    }
+
+    #[instrument]
+    async fn undo_latest(
+        &self,
+        request: Request<UndoLatestInput>,
+    ) -> Result<Response<UndoLatestOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let mut obj_client = objects_client::ObjectsClient::connect(self.obj_url.clone())
+            .instrument(info_span!("objects_client::connect"))
+            .await
+            .map_err(unavailable)?;
+        let (event, latest) = cache::undo(&redis_conn, &msg.file, &msg.user)
+            .instrument(info_span!("cache::undo"))
+            .await
+            .map_err(to_status)?;
+        let changes = invert::invert_changes(
+            &mut obj_client,
+            &msg.file,
+            &msg.user,
+            change_msg::ChangeSource::Undo(event),
+            latest,
+        )
+        .instrument(info_span!("invert_changes"))
+        .await?;
+        Ok(Response::new(UndoLatestOutput { changes }))
+    }
+
+    #[instrument]
+    async fn redo_latest(
+        &self,
+        request: Request<RedoLatestInput>,
+    ) -> Result<Response<RedoLatestOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let mut obj_client = objects_client::ObjectsClient::connect(self.obj_url.clone())
+            .instrument(info_span!("objects_client::connect"))
+            .await
+            .map_err(unavailable)?;
+        let (event, latest) = cache::redo(&redis_conn, &msg.file, &msg.user)
+            .instrument(info_span!("redo"))
+            .await
+            .map_err(to_status)?;
+        let changes = invert::invert_changes(
+            &mut obj_client,
+            &msg.file,
+            &msg.user,
+            change_msg::ChangeSource::Redo(event),
+            latest,
+        )
+        .instrument(info_span!("invert_changes"))
+        .await?;
+        Ok(Response::new(RedoLatestOutput { changes }))
+    }
+
+    #[instrument]
+    async fn revert(
+        &self,
+        request: Request<RevertInput>,
+    ) -> Result<Response<RevertOutput>, Status> {
+        propagate_trace(request.metadata());
+        let msg = request.get_ref();
+        let redis_conn = get_redis_conn(&self.redis_url).await?;
+        let (source, entries) = match &msg.target {
+            Some(revert_input::Target::Offsets(range)) => {
+                if range.start > range.end {
+                    return Err(Status::invalid_argument(format!(
+                        "Offset range {}..={} is empty",
+                        range.start, range.end
+                    )));
+                }
+                let entries =
+                    cache::get_changes_in_range(&redis_conn, &msg.file, range.start, range.end)
+                        .instrument(info_span!("cache::get_changes_in_range"))
+                        .await
+                        .map_err(to_status)?;
+                (format!("{}..={}", range.start, range.end), entries)
+            }
+            Some(revert_input::Target::Event(event)) => {
+                let entries = cache::get_event(&redis_conn, &msg.file, event)
+                    .instrument(info_span!("cache::get_event"))
+                    .await
+                    .map_err(to_status)?;
+                (event.clone(), entries)
+            }
+            None => return Err(Status::invalid_argument("Nothing given to revert")),
+        };
+        let end = entries.iter().map(|entry| entry.offset).max().unwrap_or(0);
+        let later = cache::get_changed_after(&redis_conn, &msg.file, end)
+            .instrument(info_span!("cache::get_changed_after"))
+            .await
+            .map_err(to_status)?;
+        let mut conflicts: Vec<String> = entries
+            .iter()
+            .filter(|entry| later.contains(&entry.obj_id))
+            .map(|entry| entry.obj_id.clone())
+            .collect();
+        conflicts.sort();
+        conflicts.dedup();
+        if !conflicts.is_empty() && !msg.force {
+            return Ok(Response::new(RevertOutput {
+                changes: Vec::new(),
+                conflicts,
+            }));
+        }
+        let mut obj_client = objects_client::ObjectsClient::connect(self.obj_url.clone())
+            .instrument(info_span!("objects_client::connect"))
+            .await
+            .map_err(unavailable)?;
+        let changes = invert::revert_changes(
+            &mut obj_client,
+            &msg.file,
+            &msg.user,
+            change_msg::ChangeSource::Revert(source),
+            entries,
+        )
+        .instrument(info_span!("revert_changes"))
+        .await?;
+        Ok(Response::new(RevertOutput { changes, conflicts }))
+    }
+}
+
+///Serves undo and redo while correlating the commit log into undo events
+pub async fn run(
+    run_url: SocketAddr,
+    redis_url: String,
+    obj_url: String,
+    log: Arc<dyn CommitLog>,
+    group: String,
+    topic: String,
+) -> Result<(), tonic::transport::Error> {
+    tokio::spawn(update_cache(redis_url.clone(), log, group, topic));
+    let svc = undo_server::UndoServer::new(UndoService { redis_url, obj_url });
+
+    info!("Running on {:?}", run_url);
+    Server::builder().add_service(svc).serve(run_url).await
+}
@@ -0,0 +1,10 @@
+fn main() {
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile(
+            &["../proto/geom.proto", "../proto/representation.proto"],
+            &["../proto"],
+        )
+        .unwrap_or_else(|e| panic!("Failed to compile protos {:?}", e));
+}
@@ -0,0 +1,272 @@
+use crate::geom::Point3Msg;
+use crate::representation::*;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use prost::Message;
+use std::io::Write;
+
+///Things a client can say it understands when it subscribes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    CompactMeshes,
+    ///Implies CompactMeshes
+    CompressedMeshes,
+}
+
+impl Capability {
+    ///None for names this doesn't know, which newer clients might send
+    pub fn from_name(name: &str) -> Option<Capability> {
+        match name {
+            "CompactMeshes" => Some(Capability::CompactMeshes),
+            "CompressedMeshes" => Some(Capability::CompressedMeshes),
+            _ => None,
+        }
+    }
+}
+
+///How meshes are sent to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeshEncoding {
+    Full,
+    Compact,
+    Compressed,
+}
+
+impl MeshEncoding {
+    ///Capabilities this doesn't know are ignored
+    pub fn from_capabilities(names: &[String]) -> MeshEncoding {
+        let capabilities: Vec<Capability> = names
+            .iter()
+            .filter_map(|name| Capability::from_name(name))
+            .collect();
+        if capabilities.contains(&Capability::CompressedMeshes) {
+            MeshEncoding::Compressed
+        } else if capabilities.contains(&Capability::CompactMeshes) {
+            MeshEncoding::Compact
+        } else {
+            MeshEncoding::Full
+        }
+    }
+}
+
+const QUANTIZED_MAX: f64 = 65535.0;
+const NORMAL_SCALE: f64 = 127.0;
+
+fn bounds(positions: &[f64]) -> ([f64; 3], [f64; 3]) {
+    let mut min = [0.0; 3];
+    let mut max = [0.0; 3];
+    for (i, pt) in positions.chunks_exact(3).enumerate() {
+        for axis in 0..3 {
+            if i == 0 || pt[axis] < min[axis] {
+                min[axis] = pt[axis];
+            }
+            if i == 0 || pt[axis] > max[axis] {
+                max[axis] = pt[axis];
+            }
+        }
+    }
+    (min, max)
+}
+
+fn quantize(value: f64, min: f64, max: f64) -> u16 {
+    if max > min {
+        ((value - min) / (max - min) * QUANTIZED_MAX).round() as u16
+    } else {
+        0
+    }
+}
+
+fn compress(bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if bytes.is_empty() {
+        return Ok(bytes);
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bytes)?;
+    encoder.finish()
+}
+
+///Packs a mesh down, quantizing positions across its bounding box
+pub fn compact_mesh(mesh: MeshDataMsg, compressed: bool) -> std::io::Result<CompactMeshMsg> {
+    let vertex_count = mesh.positions.len() / 3;
+    let (min, max) = bounds(&mesh.positions);
+    let mut positions = Vec::with_capacity(vertex_count * 6);
+    for pt in mesh.positions.chunks_exact(3) {
+        for axis in 0..3 {
+            positions.extend_from_slice(&quantize(pt[axis], min[axis], max[axis]).to_le_bytes());
+        }
+    }
+    let index_width = if vertex_count <= u16::MAX as usize + 1 {
+        IndexWidth::U16
+    } else {
+        IndexWidth::U32
+    };
+    let mut indices = Vec::new();
+    for index in &mesh.indices {
+        match index_width {
+            IndexWidth::U16 => indices.extend_from_slice(&(*index as u16).to_le_bytes()),
+            IndexWidth::U32 => indices.extend_from_slice(&(*index as u32).to_le_bytes()),
+        }
+    }
+    //Normals and UVs that don't line up with the positions are left out rather than sent wrong
+    let mut normals = Vec::new();
+    if mesh.normals.len() == vertex_count * 3 {
+        for coord in &mesh.normals {
+            normals.push((coord.clamp(-1.0, 1.0) * NORMAL_SCALE).round() as i8 as u8);
+        }
+    }
+    let mut uvs = Vec::new();
+    if mesh.uvs.len() == vertex_count * 2 {
+        for coord in &mesh.uvs {
+            uvs.extend_from_slice(&(*coord as f32).to_le_bytes());
+        }
+    }
+    let compression = if compressed {
+        positions = compress(positions)?;
+        indices = compress(indices)?;
+        normals = compress(normals)?;
+        uvs = compress(uvs)?;
+        MeshCompression::Zlib
+    } else {
+        MeshCompression::None
+    };
+    Ok(CompactMeshMsg {
+        min: Some(Point3Msg {
+            x: min[0],
+            y: min[1],
+            z: min[2],
+        }),
+        max: Some(Point3Msg {
+            x: max[0],
+            y: max[1],
+            z: max[2],
+        }),
+        positions,
+        index_width: index_width as i32,
+        indices,
+        meta_json: mesh.meta_json,
+        normals,
+        uvs,
+        material_indices: mesh.material_indices,
+        compression: compression as i32,
+    })
+}
+
+///Re-encodes an UpdateChangeMsg for a client.  Anything that isn't a mesh goes out unchanged.
+pub fn encode_for(payload: &[u8], encoding: MeshEncoding) -> anyhow::Result<Vec<u8>> {
+    if encoding == MeshEncoding::Full {
+        return Ok(payload.to_vec());
+    }
+    let mut change = UpdateChangeMsg::decode(payload)?;
+    let mesh = match change
+        .update
+        .as_mut()
+        .and_then(|update| update.output.take())
+    {
+        Some(update_output_msg::Output::Mesh(mesh)) => mesh,
+        _ => return Ok(payload.to_vec()),
+    };
+    let compact = compact_mesh(mesh, encoding == MeshEncoding::Compressed)?;
+    if let Some(update) = change.update.as_mut() {
+        update.output = Some(update_output_msg::Output::CompactMesh(compact));
+    }
+    let mut bytes = Vec::with_capacity(change.encoded_len());
+    change.encode(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn read_u16s(bytes: &[u8]) -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    fn triangle() -> MeshDataMsg {
+        MeshDataMsg {
+            positions: vec![1.0, 2.0, 3.0, 5.0, 2.0, 3.0, 1.0, 4.0, 3.0],
+            indices: vec![0, 1, 2],
+            meta_json: String::from("{}"),
+            normals: vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+            uvs: Vec::new(),
+            material_indices: vec![1],
+        }
+    }
+
+    #[test]
+    fn test_compact_mesh() {
+        let compact = compact_mesh(triangle(), false).unwrap();
+        assert_eq!(compact.min.unwrap().x, 1.0);
+        assert_eq!(compact.max.unwrap().y, 4.0);
+        assert_eq!(
+            read_u16s(&compact.positions),
+            vec![0, 0, 0, 65535, 0, 0, 0, 65535, 0]
+        );
+        assert_eq!(compact.index_width, IndexWidth::U16 as i32);
+        assert_eq!(read_u16s(&compact.indices), vec![0, 1, 2]);
+        assert_eq!(compact.normals[2] as i8, 127);
+        assert!(compact.uvs.is_empty());
+        assert_eq!(compact.material_indices, vec![1]);
+
+        let compressed = compact_mesh(triangle(), true).unwrap();
+        assert_eq!(compressed.compression, MeshCompression::Zlib as i32);
+        let mut positions = Vec::new();
+        ZlibDecoder::new(&compressed.positions[..])
+            .read_to_end(&mut positions)
+            .unwrap();
+        assert_eq!(positions, compact.positions);
+    }
+
+    #[test]
+    fn test_encode_for() {
+        let change = UpdateChangeMsg {
+            file: String::from("file"),
+            obj_id: String::from("obj"),
+            update: Some(UpdateOutputMsg {
+                output: Some(update_output_msg::Output::Mesh(triangle())),
+                views: None,
+                bounds: None,
+            }),
+            ..Default::default()
+        };
+        let mut payload = Vec::new();
+        change.encode(&mut payload).unwrap();
+        assert_eq!(encode_for(&payload, MeshEncoding::Full).unwrap(), payload);
+
+        let compact = encode_for(&payload, MeshEncoding::Compact).unwrap();
+        let decoded = UpdateChangeMsg::decode(&compact[..]).unwrap();
+        assert_eq!(decoded.obj_id, "obj");
+        match decoded.update.unwrap().output {
+            Some(update_output_msg::Output::CompactMesh(mesh)) => {
+                assert_eq!(mesh.meta_json, "{}")
+            }
+            other => panic!("Expected a compact mesh, got {:?}", other),
+        }
+        assert!(compact.len() < payload.len());
+    }
+
+    #[test]
+    fn test_from_capabilities() {
+        let names = |names: &[&str]| -> Vec<String> {
+            names.iter().map(|name| String::from(*name)).collect()
+        };
+        assert_eq!(MeshEncoding::from_capabilities(&[]), MeshEncoding::Full);
+        assert_eq!(
+            MeshEncoding::from_capabilities(&names(&["SomethingNewer", "CompactMeshes"])),
+            MeshEncoding::Compact
+        );
+        assert_eq!(
+            MeshEncoding::from_capabilities(&names(&["CompactMeshes", "CompressedMeshes"])),
+            MeshEncoding::Compressed
+        );
+        assert_eq!(
+            MeshEncoding::from_capabilities(&names(&["SomethingNewer"])),
+            MeshEncoding::Full
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+use commit_log::{CommitLog, LogError, Record, StartAt};
+use prost::Message as _;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+async fn handle_message(record: &Record) -> Result<(), LogError> {
+    let file = &record.key;
+    let bytes = &record.payload;
+    info!("Got msg from file: {:?}", file);
+    if let Some(mut entry) = FILE_TO_CHANNEL_MAP.get_mut(file) {
+        let mut to_delete = Vec::new();
+        let mut index = 0usize;
+        let change = match UpdateChangeMsg::decode(bytes.as_ref()) {
+            Ok(change) => Some(change),
+            Err(e) => {
+                error!("Couldn't decode update, sending it to everyone: {}", e);
+                None
+            }
+        };
+        //Each encoding is only worked out once, however many clients want it
+        let mut encoded: HashMap<MeshEncoding, Vec<u8>> = HashMap::new();
+        for sender in entry.value_mut() {
+            if let Some(change) = &change {
+                if !sender.wants(change) {
+                    index += 1;
+                    continue;
+                }
+            }
+            let msg = encoded
+                .entry(sender.encoding)
+                .or_insert_with(|| match compact::encode_for(bytes, sender.encoding) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        error!("Couldn't encode update as {:?}: {}", sender.encoding, e);
+                        bytes.to_vec()
+                    }
+                })
+                .clone();
+            if let Err(e) = sender
+                .sender
+                .send(UpdateMessage {
+                    file: String::from(file),
+                    msg,
+                })
+                .await
+            {
+                error!("Channel error: {:?}", e);
+                to_delete.push(index);
+            }
+            index += 1;
+        }
+        for index in to_delete.into_iter().rev() {
+            info!("Deleting channel");
+            entry.value_mut().remove(index);
+        }
+    }
+    Ok(())
+}
+
+async fn handle_stream(log: &dyn CommitLog, group_id: &str, topic: &str) -> Result<(), LogError> {
+    let mut consumer = log.consume(topic, group_id, StartAt::Committed).await?;
+
+    while let Some(record) = consumer.next().await {
+        match record {
+            Ok(record) => {
+                if let Err(e) = handle_message(&record).await {
+                    error!("{}", e);
+                }
+                if let Err(e) = consumer.commit(&record).await {
+                    error!("{}", e);
+                }
+            }
+            Err(e) => {
+                error!("{}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn consume(log: Arc<dyn CommitLog>, group_id: String, topic: String) {
+    if let Err(e) = handle_stream(log.as_ref(), &group_id, &topic).await {
+        error!("{}", e);
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use commit_log::CommitLog;
+use dashmap::DashMap;
+use futures_util::future::{select, Either};
+use futures_util::{SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use log::*;
+use obj_traits::Lod;
+use representation::UpdateChangeMsg;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, mpsc::Sender};
+use tungstenite::Message;
+
+mod geom {
+    tonic::include_proto!("geom");
+}
+
+mod representation {
+    tonic::include_proto!("representation");
+}
+
+mod compact;
+mod consume;
+pub use compact::{Capability, MeshEncoding};
+
+#[derive(Debug, Clone)]
+pub struct UpdateMessage {
+    file: String,
+    msg: Vec<u8>,
+}
+
+pub struct ChannelSend {
+    sender: Sender<UpdateMessage>,
+    user: String,
+    //The websocket that subscribed, since one user can have the file open in several
+    connection: u64,
+    encoding: MeshEncoding,
+    lod: Lod,
+    object_lods: HashMap<String, Lod>,
+}
+
+impl ChannelSend {
+    ///Every level of detail of a change comes through, but each client only wants the one it asked for
+    fn wants(&self, change: &UpdateChangeMsg) -> bool {
+        if change.obj_id.is_empty() {
+            return true;
+        }
+        let lod = *self.object_lods.get(&change.obj_id).unwrap_or(&self.lod);
+        change.lod == lod as i32
+    }
+}
+
+lazy_static! {
+    pub static ref FILE_TO_CHANNEL_MAP: DashMap<String, Vec<ChannelSend>> = DashMap::default();
+}
+
+#[derive(Deserialize)]
+enum Commands {
+    Subscribe {
+        filename: String,
+        user: String,
+        ///Names of Capability variants.  Ones this doesn't know are ignored.
+        #[serde(default)]
+        capabilities: Vec<String>,
+        ///Full if not given
+        lod: Option<Lod>,
+    },
+    Unsubscribe {
+        filename: String,
+        user: String,
+    },
+    ///Changes the level of detail of some objects, as they get bigger or smaller on screen.
+    ///Only applies to the connection that sends it.
+    SetLods {
+        filename: String,
+        lods: HashMap<String, Lod>,
+    },
+}
+
+async fn accept_connection(stream: tokio::net::TcpStream) {
+    if let Err(e) = handle_connection(stream).await {
+        error!("{:?}", e);
+    }
+}
+
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+async fn handle_connection(stream: tokio::net::TcpStream) -> Result<()> {
+    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+    info!("New connection {}", connection);
+    let (mut ws_send, mut ws_rcv) = ws_stream.split();
+    let (channel_send, mut channel_rcv) = mpsc::channel(100);
+    let mut channel_fut = channel_rcv.next();
+    let mut ws_fut = ws_rcv.next();
+    loop {
+        trace!("Going to select");
+        match select(ws_fut, channel_fut).await {
+            Either::Left((ws_msg, channel_fut_continue)) => {
+                trace!("Selected websocket");
+                match ws_msg {
+                    Some(msg) => {
+                        let msg = msg?;
+                        match msg {
+                            Message::Ping(msg) => {
+                                trace!("Ping");
+                                ws_send.send(Message::Pong(msg)).await?;
+                            }
+                            Message::Pong(msg) => {
+                                trace!("Pong");
+                                ws_send.send(Message::Ping(msg)).await?;
+                            }
+                            Message::Close(_) => {
+                                debug!("Close message received, breaking");
+                                break;
+                            }
+                            Message::Text(sub_msg) => match serde_json::from_str(&sub_msg) {
+                                Ok(Commands::Subscribe {
+                                    filename,
+                                    user,
+                                    capabilities,
+                                    lod,
+                                }) => {
+                                    info!("New subscribe cmd for {:?}", filename);
+                                    let send = ChannelSend {
+                                        sender: channel_send.clone(),
+                                        user: user,
+                                        connection,
+                                        encoding: MeshEncoding::from_capabilities(&capabilities),
+                                        lod: lod.unwrap_or(Lod::Full),
+                                        object_lods: HashMap::new(),
+                                    };
+                                    match FILE_TO_CHANNEL_MAP.get_mut(&filename) {
+                                        Some(mut entry) => {
+                                            entry.value_mut().push(send);
+                                        }
+                                        None => {
+                                            FILE_TO_CHANNEL_MAP.insert(filename, vec![send]);
+                                        }
+                                    }
+                                }
+                                Ok(Commands::Unsubscribe { filename, user }) => {
+                                    info!("New unsubscribe cmd for {:?}", filename);
+                                    if let Some(mut entry) = FILE_TO_CHANNEL_MAP.get_mut(&filename)
+                                    {
+                                        let senders = entry.value_mut();
+                                        let mut index = 0usize;
+                                        for send in senders.iter() {
+                                            if send.user == user {
+                                                senders.remove(index);
+                                                break;
+                                            }
+                                            index += 1;
+                                        }
+                                    }
+                                }
+                                Ok(Commands::SetLods { filename, lods }) => {
+                                    debug!("New set lods cmd for {:?}", filename);
+                                    if let Some(mut entry) = FILE_TO_CHANNEL_MAP.get_mut(&filename)
+                                    {
+                                        for send in entry.value_mut().iter_mut() {
+                                            if send.connection == connection {
+                                                send.object_lods.extend(lods.clone());
+                                            }
+                                        }
+                                    }
+                                }
+                                Err(e) => error!("Invalid JSON: {:?}", e),
+                            },
+                            _ => {
+                                error!("Unexpected message {:?} received from client", msg);
+                                ws_send
+                                    .send(Message::Text(format!("Unexpected message {:?}", msg)))
+                                    .await?
+                            }
+                        }
+                    }
+                    None => break,
+                }
+                channel_fut = channel_fut_continue;
+                ws_fut = ws_rcv.next();
+            }
+            Either::Right((channel_msg, ws_fut_continue)) => {
+                trace!("Selected channel");
+                match channel_msg {
+                    Some(ws_msg) => {
+                        debug!("Got message from channel, passing on");
+                        ws_send.send(Message::Binary(ws_msg.msg)).await?;
+                    }
+                    None => {
+                        debug!("Got None, breaking");
+                        break;
+                    }
+                }
+                ws_fut = ws_fut_continue;
+                channel_fut = channel_rcv.next();
+            }
+        }
+    }
+    info!("Closing connection");
+
+    Ok(())
+}
+
+///Accepts websocket clients on run_url and pushes them the representations of files they subscribe to
+pub async fn run(
+    run_url: String,
+    log: Arc<dyn CommitLog>,
+    group: String,
+    topic: String,
+) -> Result<(), std::io::Error> {
+    let mut server = tokio::net::TcpListener::bind(&run_url).await?;
+    info!("Listening for updates");
+    tokio::spawn(consume::consume(log, group, topic));
+    while let Ok((stream, _)) = server.accept().await {
+        tokio::spawn(accept_connection(stream));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enum_iterator::IntoEnumIterator;
+    use representation::LevelOfDetail;
+
+    #[test]
+    fn test_lod_values() {
+        //Lod's values have to line up with the ones sent over the wire
+        for lod in Lod::into_enum_iter() {
+            let level = LevelOfDetail::from_i32(lod as i32).unwrap();
+            assert_eq!(format!("{:?}", level), format!("{:?}", lod));
+            assert_eq!(Lod::from_i32(level as i32), Some(lod));
+        }
+    }
+
+    #[test]
+    fn test_wants() {
+        let (sender, _) = mpsc::channel(1);
+        let mut send = ChannelSend {
+            sender,
+            user: String::from("user"),
+            connection: 0,
+            encoding: MeshEncoding::Full,
+            lod: Lod::BoundingBox,
+            object_lods: HashMap::new(),
+        };
+        send.object_lods.insert(String::from("close"), Lod::Full);
+        let change = |obj_id: &str, lod: LevelOfDetail| UpdateChangeMsg {
+            obj_id: String::from(obj_id),
+            lod: lod as i32,
+            ..Default::default()
+        };
+        assert!(send.wants(&change("far", LevelOfDetail::BoundingBox)));
+        assert!(!send.wants(&change("far", LevelOfDetail::Full)));
+        assert!(send.wants(&change("close", LevelOfDetail::Full)));
+        assert!(!send.wants(&change("close", LevelOfDetail::Simplified)));
+        //Branch messages aren't about any one object
+        assert!(send.wants(&change("", LevelOfDetail::Full)));
+    }
+}